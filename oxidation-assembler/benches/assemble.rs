@@ -0,0 +1,78 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use oxidation_assembler::program;
+use std::fmt::Write;
+
+/// The number of blocks within the large source.
+const BLOCKS: usize = 5_000;
+
+/// The macro invoked by each block of the large source.
+const COUNTDOWN: &str = "\
+.macro countdown reg, start=0d10
+    .local loop
+    mov start, reg
+loop:
+    add -1, reg
+    mov AC, reg
+    jne 0, [loop]
+.endm
+";
+
+/// Returns a large source that uses every instruction, along with labels, a macro
+/// and data that refers to the labels.
+fn large_source() -> String {
+    let mut source = String::from(COUNTDOWN);
+    source.push_str(".sub main\n");
+    for i in 0..BLOCKS {
+        writeln!(
+            source,
+            "\
+block_{i}:
+    nop
+    mov 1234, R1
+    mov R1, R2
+    mov R2, [data_{i}]
+    mov [data_{i}], R3
+    add 1, R3
+    out R3, 1
+    in 1, R4
+    push 7
+    push R4
+    pop R5
+    countdown R6, 3
+    call [block_{i}]
+    call R5
+    jne 0A, [block_{i}]
+    jne R6, [block_{i}]
+    jeq 0A, [block_{i}]
+    jeq R6, [block_{i}]
+    ret
+    iret",
+            i = i
+        )
+        .unwrap();
+    }
+    source.push_str("    hlt\n.data\n");
+    for i in 0..BLOCKS {
+        writeln!(source, "data_{i}: .i32 block_{i}, -1", i = i).unwrap();
+    }
+
+    source
+}
+
+fn assemble(c: &mut Criterion) {
+    let source = large_source();
+    let program = program::assemble(&source, 0).unwrap();
+
+    let mut group = c.benchmark_group("assemble");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.bench_function("large_source", |b| {
+        b.iter(|| {
+            let assembled = program::assemble(black_box(&source), 0).unwrap();
+            assert_eq!(assembled.text.len(), program.text.len());
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, assemble);
+criterion_main!(benches);
@@ -1,25 +1,52 @@
+use oxidation_core::executable::{self, Executable, Section};
 use oxidation_core::instructions::enums::Instruction;
-use oxidation_core::instructions::enums::OpCode;
-use oxidation_core::registers::Registers;
-use binary_rw::{BinaryWriter, OpenType};
+use std::path::Path;
 
-pub fn assemble(instructions: &[Instruction]) {
-    let mut binary_file = BinaryWriter::new("c.bin", OpenType::OpenAndCreate).expect("Failed to create binary reader.");
-    for i in instructions {
-        match i {
-            Instruction::NOP() => write_opcode(&mut binary_file, OpCode::NOP),
-            Instruction::AddLitReg(lit, reg) => write_i32_reg(&mut binary_file, OpCode::AddLitReg, lit,reg),
-            Instruction::HLT() => write_opcode(&mut binary_file, OpCode::Hlt),
-        };
-    }
+/// Assemble a list of instructions into an executable, loaded at address zero,
+/// and write it to a file.
+///
+/// # Arguments
+///
+/// * `instructions` - the instructions to be assembled.
+/// * `path` - the path to which the executable should be written.
+pub fn assemble<P: AsRef<Path>>(instructions: &[Instruction], path: P) -> executable::Result<()> {
+    assemble_executable(instructions, 0).save(path)
 }
 
-fn write_opcode(writer: &mut BinaryWriter, opcode: OpCode) {
-    writer.write_i16(opcode as i16);
+/// Assemble a list of instructions into an executable whose code section is
+/// loaded at the specified address.
+///
+/// # Arguments
+///
+/// * `instructions` - the instructions to be assembled.
+/// * `base` - the address at which the code section is to be loaded.
+pub fn assemble_executable(instructions: &[Instruction], base: u32) -> Executable {
+    let code = instructions.iter().flat_map(|i| i.encode()).collect();
+
+    Executable::new(Section::code(base, code))
 }
 
-fn write_i32_reg(writer: &mut BinaryWriter, opcode: OpCode, param1: &i32, reg1: &Registers) {
-    writer.write_i16(opcode as i16);
-    writer.write_i32(*param1);
-    writer.write_u8(*reg1 as u8);
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidation_core::registers::Registers;
+    use std::{env, fs, process};
+
+    #[test]
+    fn executables_are_written_to_the_given_path() {
+        let instructions = [
+            Instruction::MovLitReg(0x7B, Registers::R1),
+            Instruction::Out(Registers::R1, 1),
+            Instruction::HLT(),
+        ];
+        let path = env::temp_dir().join(format!("oxidation-{}-assembled.oxe", process::id()));
+        assemble(&instructions, &path).unwrap();
+
+        let executable = Executable::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let code: Vec<u8> = instructions.iter().flat_map(|i| i.encode()).collect();
+        assert_eq!(executable.code().unwrap().bytes, code);
+        assert_eq!(executable.entry_point, 0);
+    }
+}
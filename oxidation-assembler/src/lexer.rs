@@ -0,0 +1,426 @@
+//! Splits assembly source into tokens.
+//!
+//! Numbers beginning with a digit are hexadecimal unless prefixed with `0x`
+//! (hexadecimal), `0b` (binary) or `0d` (decimal), matching the form in which
+//! instructions are displayed, while those containing a decimal point, such as
+//! `1.5`, are decimal floating-point numbers. Words beginning with a letter are returned as
+//! they are, since a word such as `FF` may be either a number or a name, which
+//! only the parser can decide. Comments begin with `;` and run to the end of
+//! the line.
+
+use snafu::Snafu;
+use std::fmt;
+
+/// A range of bytes within the source, along with the line and column at which
+/// it begins.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Span {
+    /// The byte offset of the first character.
+    pub start: usize,
+    /// The byte offset following the last character.
+    pub end: usize,
+    /// The one-based line number.
+    pub line: u32,
+    /// The one-based column number, counted in characters.
+    pub column: u32,
+}
+
+impl Span {
+    /// Returns a span that covers both this span and another that follows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - the later span.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end,
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// A word that begins with a letter, an underscore or a period.
+    Word(String),
+    /// A number or character literal.
+    Number(i64),
+    /// A floating-point number.
+    Float(f64),
+    /// A string literal, with its escape sequences replaced.
+    String(String),
+    Comma,
+    Colon,
+    Equals,
+    Minus,
+    LeftBracket,
+    RightBracket,
+    /// The end of a line.
+    Newline,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenKind::Word(w) => write!(f, "'{}'", w),
+            TokenKind::Number(n) => write!(f, "the number {}", n),
+            TokenKind::Float(n) => write!(f, "the number {}", n),
+            TokenKind::String(_) => write!(f, "a string"),
+            TokenKind::Comma => write!(f, "','"),
+            TokenKind::Colon => write!(f, "':'"),
+            TokenKind::Equals => write!(f, "'='"),
+            TokenKind::Minus => write!(f, "'-'"),
+            TokenKind::LeftBracket => write!(f, "'['"),
+            TokenKind::RightBracket => write!(f, "']'"),
+            TokenKind::Newline => write!(f, "the end of the line"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Snafu)]
+#[snafu(visibility = "pub(crate)")]
+pub enum LexError {
+    #[snafu(display("{}: unexpected character '{}'", span, character))]
+    UnexpectedCharacter { span: Span, character: char },
+    #[snafu(display("{}: '{}' is not a valid number", span, text))]
+    InvalidNumber { span: Span, text: String },
+    #[snafu(display("{}: invalid character literal", span))]
+    InvalidCharacter { span: Span },
+    #[snafu(display("{}: invalid string literal", span))]
+    InvalidString { span: Span },
+}
+
+impl LexError {
+    /// Returns the span of the source at which the error was found.
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedCharacter { span, .. }
+            | LexError::InvalidNumber { span, .. }
+            | LexError::InvalidCharacter { span }
+            | LexError::InvalidString { span } => *span,
+        }
+    }
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    line: u32,
+    column: u32,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().peekable(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, c)| *c)
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars
+            .peek()
+            .map(|(i, _)| *i)
+            .unwrap_or_else(|| self.source.len())
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(c)
+    }
+
+    /// Returns a span from a starting position to the current position.
+    fn span_from(&mut self, start: usize, line: u32, column: u32) -> Span {
+        Span {
+            start,
+            end: self.offset(),
+            line,
+            column,
+        }
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token, LexError>> {
+        // Skip whitespace and comments, but not the ends of lines.
+        loop {
+            match self.peek()? {
+                '\n' => break,
+                c if c.is_whitespace() => {
+                    self.bump();
+                }
+                ';' => {
+                    while !matches!(self.peek(), Some('\n') | None) {
+                        self.bump();
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let (start, line, column) = (self.offset(), self.line, self.column);
+        let c = self.bump()?;
+        let kind = match c {
+            '\n' => TokenKind::Newline,
+            ',' => TokenKind::Comma,
+            ':' => TokenKind::Colon,
+            '=' => TokenKind::Equals,
+            '-' => TokenKind::Minus,
+            '[' => TokenKind::LeftBracket,
+            ']' => TokenKind::RightBracket,
+            '\'' => return Some(self.character(start, line, column)),
+            '"' => return Some(self.string(start, line, column)),
+            c if c.is_ascii_digit() => {
+                self.word();
+                let span = self.span_from(start, line, column);
+                let text = &self.source[span.start..span.end];
+                let kind = match parse_number(text) {
+                    Some(n) => Some(TokenKind::Number(n)),
+                    None if text.contains('.') => text.parse().ok().map(TokenKind::Float),
+                    None => None,
+                };
+                return Some(match kind {
+                    Some(kind) => Ok(Token { kind, span }),
+                    None => InvalidNumber {
+                        span,
+                        text: text.to_string(),
+                    }
+                    .fail(),
+                });
+            }
+            c if is_word_start(c) => {
+                self.word();
+                let span = self.span_from(start, line, column);
+                TokenKind::Word(self.source[span.start..span.end].to_string())
+            }
+            character => {
+                let span = self.span_from(start, line, column);
+                return Some(UnexpectedCharacter { span, character }.fail());
+            }
+        };
+
+        Some(Ok(Token {
+            kind,
+            span: self.span_from(start, line, column),
+        }))
+    }
+
+    /// Consume the remaining characters of a word.
+    fn word(&mut self) {
+        while self.peek().is_some_and(is_word_character) {
+            self.bump();
+        }
+    }
+
+    /// Read a character literal, the opening quote of which has been consumed.
+    fn character(&mut self, start: usize, line: u32, column: u32) -> Result<Token, LexError> {
+        let value = match self.peek() {
+            Some('\n') | Some('\'') | None => None,
+            _ => self.character_value(),
+        };
+
+        let closed = self.peek() == Some('\'');
+
+        // Resynchronise at the closing quote, or at the end of the line if there is none.
+        while !matches!(self.peek(), Some('\'') | Some('\n') | None) {
+            self.bump();
+        }
+        if self.peek() == Some('\'') {
+            self.bump();
+        }
+
+        let span = self.span_from(start, line, column);
+        match value {
+            Some(c) if closed => Ok(Token {
+                kind: TokenKind::Number(c as i64),
+                span,
+            }),
+            _ => InvalidCharacter { span }.fail(),
+        }
+    }
+
+    /// Read a string literal, the opening quote of which has been consumed.
+    fn string(&mut self, start: usize, line: u32, column: u32) -> Result<Token, LexError> {
+        let mut value = Some(String::new());
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.bump();
+                    break;
+                }
+                Some('\n') | None => {
+                    value = None;
+                    break;
+                }
+                _ => match (self.character_value(), &mut value) {
+                    (Some(c), Some(v)) => v.push(c),
+                    _ => value = None,
+                },
+            }
+        }
+
+        let span = self.span_from(start, line, column);
+        match value {
+            Some(s) => Ok(Token {
+                kind: TokenKind::String(s),
+                span,
+            }),
+            None => InvalidString { span }.fail(),
+        }
+    }
+
+    /// Read the value of a character literal, which may be an escape sequence.
+    fn character_value(&mut self) -> Option<char> {
+        match self.bump() {
+            Some('\\') => match self.bump() {
+                Some('n') => Some('\n'),
+                Some('r') => Some('\r'),
+                Some('t') => Some('\t'),
+                Some('0') => Some('\0'),
+                Some(c @ '\\') | Some(c @ '\'') | Some(c @ '"') => Some(c),
+                _ => None,
+            },
+            c => c,
+        }
+    }
+}
+
+fn is_word_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '.'
+}
+
+fn is_word_character(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Parse a number, which is hexadecimal unless it has a prefix specifying
+/// otherwise. A prefix that is not followed by any digits is itself read as a
+/// hexadecimal number, such that `0B` is eleven.
+///
+/// # Arguments
+///
+/// * `text` - the text of the number.
+pub fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    let (digits, radix) = if let Some(d) = lower.strip_prefix("0x") {
+        (d, 16)
+    } else if let Some(d) = lower.strip_prefix("0b").filter(|d| !d.is_empty()) {
+        (d, 2)
+    } else if let Some(d) = lower.strip_prefix("0d").filter(|d| !d.is_empty()) {
+        (d, 10)
+    } else {
+        (lower.as_str(), 16)
+    };
+
+    let digits = digits.replace('_', "");
+    if digits.is_empty() {
+        return None;
+    }
+
+    i64::from_str_radix(&digits, radix).ok()
+}
+
+/// Split source into tokens, returning every error found.
+///
+/// # Arguments
+///
+/// * `source` - the assembly source.
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Vec<LexError>> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    while let Some(token) = lexer.next_token() {
+        match token {
+            Ok(t) => tokens.push(t),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.kind)
+            .collect()
+    }
+
+    #[test]
+    fn literals_are_lexed() {
+        assert_eq!(
+            kinds("7B 0x7b 0b1010 0d42 -'a' '\\n' FF 2.5 \"a\\\"b\" ; comment"),
+            vec![
+                TokenKind::Number(0x7B),
+                TokenKind::Number(0x7B),
+                TokenKind::Number(0b1010),
+                TokenKind::Number(42),
+                TokenKind::Minus,
+                TokenKind::Number('a' as i64),
+                TokenKind::Number('\n' as i64),
+                TokenKind::Word("FF".to_string()),
+                TokenKind::Float(2.5),
+                TokenKind::String("a\"b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_locate_tokens() {
+        let tokens = tokenize("nop\n  mov [0A], R1").unwrap();
+        assert_eq!(tokens[1].kind, TokenKind::Newline);
+        assert_eq!(
+            tokens[2].span,
+            Span {
+                start: 6,
+                end: 9,
+                line: 2,
+                column: 3
+            }
+        );
+        assert_eq!(tokens[4].kind, TokenKind::Number(0x0A));
+        assert_eq!(tokens[4].span.column, 8);
+    }
+
+    #[test]
+    fn errors_are_collected() {
+        let errors = tokenize("mov 12G, R1\nadd $, 'ab'\n.string \"a\\q\"").unwrap_err();
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[0].to_string(), "1:5: '12G' is not a valid number");
+        assert_eq!(errors[1].to_string(), "2:5: unexpected character '$'");
+        assert_eq!(errors[2].to_string(), "2:8: invalid character literal");
+        assert_eq!(errors[3].to_string(), "3:9: invalid string literal");
+    }
+}
@@ -1,3 +1,7 @@
 #![crate_name = "oxidation_assembler"]
 
-pub mod assembler;
\ No newline at end of file
+pub mod assembler;
+pub mod lexer;
+pub mod macros;
+pub mod parser;
+pub mod program;
@@ -0,0 +1,609 @@
+//! Expands macros within assembly source.
+//!
+//! A macro is defined by `.macro`, followed by its name and its parameters, any of
+//! which may be given a default value, and ends with `.endm`. Within the body, each
+//! parameter is replaced by the value given to it, and each name declared by
+//! `.local` is replaced by a name that is unique to the expansion, so that labels
+//! declared within the body do not clash between expansions:
+//!
+//! ```text
+//! .macro countdown reg, start=0d10
+//!     .local loop
+//!     mov start, reg
+//! loop:
+//!     add -1, reg
+//!     mov AC, reg
+//!     jne 0, [loop]
+//! .endm
+//!
+//!     countdown R1
+//!     countdown R2, 3
+//! ```
+//!
+//! A macro must be defined before it is used, and may use other macros, or
+//! itself, up to a limited depth of expansion. The total number of expansions
+//! within a source is also limited, as each level of expansion may multiply it.
+
+use crate::lexer::{Span, Token, TokenKind};
+use crate::parser::{self, ParseError};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The maximum depth to which macros may be expanded within other macros.
+pub const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// The maximum number of expansions made within a source.
+pub const MAX_EXPANSIONS: usize = 100_000;
+
+/// The expansion of a macro from which a statement was produced.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    /// The name of the macro.
+    pub name: String,
+    /// The span of the invocation of the macro.
+    pub call: Span,
+    /// The line on which the macro was defined.
+    pub definition: u32,
+    /// The expansion within which the macro was invoked, if any.
+    pub parent: Option<Rc<Expansion>>,
+}
+
+impl Expansion {
+    /// Returns the line of the invocation made directly within the source.
+    pub fn line(&self) -> u32 {
+        self.root().line
+    }
+
+    /// Returns the span of the invocation made directly within the source.
+    pub fn root(&self) -> Span {
+        match &self.parent {
+            Some(parent) => parent.root(),
+            None => self.call,
+        }
+    }
+
+    /// Wrap an error found within the expansion, such that it describes every
+    /// invocation that led to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - the error.
+    pub fn wrap(&self, error: ParseError) -> ParseError {
+        let error = ParseError::InExpansion {
+            span: self.call,
+            name: self.name.clone(),
+            definition: self.definition,
+            source: Box::new(error),
+        };
+
+        match &self.parent {
+            Some(parent) => parent.wrap(error),
+            None => error,
+        }
+    }
+}
+
+/// A line of tokens, once every macro within it has been expanded.
+pub(crate) struct Line {
+    pub tokens: Vec<Token>,
+    /// The expansion from which the line was produced, if any.
+    pub expansion: Option<Rc<Expansion>>,
+}
+
+struct Macro {
+    /// The parameters, each paired with its default value, if any.
+    parameters: Vec<(String, Option<Vec<Token>>)>,
+    /// The names that are made unique within each expansion.
+    locals: Vec<String>,
+    body: Vec<Vec<Token>>,
+    /// The line on which the macro was defined.
+    line: u32,
+}
+
+/// Returns whether a line begins with a directive.
+///
+/// # Arguments
+///
+/// * `line` - the tokens of the line.
+/// * `directive` - the lowercase name of the directive.
+fn is_directive(line: &[Token], directive: &str) -> bool {
+    matches!(line.first(), Some(Token { kind: TokenKind::Word(w), .. }) if w.eq_ignore_ascii_case(directive))
+}
+
+/// Returns an error describing a token, which was not what was expected.
+///
+/// # Arguments
+///
+/// * `line` - the tokens of the line.
+/// * `group` - the tokens of the line in which the unexpected token was found.
+/// * `index` - the index of the unexpected token within the group, which may lie
+///   past its end.
+/// * `expected` - a description of what was expected.
+fn unexpected(line: &[Token], group: &[Token], index: usize, expected: &'static str) -> ParseError {
+    let (span, found) = match group.get(index) {
+        Some(t) => (t.span, t.kind.clone()),
+        None => (line[line.len() - 1].span, TokenKind::Newline),
+    };
+
+    ParseError::UnexpectedToken {
+        span,
+        expected,
+        found,
+    }
+}
+
+/// Split tokens into the groups separated by commas.
+fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    tokens.split(|t| t.kind == TokenKind::Comma).collect()
+}
+
+struct Expander {
+    macros: HashMap<String, Rc<Macro>>,
+    /// The number of expansions made so far, from which local names are made unique.
+    expansions: usize,
+    /// Whether the rest of the current invocation within the source is to be
+    /// skipped, as an error was found that would recur within every branch of it.
+    abandoned: bool,
+    lines: Vec<Line>,
+    errors: Vec<ParseError>,
+}
+
+impl Expander {
+    /// Define a macro, consuming the lines of its body.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - the tokens of the line holding the `.macro` directive.
+    /// * `lines` - the lines that follow the header.
+    fn define(&mut self, header: &[Token], lines: &mut impl Iterator<Item = Vec<Token>>) {
+        let mut body = Vec::new();
+        let mut locals = Vec::new();
+        let mut terminated = false;
+        for line in lines {
+            if is_directive(&line, ".endm") {
+                terminated = true;
+                break;
+            }
+            if is_directive(&line, ".macro") {
+                self.errors
+                    .push(ParseError::NestedMacro { span: line[0].span });
+                continue;
+            }
+            if is_directive(&line, ".local") {
+                for group in split_commas(&line[1..]) {
+                    let error = match group {
+                        [Token {
+                            kind: TokenKind::Word(name),
+                            ..
+                        }] => {
+                            locals.push(name.clone());
+                            continue;
+                        }
+                        [Token {
+                            kind: TokenKind::Word(_),
+                            ..
+                        }, ..] => unexpected(&line, group, 1, "','"),
+                        _ => unexpected(&line, group, 0, "the name of a local label"),
+                    };
+                    self.errors.push(error);
+                }
+                if line.len() == 1 {
+                    self.errors
+                        .push(unexpected(&line, &line, 1, "the name of a local label"));
+                }
+                continue;
+            }
+
+            body.push(line);
+        }
+
+        let (name, span) = match header.get(1) {
+            Some(Token {
+                kind: TokenKind::Word(name),
+                span,
+            }) => (name.clone(), *span),
+            _ => {
+                self.errors
+                    .push(unexpected(header, header, 1, "the name of the macro"));
+                return;
+            }
+        };
+        if !terminated {
+            self.errors
+                .push(ParseError::UnterminatedMacro { span, name });
+            return;
+        }
+
+        let lowercase = name.to_ascii_lowercase();
+        if name.starts_with('.') || parser::MNEMONICS.contains(&lowercase.as_str()) {
+            self.errors
+                .push(ParseError::InvalidMacroName { span, name });
+            return;
+        }
+        if let Some(previous) = self.macros.get(&name) {
+            self.errors.push(ParseError::DuplicateMacro {
+                span,
+                name,
+                previous: previous.line,
+            });
+            return;
+        }
+
+        let mut parameters = Vec::new();
+        for group in split_commas(&header[2..]) {
+            let parameter = match group {
+                [Token {
+                    kind: TokenKind::Word(p),
+                    ..
+                }] => (p.clone(), None),
+                [Token {
+                    kind: TokenKind::Word(p),
+                    ..
+                }, Token {
+                    kind: TokenKind::Equals,
+                    ..
+                }, default @ ..]
+                    if !default.is_empty() =>
+                {
+                    (p.clone(), Some(default.to_vec()))
+                }
+                [Token {
+                    kind: TokenKind::Word(_),
+                    ..
+                }, Token {
+                    kind: TokenKind::Equals,
+                    ..
+                }] => {
+                    self.errors
+                        .push(unexpected(header, group, 2, "a default value"));
+                    return;
+                }
+                [Token {
+                    kind: TokenKind::Word(_),
+                    ..
+                }, ..] => {
+                    self.errors.push(unexpected(header, group, 1, "',' or '='"));
+                    return;
+                }
+                _ => {
+                    self.errors
+                        .push(unexpected(header, group, 0, "a parameter"));
+                    return;
+                }
+            };
+            parameters.push(parameter);
+        }
+
+        let line = span.line;
+        self.macros.insert(
+            name,
+            Rc::new(Macro {
+                parameters,
+                locals,
+                body,
+                line,
+            }),
+        );
+    }
+
+    /// Expand any macro invoked by a line, adding the resulting lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - the tokens of the line.
+    /// * `expansion` - the expansion from which the line was produced, if any.
+    /// * `depth` - the number of expansions within which the line lies.
+    fn expand(&mut self, tokens: Vec<Token>, expansion: Option<Rc<Expansion>>, depth: usize) {
+        // An invocation may follow a label.
+        let start = match tokens.as_slice() {
+            [Token {
+                kind: TokenKind::Word(_),
+                ..
+            }, Token {
+                kind: TokenKind::Colon,
+                ..
+            }, ..] => 2,
+            _ => 0,
+        };
+        let (name, definition) = match tokens.get(start) {
+            Some(Token {
+                kind: TokenKind::Word(name),
+                ..
+            }) if self.macros.contains_key(name) => (name.clone(), self.macros[name].clone()),
+            _ => {
+                self.lines.push(Line { tokens, expansion });
+                return;
+            }
+        };
+
+        let call = tokens[start].span.to(tokens[tokens.len() - 1].span);
+        let error = |e| match &expansion {
+            Some(expansion) => expansion.wrap(e),
+            None => e,
+        };
+
+        // The whole chain of expansions would be too long to be of use, so these
+        // errors are reported at the invocation made directly within the source.
+        let root = expansion.as_ref().map_or(call, |e| e.root());
+        if depth == MAX_EXPANSION_DEPTH {
+            self.errors.push(ParseError::ExpansionTooDeep {
+                span: root,
+                name,
+                definition: definition.line,
+                limit: MAX_EXPANSION_DEPTH,
+            });
+            self.abandoned = true;
+            return;
+        }
+        if self.expansions == MAX_EXPANSIONS {
+            self.errors.push(ParseError::TooManyExpansions {
+                span: root,
+                limit: MAX_EXPANSIONS,
+            });
+            self.abandoned = true;
+            return;
+        }
+
+        let arguments = split_commas(&tokens[start + 1..]);
+        if arguments.len() > definition.parameters.len() {
+            self.errors.push(error(ParseError::TooManyArguments {
+                span: call,
+                name,
+                definition: definition.line,
+                expected: definition.parameters.len(),
+            }));
+            return;
+        }
+
+        let mut values = HashMap::new();
+        for (i, (parameter, default)) in definition.parameters.iter().enumerate() {
+            let value = match (arguments.get(i), default) {
+                (Some(argument), _) if !argument.is_empty() => argument.to_vec(),
+                (_, Some(default)) => default.clone(),
+                (_, None) => {
+                    self.errors.push(error(ParseError::MissingArgument {
+                        span: call,
+                        name,
+                        definition: definition.line,
+                        parameter: parameter.clone(),
+                    }));
+                    return;
+                }
+            };
+            values.insert(parameter.as_str(), value);
+        }
+
+        if start > 0 {
+            self.lines.push(Line {
+                tokens: tokens[..start].to_vec(),
+                expansion: expansion.clone(),
+            });
+        }
+
+        self.expansions += 1;
+        let locals: HashMap<&str, String> = definition
+            .locals
+            .iter()
+            .map(|l| (l.as_str(), format!("{}.{}", l, self.expansions)))
+            .collect();
+        let expansion = Rc::new(Expansion {
+            name,
+            call,
+            definition: definition.line,
+            parent: expansion,
+        });
+
+        for line in &definition.body {
+            let mut expanded = Vec::new();
+            for token in line {
+                match &token.kind {
+                    TokenKind::Word(w) if values.contains_key(w.as_str()) => {
+                        expanded.extend(values[w.as_str()].iter().cloned())
+                    }
+                    TokenKind::Word(w) if locals.contains_key(w.as_str()) => expanded.push(Token {
+                        kind: TokenKind::Word(locals[w.as_str()].clone()),
+                        span: token.span,
+                    }),
+                    _ => expanded.push(token.clone()),
+                }
+            }
+
+            if self.abandoned {
+                return;
+            }
+            if !expanded.is_empty() {
+                self.expand(expanded, Some(expansion.clone()), depth + 1);
+            }
+        }
+    }
+}
+
+/// Expand the macros within the lines of a source, returning the resulting lines
+/// along with every error found.
+///
+/// # Arguments
+///
+/// * `lines` - the tokens of each non-empty line.
+pub(crate) fn expand(lines: Vec<Vec<Token>>) -> (Vec<Line>, Vec<ParseError>) {
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        expansions: 0,
+        abandoned: false,
+        lines: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut lines = lines.into_iter();
+    while let Some(line) = lines.next() {
+        if is_directive(&line, ".macro") {
+            expander.define(&line, &mut lines);
+        } else if is_directive(&line, ".endm") {
+            expander
+                .errors
+                .push(ParseError::UnexpectedEndm { span: line[0].span });
+        } else {
+            expander.abandoned = false;
+            expander.expand(line, None, 0);
+        }
+    }
+
+    (expander.lines, expander.errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_EXPANSIONS;
+    use crate::program;
+    use oxidation_core::instructions::enums::Instruction;
+    use oxidation_core::registers::Registers;
+
+    fn errors(source: &str) -> Vec<String> {
+        program::assemble(source, 0)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn macros_are_expanded() {
+        let source = "\
+.macro countdown reg, start=0d10
+    .local loop
+    mov start, reg
+loop:
+    add -1, reg
+    mov AC, reg
+    jne 0, [loop]
+.endm
+.macro twice a, b
+    countdown a
+    countdown b, 3
+.endm
+.sub main
+    twice R1, R2
+    hlt
+";
+        let program = program::assemble(source, 0).unwrap();
+
+        let instructions: Vec<Instruction> = program
+            .instructions
+            .iter()
+            .map(|(_, i)| i.clone())
+            .collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::MovLitReg(10, Registers::R1),
+                Instruction::AddLitReg(-1, Registers::R1),
+                Instruction::MovRegReg(Registers::AC, Registers::R1),
+                Instruction::JmpNotEq(0, 0x07),
+                Instruction::MovLitReg(3, Registers::R2),
+                Instruction::AddLitReg(-1, Registers::R2),
+                Instruction::MovRegReg(Registers::AC, Registers::R2),
+                Instruction::JmpNotEq(0, 0x23),
+                Instruction::HLT(),
+            ]
+        );
+
+        assert_eq!(program.labels.address_of("loop.2"), Some(0x07));
+        assert_eq!(program.labels.address_of("loop.3"), Some(0x23));
+        assert_eq!(program.lines[7], (0x2E, 14));
+        assert_eq!(program.lines[8], (0x38, 15));
+    }
+
+    #[test]
+    fn errors_report_the_invocation_and_definition() {
+        let source = "\
+.macro load reg, value
+    mov value, reg
+    jne 0, [missing]
+.endm
+.macro forever
+    forever
+.endm
+    load 5, R1
+    load
+    load R1, 1, 2
+    forever
+";
+        assert_eq!(
+            errors(source),
+            vec![
+                "8:5: in the expansion of 'load', defined on line 1: 2:5: invalid operands for 'mov'",
+                "9:5: no value was given for the parameter 'reg' of 'load', defined on line 1",
+                "10:5: 'load', defined on line 1, takes at most 2 arguments",
+                "11:5: the expansion of 'forever', defined on line 5, exceeds the depth limit of 64",
+            ]
+        );
+
+        let source = "\
+.macro load reg, value
+    mov value, reg
+    jne 0, [missing]
+.endm
+.macro outer
+    load R1, 5
+.endm
+    outer
+";
+        assert_eq!(
+            errors(source),
+            vec![
+                "8:5: in the expansion of 'outer', defined on line 5: \
+                 6:5: in the expansion of 'load', defined on line 1: \
+                 3:13: the label 'missing' is not defined"
+            ]
+        );
+
+        // Every span is preceded by the file when the errors are described.
+        let described: Vec<String> = program::assemble(source, 0)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.describe("m.s"))
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                "m.s:8:5: in the expansion of 'outer', defined on line 5: \
+                 m.s:6:5: in the expansion of 'load', defined on line 1: \
+                 m.s:3:13: the label 'missing' is not defined"
+            ]
+        );
+
+        // Each expansion invokes the macro twice, so the expansion is abandoned
+        // once the first chain reaches the depth limit.
+        assert_eq!(
+            errors(".macro f\n    f\n    f\n.endm\n    f\n"),
+            vec!["5:5: the expansion of 'f', defined on line 1, exceeds the depth limit of 64"]
+        );
+
+        // The same fan-out without recursion is caught by the limit on expansions.
+        let mut source = ".macro m0\n    nop\n.endm\n".to_string();
+        for i in 1..20 {
+            source += &format!(".macro m{}\n    m{}\n    m{}\n.endm\n", i, i - 1, i - 1);
+        }
+        source += "    m19\n    nop\n";
+        assert_eq!(
+            errors(&source),
+            vec![format!(
+                "80:5: the expansion exceeds the limit of {} macro expansions within a source",
+                MAX_EXPANSIONS
+            )]
+        );
+
+        assert_eq!(
+            errors(".macro mov\n.endm\n.macro m a=\n.endm\n.endm\n.macro open"),
+            vec![
+                "1:8: 'mov' cannot be used as the name of a macro",
+                "3:11: expected a default value, but found the end of the line",
+                "5:1: '.endm' was found outside of a macro",
+                "6:8: the macro 'open' is missing '.endm'",
+            ]
+        );
+    }
+}
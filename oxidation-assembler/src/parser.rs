@@ -0,0 +1,1008 @@
+//! Parses assembly source into instructions.
+//!
+//! The syntax matches the form in which instructions are displayed, with one
+//! instruction per line. Operands are separated by commas and are either a
+//! register, a literal or a literal address enclosed in square brackets:
+//!
+//! ```text
+//! mov 0d10, R1    ; R1 = 10
+//! add -1, R1
+//! mov AC, R1
+//! jne 0, [07]
+//! hlt
+//! ```
+//!
+//! A label is declared by a name followed by a colon, either alone on a line or
+//! before an instruction, and a subroutine by the `.sub` directive followed by
+//! its name. Both refer to the address of the instruction that follows them and
+//! may be used in place of any literal, before or after their declaration:
+//!
+//! ```text
+//! .sub main
+//!     mov 0d10, R1
+//! loop:
+//!     add -1, R1
+//!     mov AC, R1
+//!     jne 0, [loop]
+//!     hlt
+//! ```
+//!
+//! Directives begin with a period. `.text` and `.data` select the section into
+//! which the statements that follow are placed. `.byte`, `.i16`, `.i32` and `.i64`
+//! place lists of integers of each size, any of which may be a label, while `.f32`
+//! places single-precision floating-point numbers and `.string` (or `.asciz`) a
+//! string followed by a zero byte. `.zero` places a number of zero bytes, `.align`
+//! pads the section to a multiple of a power of two and `.org` moves the section
+//! to an address:
+//!
+//! ```text
+//! .data
+//! message: .string "hello\n"
+//! .align 4
+//! table:   .i32 message, 0, -1
+//! ```
+//!
+//! Mnemonics and register names are not case sensitive. A word that names a
+//! register is always a register, so a hexadecimal literal that could be read
+//! as one, such as `AC`, must be written with a prefix (`0xAC`). Likewise, a word
+//! that is a valid hexadecimal literal, such as `beef`, cannot be a label.
+
+use crate::lexer::{self, LexError, Span, Token, TokenKind};
+use crate::macros::{self, Expansion};
+use oxidation_core::instructions::enums::Instruction;
+use oxidation_core::registers::Registers;
+use snafu::Snafu;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq, Snafu)]
+#[snafu(visibility = "pub(crate)")]
+pub enum ParseError {
+    #[snafu(display("{}", source))]
+    Lex { source: LexError },
+    #[snafu(display("{}: unknown mnemonic '{}'", span, mnemonic))]
+    UnknownMnemonic { span: Span, mnemonic: String },
+    #[snafu(display("{}: expected {}, but found {}", span, expected, found))]
+    UnexpectedToken {
+        span: Span,
+        expected: &'static str,
+        found: TokenKind,
+    },
+    #[snafu(display("{}: invalid operands for '{}'", span, mnemonic))]
+    InvalidOperands { span: Span, mnemonic: String },
+    #[snafu(display("{}: the value {} is out of range", span, value))]
+    LiteralOutOfRange { span: Span, value: i64 },
+    #[snafu(display("{}: unknown directive '{}'", span, directive))]
+    UnknownDirective { span: Span, directive: String },
+    #[snafu(display("{}: the alignment {} is not a power of two", span, alignment))]
+    InvalidAlignment { span: Span, alignment: i64 },
+    #[snafu(display("{}: instructions may only be placed in the text section", span))]
+    InstructionOutsideText { span: Span },
+    #[snafu(display(
+        "{}: the origin {:#010X} lies before the current address {:#010X}",
+        span,
+        origin,
+        address
+    ))]
+    OriginBehind {
+        span: Span,
+        origin: u32,
+        address: u32,
+    },
+    #[snafu(display("{}: the data overlaps other data or instructions", span))]
+    OverlappingData { span: Span },
+    #[snafu(display("{}: the statement extends beyond the end of memory", span))]
+    AddressOverflow { span: Span },
+    #[snafu(display("{}: the text section is empty, but a program requires code", span))]
+    EmptyText { span: Span },
+    #[snafu(display(
+        "{}: in the expansion of '{}', defined on line {}: {}",
+        span,
+        name,
+        definition,
+        source
+    ))]
+    InExpansion {
+        span: Span,
+        name: String,
+        definition: u32,
+        source: Box<ParseError>,
+    },
+    #[snafu(display("{}: macros cannot be defined within other macros", span))]
+    NestedMacro { span: Span },
+    #[snafu(display("{}: the macro '{}' is missing '.endm'", span, name))]
+    UnterminatedMacro { span: Span, name: String },
+    #[snafu(display("{}: '.endm' was found outside of a macro", span))]
+    UnexpectedEndm { span: Span },
+    #[snafu(display("{}: '{}' cannot be used as the name of a macro", span, name))]
+    InvalidMacroName { span: Span, name: String },
+    #[snafu(display(
+        "{}: the macro '{}' is already defined on line {}",
+        span,
+        name,
+        previous
+    ))]
+    DuplicateMacro {
+        span: Span,
+        name: String,
+        previous: u32,
+    },
+    #[snafu(display(
+        "{}: '{}', defined on line {}, takes at most {} arguments",
+        span,
+        name,
+        definition,
+        expected
+    ))]
+    TooManyArguments {
+        span: Span,
+        name: String,
+        definition: u32,
+        expected: usize,
+    },
+    #[snafu(display(
+        "{}: no value was given for the parameter '{}' of '{}', defined on line {}",
+        span,
+        parameter,
+        name,
+        definition
+    ))]
+    MissingArgument {
+        span: Span,
+        name: String,
+        definition: u32,
+        parameter: String,
+    },
+    #[snafu(display(
+        "{}: the expansion of '{}', defined on line {}, exceeds the depth limit of {}",
+        span,
+        name,
+        definition,
+        limit
+    ))]
+    ExpansionTooDeep {
+        span: Span,
+        name: String,
+        definition: u32,
+        limit: usize,
+    },
+    #[snafu(display(
+        "{}: the expansion exceeds the limit of {} macro expansions within a source",
+        span,
+        limit
+    ))]
+    TooManyExpansions { span: Span, limit: usize },
+    #[snafu(display("{}: '{}' cannot be used as a label", span, name))]
+    InvalidLabel { span: Span, name: String },
+    #[snafu(display("{}: the label '{}' is not defined", span, name))]
+    UndefinedLabel { span: Span, name: String },
+    #[snafu(display(
+        "{}: the label '{}' is already defined on line {}",
+        span,
+        name,
+        previous
+    ))]
+    DuplicateLabel {
+        span: Span,
+        name: String,
+        previous: u32,
+    },
+}
+
+impl ParseError {
+    /// Returns a description of the error in which every span, including those of
+    /// the invocations of macros that led to it, is preceded by the source file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - the path of the source file.
+    pub fn describe(&self, file: &str) -> String {
+        match self {
+            ParseError::InExpansion {
+                span,
+                name,
+                definition,
+                source,
+            } => format!(
+                "{}:{}: in the expansion of '{}', defined on line {}: {}",
+                file,
+                span,
+                name,
+                definition,
+                source.describe(file)
+            ),
+            _ => format!("{}:{}", file, self),
+        }
+    }
+
+    /// Returns the span of the source at which the error was found.
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Lex { source } => source.span(),
+            ParseError::UnknownMnemonic { span, .. }
+            | ParseError::UnexpectedToken { span, .. }
+            | ParseError::InvalidOperands { span, .. }
+            | ParseError::LiteralOutOfRange { span, .. }
+            | ParseError::UnknownDirective { span, .. }
+            | ParseError::InvalidAlignment { span, .. }
+            | ParseError::InstructionOutsideText { span }
+            | ParseError::OriginBehind { span, .. }
+            | ParseError::OverlappingData { span }
+            | ParseError::AddressOverflow { span }
+            | ParseError::EmptyText { span }
+            | ParseError::InExpansion { span, .. }
+            | ParseError::NestedMacro { span }
+            | ParseError::UnterminatedMacro { span, .. }
+            | ParseError::UnexpectedEndm { span }
+            | ParseError::InvalidMacroName { span, .. }
+            | ParseError::DuplicateMacro { span, .. }
+            | ParseError::TooManyArguments { span, .. }
+            | ParseError::MissingArgument { span, .. }
+            | ParseError::ExpansionTooDeep { span, .. }
+            | ParseError::TooManyExpansions { span, .. }
+            | ParseError::InvalidLabel { span, .. }
+            | ParseError::UndefinedLabel { span, .. }
+            | ParseError::DuplicateLabel { span, .. } => *span,
+        }
+    }
+}
+
+type Result<T, E = ParseError> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
+    /// An instruction, any operand of which that refers to a label holds zero
+    /// until the reference is resolved.
+    Instruction(Instruction),
+    /// The declaration of a label.
+    Label(String),
+    /// The declaration of a subroutine.
+    Subroutine(String),
+    /// A switch to the section into which the statements that follow are placed.
+    Segment(Segment),
+    /// A list of values, any of which that refers to a label holds zero until the
+    /// reference is resolved.
+    Data {
+        bytes: Vec<u8>,
+        /// The size, in bytes, of each value.
+        width: usize,
+    },
+    /// A number of bytes filled with zeros.
+    Zero(u32),
+    /// Padding up to the next multiple of an alignment, which is a power of two.
+    Align(u32),
+    /// A move of the current section to an address.
+    Org(u32),
+}
+
+/// The sections of a program.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// The section holding the instructions.
+    Text,
+    /// The section holding initialised data.
+    Data,
+}
+
+/// A reference to a label from an operand of an instruction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub name: String,
+    /// The zero-based index of the operand.
+    pub operand: usize,
+    pub span: Span,
+}
+
+/// A statement parsed from a single line of source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    /// The span of the source from which the statement was parsed.
+    pub span: Span,
+    /// The references to labels made by the operands of the statement.
+    pub references: Vec<Reference>,
+    /// The expansion of the macro from which the statement was produced, if any.
+    pub expansion: Option<Rc<Expansion>>,
+}
+
+impl Statement {
+    /// Returns the line of the source to which the statement belongs, which is that
+    /// of the invocation for a statement produced by a macro.
+    pub fn line(&self) -> u32 {
+        match &self.expansion {
+            Some(expansion) => expansion.line(),
+            None => self.span.line,
+        }
+    }
+
+    /// Wrap an error found within the statement, such that it describes the
+    /// invocations of any macros that produced the statement.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - the error.
+    pub fn wrap(&self, error: ParseError) -> ParseError {
+        match &self.expansion {
+            Some(expansion) => expansion.wrap(error),
+            None => error,
+        }
+    }
+}
+
+/// The mnemonics of every instruction.
+pub(crate) const MNEMONICS: [&str; 14] = [
+    "nop", "mov", "add", "out", "in", "push", "pop", "call", "ret", "iret", "jne", "jeq", "hlt",
+    "halt",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Register(Registers),
+    Literal(i64, Span),
+    Address(i64, Span),
+}
+
+/// Returns the register with the specified name, ignoring case.
+///
+/// # Arguments
+///
+/// * `name` - the name of the register.
+pub fn register(name: &str) -> Option<Registers> {
+    (0..Registers::COUNT as u8)
+        .filter_map(Registers::from_u8)
+        .find(|r| r.to_string().eq_ignore_ascii_case(name))
+}
+
+/// Parses the tokens of a single line.
+struct LineParser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    /// The span of the end of the line.
+    end: Span,
+    /// The references made by the operands parsed so far.
+    references: Vec<Reference>,
+}
+
+impl<'a> LineParser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Returns an error describing the next token, which was not what was expected.
+    fn unexpected<T>(&self, expected: &'static str) -> Result<T> {
+        let (span, found) = match self.peek() {
+            Some(t) => (t.span, t.kind.clone()),
+            None => (self.end, TokenKind::Newline),
+        };
+
+        UnexpectedToken {
+            span,
+            expected,
+            found,
+        }
+        .fail()
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<&'a Token> {
+        match self.peek() {
+            Some(t) if t.kind == kind => {
+                self.position += 1;
+                Ok(t)
+            }
+            _ => self.unexpected(expected),
+        }
+    }
+
+    /// Parse the line, which holds an optional label followed by an optional statement.
+    fn line(&mut self, statements: &mut Vec<Statement>) -> Result<()> {
+        if let [Token {
+            kind: TokenKind::Word(name),
+            span,
+        }, Token {
+            kind: TokenKind::Colon,
+            span: colon,
+        }, ..] = self.tokens
+        {
+            self.position += 2;
+            let span = span.to(*colon);
+            check_label(name, span)?;
+            statements.push(Statement {
+                kind: StatementKind::Label(name.clone()),
+                span,
+                references: Vec::new(),
+                expansion: None,
+            });
+        }
+
+        if self.peek().is_some() {
+            statements.push(self.statement()?);
+        }
+
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<Statement> {
+        let (mnemonic, start) = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Word(w),
+                span,
+            }) => (w.to_ascii_lowercase(), *span),
+            _ => return self.unexpected("a mnemonic"),
+        };
+        self.position += 1;
+
+        if mnemonic.starts_with('.') {
+            return self.directive(&mnemonic, start);
+        }
+
+        let mut operands = Vec::new();
+        let mut end = start;
+        if self.peek().is_some() {
+            loop {
+                let (operand, span) = self.operand(operands.len())?;
+                operands.push(operand);
+                end = span;
+                if self.peek().is_none() {
+                    break;
+                }
+                self.expect(TokenKind::Comma, "','")?;
+            }
+        }
+
+        let span = start.to(end);
+        let instruction = build(&mnemonic, &operands, span)?;
+
+        Ok(Statement {
+            kind: StatementKind::Instruction(instruction),
+            span,
+            references: std::mem::take(&mut self.references),
+            expansion: None,
+        })
+    }
+
+    /// Parse the remainder of a directive.
+    ///
+    /// # Arguments
+    ///
+    /// * `directive` - the lowercase name of the directive.
+    /// * `start` - the span of the name of the directive.
+    fn directive(&mut self, directive: &str, start: Span) -> Result<Statement> {
+        let kind = match directive {
+            ".sub" => {
+                let (name, span) = match self.peek() {
+                    Some(Token {
+                        kind: TokenKind::Word(w),
+                        span,
+                    }) => (w.clone(), *span),
+                    _ => return self.unexpected("the name of the subroutine"),
+                };
+                self.position += 1;
+                check_label(&name, span)?;
+                StatementKind::Subroutine(name)
+            }
+            ".text" => StatementKind::Segment(Segment::Text),
+            ".data" => StatementKind::Segment(Segment::Data),
+            ".byte" => self.values(1)?,
+            ".i16" => self.values(2)?,
+            ".i32" => self.values(4)?,
+            ".i64" => self.values(8)?,
+            ".f32" => self.floats()?,
+            ".string" | ".asciz" => {
+                let mut bytes = match self.peek() {
+                    Some(Token {
+                        kind: TokenKind::String(s),
+                        ..
+                    }) => s.clone().into_bytes(),
+                    _ => return self.unexpected("a string"),
+                };
+                self.position += 1;
+                bytes.push(0);
+                StatementKind::Data { bytes, width: 1 }
+            }
+            ".zero" => {
+                let (size, span) = self.constant()?;
+                StatementKind::Zero(to_u32(size, span)?)
+            }
+            ".align" => {
+                let (alignment, span) = self.constant()?;
+                match to_u32(alignment, span)? {
+                    a if a.is_power_of_two() => StatementKind::Align(a),
+                    _ => return InvalidAlignment { span, alignment }.fail(),
+                }
+            }
+            ".org" => {
+                let (address, span) = self.constant()?;
+                StatementKind::Org(to_u32(address, span)?)
+            }
+            _ => {
+                return UnknownDirective {
+                    span: start,
+                    directive,
+                }
+                .fail()
+            }
+        };
+
+        if self.peek().is_some() {
+            return self.unexpected("the end of the line");
+        }
+
+        Ok(Statement {
+            kind,
+            span: start.to(self.tokens[self.position - 1].span),
+            references: std::mem::take(&mut self.references),
+            expansion: None,
+        })
+    }
+
+    /// Parse a list of integers, each of which may be a reference to a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - the size, in bytes, of each integer.
+    fn values(&mut self, width: usize) -> Result<StatementKind> {
+        let mut bytes = Vec::new();
+        loop {
+            let (value, span) = self.literal(bytes.len() / width)?;
+            bytes.extend(encode_value(value, width, span)?);
+            if self.peek().is_none() {
+                break;
+            }
+            self.expect(TokenKind::Comma, "','")?;
+        }
+
+        Ok(StatementKind::Data { bytes, width })
+    }
+
+    /// Parse a list of single-precision floating-point numbers.
+    fn floats(&mut self) -> Result<StatementKind> {
+        let mut bytes = Vec::new();
+        loop {
+            let negative = self.peek().is_some_and(|t| t.kind == TokenKind::Minus);
+            if negative {
+                self.position += 1;
+            }
+
+            let value = match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::Float(f)) => *f,
+                Some(TokenKind::Number(n)) => *n as f64,
+                _ => return self.unexpected("a number"),
+            };
+            self.position += 1;
+
+            let value = if negative { -value } else { value };
+            bytes.extend_from_slice(&(value as f32).to_le_bytes());
+            if self.peek().is_none() {
+                break;
+            }
+            self.expect(TokenKind::Comma, "','")?;
+        }
+
+        Ok(StatementKind::Data { bytes, width: 4 })
+    }
+
+    /// Parse a literal that may not refer to a label, since its value is needed
+    /// before any label can be resolved.
+    fn constant(&mut self) -> Result<(i64, Span)> {
+        let position = self.position;
+        let count = self.references.len();
+        let result = self.literal(0)?;
+        if self.references.len() > count {
+            self.references.truncate(count);
+            self.position = position;
+            return self.unexpected("a number");
+        }
+
+        Ok(result)
+    }
+
+    /// Parse an operand, recording any reference that it makes to a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the zero-based index of the operand.
+    fn operand(&mut self, index: usize) -> Result<(Operand, Span)> {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::LeftBracket,
+                span: start,
+            }) => {
+                self.position += 1;
+                let (value, _) = self.literal(index)?;
+                let end = self.expect(TokenKind::RightBracket, "']'")?.span;
+                let span = start.to(end);
+                Ok((Operand::Address(value, span), span))
+            }
+            Some(Token {
+                kind: TokenKind::Word(w),
+                span,
+            }) if register(w).is_some() => {
+                self.position += 1;
+                Ok((Operand::Register(register(w).unwrap()), *span))
+            }
+            _ => {
+                let (value, span) = self.literal(index)?;
+                Ok((Operand::Literal(value, span), span))
+            }
+        }
+    }
+
+    /// Parse a literal, which may be a reference to a label.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - the zero-based index of the operand holding the literal.
+    fn literal(&mut self, index: usize) -> Result<(i64, Span)> {
+        let negative = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Minus,
+                span,
+            }) => {
+                self.position += 1;
+                Some(*span)
+            }
+            _ => None,
+        };
+
+        let (value, span) = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Number(n),
+                span,
+            }) => (*n, *span),
+            Some(Token {
+                kind: TokenKind::Word(w),
+                span,
+            }) => match lexer::parse_number(w) {
+                Some(n) => (n, *span),
+                None if negative.is_none() => {
+                    self.references.push(Reference {
+                        name: w.clone(),
+                        operand: index,
+                        span: *span,
+                    });
+                    (0, *span)
+                }
+                None => return self.unexpected("a literal"),
+            },
+            _ => return self.unexpected("a literal"),
+        };
+        self.position += 1;
+
+        match negative {
+            Some(start) => Ok((-value, start.to(span))),
+            None => Ok((value, span)),
+        }
+    }
+}
+
+/// Build an instruction from its mnemonic and operands.
+///
+/// # Arguments
+///
+/// * `mnemonic` - the lowercase mnemonic.
+/// * `operands` - the operands.
+/// * `span` - the span of the whole statement.
+fn build(mnemonic: &str, operands: &[Operand], span: Span) -> Result<Instruction> {
+    use Operand::*;
+
+    let ins = match (mnemonic, operands) {
+        ("nop", []) => Instruction::NOP(),
+        ("hlt", []) | ("halt", []) => Instruction::HLT(),
+        ("ret", []) => Instruction::Ret(),
+        ("iret", []) => Instruction::IRet(),
+        ("mov", [Literal(v, s), Register(r)]) => Instruction::MovLitReg(to_i32(*v, *s)?, *r),
+        ("mov", [Register(a), Register(b)]) => Instruction::MovRegReg(*a, *b),
+        ("mov", [Register(r), Address(a, s)]) => Instruction::MovRegMem(*r, to_u32(*a, *s)?),
+        ("mov", [Address(a, s), Register(r)]) => Instruction::MovMemReg(to_u32(*a, *s)?, *r),
+        ("add", [Literal(v, s), Register(r)]) => Instruction::AddLitReg(to_i32(*v, *s)?, *r),
+        ("out", [Register(r), Literal(p, s)]) => Instruction::Out(*r, to_u8(*p, *s)?),
+        ("in", [Literal(p, s), Register(r)]) => Instruction::In(to_u8(*p, *s)?, *r),
+        ("push", [Literal(v, s)]) => Instruction::PshLit(to_i32(*v, *s)?),
+        ("push", [Register(r)]) => Instruction::PshReg(*r),
+        ("pop", [Register(r)]) => Instruction::Pop(*r),
+        ("call", [Address(a, s)]) => Instruction::CalLit(to_u32(*a, *s)?),
+        ("call", [Register(r)]) => Instruction::CalReg(*r),
+        ("jne", [Literal(v, vs), Address(a, s)]) => {
+            Instruction::JmpNotEq(to_i32(*v, *vs)?, to_u32(*a, *s)?)
+        }
+        ("jne", [Register(r), Address(a, s)]) => Instruction::JneReg(*r, to_u32(*a, *s)?),
+        ("jeq", [Literal(v, vs), Address(a, s)]) => {
+            Instruction::JeqLit(to_i32(*v, *vs)?, to_u32(*a, *s)?)
+        }
+        ("jeq", [Register(r), Address(a, s)]) => Instruction::JeqReg(*r, to_u32(*a, *s)?),
+        (m, _) if MNEMONICS.contains(&m) => {
+            return InvalidOperands {
+                span,
+                mnemonic: m.to_string(),
+            }
+            .fail()
+        }
+        (m, _) => {
+            return UnknownMnemonic {
+                span,
+                mnemonic: m.to_string(),
+            }
+            .fail()
+        }
+    };
+
+    Ok(ins)
+}
+
+/// Check that a name may be used as a label, which it may not if it would be
+/// read as a register or a literal.
+fn check_label(name: &str, span: Span) -> Result<()> {
+    if register(name).is_some() || lexer::parse_number(name).is_some() {
+        return InvalidLabel { span, name }.fail();
+    }
+
+    Ok(())
+}
+
+/// Replace the value of an operand of an instruction with the address of the
+/// label to which it refers.
+///
+/// # Arguments
+///
+/// * `ins` - the instruction.
+/// * `operand` - the zero-based index of the operand.
+/// * `value` - the value of the operand.
+/// * `span` - the span of the reference.
+pub(crate) fn patch(ins: &mut Instruction, operand: usize, value: i64, span: Span) -> Result<()> {
+    use Instruction::*;
+
+    match (ins, operand) {
+        (MovLitReg(v, _), 0)
+        | (AddLitReg(v, _), 0)
+        | (PshLit(v), 0)
+        | (JmpNotEq(v, _), 0)
+        | (JeqLit(v, _), 0) => *v = to_i32(value, span)?,
+        (MovRegMem(_, a), 1)
+        | (MovMemReg(a, _), 0)
+        | (CalLit(a), 0)
+        | (JmpNotEq(_, a), 1)
+        | (JneReg(_, a), 1)
+        | (JeqLit(_, a), 1)
+        | (JeqReg(_, a), 1) => *a = to_u32(value, span)?,
+        (Out(_, p), 1) | (In(p, _), 0) => *p = to_u8(value, span)?,
+        (ins, operand) => unreachable!("'{}' has no literal operand {}", ins, operand),
+    }
+
+    Ok(())
+}
+
+/// Replace a value of a data statement with the address of the label to which
+/// it refers.
+///
+/// # Arguments
+///
+/// * `bytes` - the bytes of the data statement.
+/// * `width` - the size, in bytes, of each value.
+/// * `index` - the zero-based index of the value.
+/// * `value` - the new value.
+/// * `span` - the span of the reference.
+pub(crate) fn patch_data(
+    bytes: &mut [u8],
+    width: usize,
+    index: usize,
+    value: i64,
+    span: Span,
+) -> Result<()> {
+    let start = index * width;
+    bytes[start..start + width].copy_from_slice(&encode_value(value, width, span)?);
+    Ok(())
+}
+
+/// Encode an integer as little-endian bytes. Values may be either signed or
+/// unsigned, so long as they fit within the width.
+///
+/// # Arguments
+///
+/// * `value` - the value.
+/// * `width` - the size, in bytes, of the encoded value.
+/// * `span` - the span of the value.
+fn encode_value(value: i64, width: usize, span: Span) -> Result<Vec<u8>> {
+    if width < 8 {
+        let bits = width as u32 * 8;
+        if value < -(1 << (bits - 1)) || value >= 1 << bits {
+            return LiteralOutOfRange { span, value }.fail();
+        }
+    }
+
+    Ok(value.to_le_bytes()[..width].to_vec())
+}
+
+/// Convert a literal to a 32-bit integer. Values above the largest signed
+/// integer are taken to be unsigned, as they are displayed.
+fn to_i32(value: i64, span: Span) -> Result<i32> {
+    if value < i32::MIN as i64 || value > u32::MAX as i64 {
+        return LiteralOutOfRange { span, value }.fail();
+    }
+
+    Ok(value as u32 as i32)
+}
+
+fn to_u32(value: i64, span: Span) -> Result<u32> {
+    if value < 0 || value > u32::MAX as i64 {
+        return LiteralOutOfRange { span, value }.fail();
+    }
+
+    Ok(value as u32)
+}
+
+fn to_u8(value: i64, span: Span) -> Result<u8> {
+    if value < 0 || value > u8::MAX as i64 {
+        return LiteralOutOfRange { span, value }.fail();
+    }
+
+    Ok(value as u8)
+}
+
+/// Parse assembly source into statements, returning every error found.
+///
+/// # Arguments
+///
+/// * `source` - the assembly source.
+pub fn parse(source: &str) -> Result<Vec<Statement>, Vec<ParseError>> {
+    let tokens = lexer::tokenize(source).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|e| ParseError::Lex { source: e })
+            .collect::<Vec<_>>()
+    })?;
+
+    let lines = tokens
+        .split(|t| t.kind == TokenKind::Newline)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_vec())
+        .collect();
+    let (lines, mut errors) = macros::expand(lines);
+
+    let mut statements = Vec::new();
+    for line in &lines {
+        let last = &line.tokens[line.tokens.len() - 1];
+        let mut parser = LineParser {
+            tokens: &line.tokens,
+            position: 0,
+            end: Span {
+                start: last.span.end,
+                end: last.span.end,
+                line: last.span.line,
+                column: last.span.column + (last.span.end - last.span.start) as u32,
+            },
+            references: Vec::new(),
+        };
+
+        let first = statements.len();
+        let result = parser.line(&mut statements);
+        for statement in &mut statements[first..] {
+            statement.expansion = line.expansion.clone();
+        }
+        if let Err(e) = result {
+            errors.push(match &line.expansion {
+                Some(expansion) => expansion.wrap(e),
+                None => e,
+            });
+        }
+    }
+
+    errors.sort_by_key(|e| e.span().start);
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_instruction() -> Vec<Instruction> {
+        vec![
+            Instruction::NOP(),
+            Instruction::MovLitReg(0x7B, Registers::R1),
+            Instruction::MovLitReg(-5, Registers::R2),
+            Instruction::MovRegReg(Registers::R1, Registers::R8),
+            Instruction::MovRegMem(Registers::FL, 0xDEAD_BEEF),
+            Instruction::MovMemReg(12, Registers::R2),
+            Instruction::AddLitReg(7, Registers::R3),
+            Instruction::Out(Registers::R5, 0xFE),
+            Instruction::In(0x01, Registers::R6),
+            Instruction::PshLit(-1),
+            Instruction::PshReg(Registers::R4),
+            Instruction::Pop(Registers::R7),
+            Instruction::CalLit(0x100),
+            Instruction::CalReg(Registers::R1),
+            Instruction::Ret(),
+            Instruction::IRet(),
+            Instruction::JmpNotEq(0x10, 0x200),
+            Instruction::JneReg(Registers::R2, 0x300),
+            Instruction::JeqLit(i32::MIN, u32::MAX),
+            Instruction::JeqReg(Registers::AC, 0),
+            Instruction::HLT(),
+        ]
+    }
+
+    #[test]
+    fn displayed_instructions_are_parsed() {
+        // Literals that read as binary or decimal prefixes, or as the name of a register.
+        let mut instructions = every_instruction();
+        instructions.extend(vec![
+            Instruction::MovLitReg(0x0B, Registers::R1),
+            Instruction::AddLitReg(0x0D, Registers::R2),
+            Instruction::JeqLit(0x0D, 0x0B),
+            Instruction::MovLitReg(0xAC, Registers::R1),
+            Instruction::JmpNotEq(0xAC, 0xAC),
+            Instruction::PshLit(0xAC),
+            Instruction::Out(Registers::R1, 0xAC),
+            Instruction::In(0xAC, Registers::R2),
+        ]);
+        let source: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+
+        let parsed: Vec<(u32, Instruction)> = parse(&source.join("\n"))
+            .unwrap()
+            .into_iter()
+            .filter_map(|s| match s.kind {
+                StatementKind::Instruction(ins) => Some((s.span.line, ins)),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<(u32, Instruction)> = instructions
+            .into_iter()
+            .enumerate()
+            .map(|(i, ins)| (i as u32 + 1, ins))
+            .collect();
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn literals_comments_and_spans() {
+        let source =
+            "\n  ; a comment\n  MOV 0d10, r1 ; ten\npush 'A'\nadd -0b11, R2\nout R1, 0xFF\n";
+        let statements = parse(source).unwrap();
+
+        let instructions: Vec<Instruction> = statements
+            .iter()
+            .filter_map(|s| match &s.kind {
+                StatementKind::Instruction(i) => Some(i.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::MovLitReg(10, Registers::R1),
+                Instruction::PshLit('A' as i32),
+                Instruction::AddLitReg(-3, Registers::R2),
+                Instruction::Out(Registers::R1, 0xFF),
+            ]
+        );
+
+        let span = statements[0].span;
+        assert_eq!((span.line, span.column), (3, 3));
+        assert_eq!(&source[span.start..span.end], "MOV 0d10, r1");
+    }
+
+    #[test]
+    fn errors_are_reported_for_each_line() {
+        let source = "jmp [10]\nmov [10], [20]\nout R1, 100\nadd 1 R1\npush\nmov 1,\nnop\n.align 3";
+        let errors: Vec<String> = parse(source)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "1:1: unknown mnemonic 'jmp'",
+                "2:1: invalid operands for 'mov'",
+                "3:9: the value 256 is out of range",
+                "4:7: expected ',', but found 'R1'",
+                "5:1: invalid operands for 'push'",
+                "6:7: expected a literal, but found the end of the line",
+                "8:8: the alignment 3 is not a power of two",
+            ]
+        );
+    }
+}
@@ -0,0 +1,574 @@
+//! Assembles source into a program, resolving the labels to which it refers.
+//!
+//! Statements are placed into either the text section, which holds the
+//! instructions, or the data section, according to the most recent `.text` or
+//! `.data` directive. The text section begins at the base address of the program
+//! and the data section at the next multiple of four bytes after the end of the
+//! text section, unless either is moved by `.org`. A `.org` within the data
+//! section starts a new block of data, whereas within the text section it may only
+//! move forward, with the gap filled by zeros.
+//!
+//! The first pass assigns an address to every statement and, from those, to every
+//! label and subroutine. The second pass replaces each reference to a label with
+//! the address of the label, so a label may be used before it is declared.
+
+use crate::lexer::Span;
+use crate::parser::{self, ParseError, Segment, Statement, StatementKind};
+use oxidation_core::debug_info::DebugInfo;
+use oxidation_core::executable::{Executable, Section};
+use oxidation_core::instructions::enums::Instruction;
+use oxidation_core::memory::MemoryAccess;
+use oxidation_core::symbols::SymbolTable;
+use std::collections::HashMap;
+
+/// The alignment of the default start of the data section.
+const DATA_ALIGNMENT: u32 = 4;
+
+/// A program assembled from a single source file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    /// The address of the text section, at which execution begins.
+    pub text_address: u32,
+    /// The contents of the text section.
+    pub text: Vec<u8>,
+    /// The blocks of the data section, each paired with its address.
+    pub data: Vec<(u32, Vec<u8>)>,
+    /// The instructions of the program, each paired with its address.
+    pub instructions: Vec<(u32, Instruction)>,
+    /// The one-based source line of each instruction, paired with its address. The
+    /// instructions produced by a macro belong to the line of its invocation.
+    pub lines: Vec<(u32, u32)>,
+    /// The labels declared within the source.
+    pub labels: SymbolTable,
+    /// The subroutines declared within the source.
+    pub subroutines: SymbolTable,
+}
+
+impl Program {
+    /// Returns the symbols of the program, which are its labels and subroutines.
+    /// Where a label and a subroutine share an address, the subroutine is used.
+    pub fn symbols(&self) -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+        for (address, name) in self.labels.iter().chain(self.subroutines.iter()) {
+            symbols.insert(address, name.to_string());
+        }
+
+        symbols
+    }
+
+    /// Build an executable holding the program, along with its symbols and
+    /// debug information. Blocks of data that hold only zeros are stored as
+    /// zero-filled sections.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - the path of the source file.
+    pub fn executable(&self, file: &str) -> Executable {
+        let mut executable = Executable::new(Section::code(self.text_address, self.text.clone()));
+        for (address, bytes) in &self.data {
+            let access = MemoryAccess::R | MemoryAccess::W;
+            let section = if bytes.iter().all(|b| *b == 0) {
+                Section::zero(*address, bytes.len() as u32, access)
+            } else {
+                Section::data(*address, bytes.clone(), access)
+            };
+            executable.sections.push(section);
+        }
+
+        let mut debug_info = DebugInfo::new();
+        for (address, line) in &self.lines {
+            debug_info.lines.insert(*address, file, *line);
+        }
+        debug_info.labels = self.labels.clone();
+        debug_info.subroutines = self.subroutines.clone();
+
+        executable.symbols = self.symbols();
+        executable.debug_info = Some(debug_info);
+        executable
+    }
+}
+
+/// The addresses of the labels and subroutines, along with the lines on which
+/// they were declared.
+type Symbols<'a> = HashMap<&'a str, (u32, u32)>;
+
+/// The addresses assigned to the statements of a section.
+struct Placement<'a> {
+    /// Each statement, paired with its address.
+    statements: Vec<(u32, &'a Statement)>,
+    /// The address at which the section begins.
+    start: u32,
+    /// The address following the end of the section.
+    end: u32,
+}
+
+/// Returns the number of bytes that a statement adds to its section.
+fn size(kind: &StatementKind) -> u32 {
+    match kind {
+        StatementKind::Instruction(ins) => ins.size(),
+        StatementKind::Data { bytes, .. } => bytes.len() as u32,
+        StatementKind::Zero(size) => *size,
+        _ => 0,
+    }
+}
+
+/// Assign an address to each statement of a section, declaring the labels and
+/// subroutines found within it.
+///
+/// # Arguments
+///
+/// * `statements` - the statements of the section.
+/// * `segment` - the section.
+/// * `start` - the default address of the start of the section.
+/// * `program` - the program, into which the labels and subroutines are declared.
+/// * `symbols` - the addresses of the labels and subroutines declared so far.
+/// * `errors` - the errors found so far.
+fn place<'a>(
+    statements: &[&'a Statement],
+    segment: Segment,
+    start: u32,
+    program: &mut Program,
+    symbols: &mut Symbols<'a>,
+    errors: &mut Vec<ParseError>,
+) -> Placement<'a> {
+    let mut placement = Placement {
+        statements: Vec::new(),
+        start,
+        end: start,
+    };
+    let mut address = start;
+    let mut empty = true;
+
+    for &statement in statements {
+        match &statement.kind {
+            StatementKind::Label(name) | StatementKind::Subroutine(name) => {
+                match symbols.get(name.as_str()) {
+                    Some((_, line)) => errors.push(statement.wrap(ParseError::DuplicateLabel {
+                        span: statement.span,
+                        name: name.clone(),
+                        previous: *line,
+                    })),
+                    None => {
+                        symbols.insert(name, (address, statement.line()));
+                        let table = match statement.kind {
+                            StatementKind::Label(_) => &mut program.labels,
+                            _ => &mut program.subroutines,
+                        };
+                        table.insert(address, name.clone());
+                    }
+                }
+            }
+            StatementKind::Org(origin) => {
+                if segment == Segment::Text && !empty && *origin < address {
+                    errors.push(statement.wrap(ParseError::OriginBehind {
+                        span: statement.span,
+                        origin: *origin,
+                        address,
+                    }));
+                    continue;
+                }
+                if empty {
+                    placement.start = *origin;
+                }
+                address = *origin;
+                placement.statements.push((address, statement));
+            }
+            StatementKind::Align(alignment) => {
+                let padding = address.wrapping_neg() & (alignment - 1);
+                address = address.saturating_add(padding);
+            }
+            kind => {
+                let size = size(kind);
+                if size > 0 {
+                    empty = false;
+                }
+                match address.checked_add(size) {
+                    Some(end) => {
+                        placement.statements.push((address, statement));
+                        address = end;
+                    }
+                    None => errors.push(statement.wrap(ParseError::AddressOverflow {
+                        span: statement.span,
+                    })),
+                }
+            }
+        }
+
+        // The data section ends at its highest address, as it may be moved backwards.
+        placement.end = match segment {
+            Segment::Text => address,
+            Segment::Data => placement.end.max(address),
+        };
+    }
+
+    placement
+}
+
+/// Returns the kind of a statement, with its references to labels resolved.
+fn resolve(statement: &Statement, symbols: &Symbols) -> Result<StatementKind, ParseError> {
+    let mut kind = statement.kind.clone();
+    for reference in &statement.references {
+        let value = match symbols.get(reference.name.as_str()) {
+            Some((address, _)) => *address as i64,
+            None => {
+                return Err(ParseError::UndefinedLabel {
+                    span: reference.span,
+                    name: reference.name.clone(),
+                })
+            }
+        };
+
+        match &mut kind {
+            StatementKind::Instruction(ins) => {
+                parser::patch(ins, reference.operand, value, reference.span)?
+            }
+            StatementKind::Data { bytes, width } => {
+                parser::patch_data(bytes, *width, reference.operand, value, reference.span)?
+            }
+            _ => {}
+        }
+    }
+
+    Ok(kind)
+}
+
+/// Returns the bytes that a statement adds to its section.
+fn contents(kind: &StatementKind) -> Vec<u8> {
+    match kind {
+        StatementKind::Instruction(ins) => ins.encode(),
+        StatementKind::Data { bytes, .. } => bytes.clone(),
+        StatementKind::Zero(size) => vec![0; *size as usize],
+        _ => Vec::new(),
+    }
+}
+
+/// Assemble source into a program, returning every error found, ordered by its
+/// position within the source.
+///
+/// # Arguments
+///
+/// * `source` - the assembly source.
+/// * `base` - the default address of the text section, relative to which the
+///   addresses of labels are given.
+pub fn assemble(source: &str, base: u32) -> Result<Program, Vec<ParseError>> {
+    let statements = parser::parse(source)?;
+
+    let mut program = Program {
+        text_address: base,
+        text: Vec::new(),
+        data: Vec::new(),
+        instructions: Vec::new(),
+        lines: Vec::new(),
+        labels: SymbolTable::new(),
+        subroutines: SymbolTable::new(),
+    };
+    let mut errors = Vec::new();
+
+    let mut text = Vec::new();
+    let mut data = Vec::new();
+    let mut segment = Segment::Text;
+    for statement in &statements {
+        match (&statement.kind, segment) {
+            (StatementKind::Segment(s), _) => segment = *s,
+            (StatementKind::Instruction(_), Segment::Data) => {
+                errors.push(statement.wrap(ParseError::InstructionOutsideText {
+                    span: statement.span,
+                }))
+            }
+            (_, Segment::Text) => text.push(statement),
+            (_, Segment::Data) => data.push(statement),
+        }
+    }
+
+    // The first pass, in which the addresses of the labels are found.
+    let mut symbols = Symbols::new();
+    let text = place(
+        &text,
+        Segment::Text,
+        base,
+        &mut program,
+        &mut symbols,
+        &mut errors,
+    );
+    let data_start = text.end.saturating_add(DATA_ALIGNMENT - 1) & !(DATA_ALIGNMENT - 1);
+    let data = place(
+        &data,
+        Segment::Data,
+        data_start,
+        &mut program,
+        &mut symbols,
+        &mut errors,
+    );
+
+    // An executable requires a code section, which cannot be empty.
+    if text.statements.iter().all(|(_, s)| size(&s.kind) == 0) {
+        let span = statements.first().map(|s| s.span).unwrap_or(Span {
+            line: 1,
+            column: 1,
+            ..Span::default()
+        });
+        errors.push(ParseError::EmptyText { span });
+    }
+
+    // The second pass, in which the references to the labels are resolved.
+    program.text_address = text.start;
+    for (address, statement) in text.statements {
+        let kind = match resolve(statement, &symbols) {
+            Ok(kind) => kind,
+            Err(e) => {
+                errors.push(statement.wrap(e));
+                continue;
+            }
+        };
+        if let StatementKind::Org(_) = kind {
+            continue;
+        }
+
+        program.text.resize((address - text.start) as usize, 0);
+        program.text.extend(contents(&kind));
+        if let StatementKind::Instruction(ins) = kind {
+            program.instructions.push((address, ins));
+            program.lines.push((address, statement.line()));
+        }
+    }
+
+    // Each origin within the data section starts a new block.
+    let mut blocks: Vec<(u32, Vec<u8>, &Statement)> = Vec::new();
+    let mut new_block = true;
+    for (address, statement) in data.statements {
+        let kind = match resolve(statement, &symbols) {
+            Ok(kind) => kind,
+            Err(e) => {
+                errors.push(statement.wrap(e));
+                continue;
+            }
+        };
+
+        match (kind, blocks.last_mut()) {
+            (StatementKind::Org(_), _) => new_block = true,
+            (kind, Some((start, block, _))) if !new_block => {
+                block.resize((address - *start) as usize, 0);
+                block.extend(contents(&kind));
+            }
+            (kind, _) => {
+                blocks.push((address, contents(&kind), statement));
+                new_block = false;
+            }
+        }
+    }
+
+    let text_end = text.start as u64 + program.text.len() as u64;
+    for (i, (address, bytes, statement)) in blocks.iter().enumerate() {
+        let (start, end) = (*address as u64, *address as u64 + bytes.len() as u64);
+        let overlapping = (start < text_end && (text.start as u64) < end)
+            || blocks[..i]
+                .iter()
+                .any(|(a, b, _)| start < *a as u64 + b.len() as u64 && (*a as u64) < end);
+        if overlapping && !bytes.is_empty() {
+            errors.push(statement.wrap(ParseError::OverlappingData {
+                span: statement.span,
+            }));
+        }
+    }
+    program.data = blocks
+        .into_iter()
+        .filter(|(_, b, _)| !b.is_empty())
+        .map(|(a, b, _)| (a, b))
+        .collect();
+
+    if errors.is_empty() {
+        Ok(program)
+    } else {
+        errors.sort_by_key(|e| e.span().start);
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidation_core::execution::RunOutcome;
+    use oxidation_core::registers::Registers;
+    use oxidation_core::security_context::SecurityContext;
+    use oxidation_core::virtual_machine::VirtualMachine;
+
+    #[test]
+    fn references_are_resolved() {
+        let source = "\
+.sub main
+    call [count]
+    hlt
+.sub count
+    mov 3, R1
+loop: add -1, R1
+    mov AC, R1
+    jne 0, [loop]
+    push end
+    ret
+end:
+";
+        let program = assemble(source, 0x100).unwrap();
+
+        let instructions: Vec<Instruction> = program
+            .instructions
+            .iter()
+            .map(|(_, i)| i.clone())
+            .collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::CalLit(0x108),
+                Instruction::HLT(),
+                Instruction::MovLitReg(3, Registers::R1),
+                Instruction::AddLitReg(-1, Registers::R1),
+                Instruction::MovRegReg(Registers::AC, Registers::R1),
+                Instruction::JmpNotEq(0, 0x10F),
+                Instruction::PshLit(0x12C),
+                Instruction::Ret(),
+            ]
+        );
+        assert_eq!(program.lines[3], (0x10F, 6));
+
+        assert_eq!(program.subroutines.address_of("count"), Some(0x108));
+        assert_eq!(program.labels.address_of("loop"), Some(0x10F));
+        assert_eq!(program.labels.address_of("end"), Some(0x12C));
+
+        let executable = program.executable("count.asm");
+        assert_eq!(executable.symbols.address_of("main"), Some(0x100));
+        let code = &executable.sections[0];
+        let location = executable
+            .debug_info
+            .as_ref()
+            .unwrap()
+            .locate(0x111, &(code.address..=code.end()));
+        assert_eq!(location.to_string(), "count+0x9 (count.asm:6)");
+    }
+
+    #[test]
+    fn label_errors_are_reported() {
+        let source = "\
+start:
+    jne 0, [missing]
+start: nop
+.sub R1
+beef:
+    out R1, start
+    call [start]
+";
+        let errors: Vec<String> = assemble(source, 0)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "4:6: 'R1' cannot be used as a label",
+                "5:1: 'beef' cannot be used as a label",
+            ]
+        );
+
+        let errors: Vec<String> = assemble(&source.replace("R1\nbeef", "setup\nlate"), 0x1000)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                "2:13: the label 'missing' is not defined",
+                "3:1: the label 'start' is already defined on line 1",
+                "6:13: the value 4096 is out of range",
+            ]
+        );
+    }
+
+    #[test]
+    fn programs_without_code_are_rejected() {
+        for (source, expected) in [
+            (".data\nx: .byte 1\n", "1:1"),
+            ("", "1:1"),
+            ("; nothing\n\nstart:\n", "3:1"),
+        ]
+        .iter()
+        {
+            let errors: Vec<String> = assemble(source, 0)
+                .unwrap_err()
+                .iter()
+                .map(|e| e.to_string())
+                .collect();
+
+            assert_eq!(
+                errors,
+                vec![format!(
+                    "{}: the text section is empty, but a program requires code",
+                    expected
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn data_is_placed_into_sections() {
+        let source = r#"
+.data
+message: .string "hi"
+.align 4
+table:   .i32 message, -1
+         .byte 1, FF
+         .f32 1.5
+.org 2000
+buffer:  .zero 8
+
+.text
+.org 100
+.sub main
+    mov [table], R1
+    mov R1, [buffer]
+    hlt
+"#;
+        let program = assemble(source, 0).unwrap();
+
+        assert_eq!(program.text_address, 0x100);
+        assert_eq!(
+            program.instructions,
+            vec![
+                (0x100, Instruction::MovMemReg(0x114, Registers::R1)),
+                (0x107, Instruction::MovRegMem(Registers::R1, 0x2000)),
+                (0x10E, Instruction::HLT()),
+            ]
+        );
+
+        let mut data = b"hi\0\0".to_vec();
+        data.extend_from_slice(&0x110u32.to_le_bytes());
+        data.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 1, 0xFF]);
+        data.extend_from_slice(&1.5f32.to_le_bytes());
+        assert_eq!(program.data, vec![(0x110, data), (0x2000, vec![0; 8])]);
+        assert_eq!(program.labels.address_of("buffer"), Some(0x2000));
+
+        let mut vm = VirtualMachine::new(0x3000, 10, false);
+        vm.load_executable(&program.executable("data.asm")).unwrap();
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+        assert_eq!(
+            vm.memory.read_i32(0x2000, SecurityContext::User).unwrap(),
+            0x110
+        );
+
+        let errors: Vec<String> =
+            assemble(".text\nnop\n.org 0\n.data\nnop\n.org 11\n.byte 10", 0x10)
+                .unwrap_err()
+                .iter()
+                .map(|e| e.to_string())
+                .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "3:1: the origin 0x00000000 lies before the current address 0x00000012",
+                "5:1: instructions may only be placed in the text section",
+                "7:1: the data overlaps other data or instructions",
+            ]
+        );
+    }
+}
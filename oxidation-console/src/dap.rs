@@ -0,0 +1,868 @@
+use crate::debugger::{format_access, format_value};
+use oxidation_core::instructions::enums::Instruction;
+use oxidation_core::security_context::SecurityContext;
+use oxidation_core::source_map::SourceMap;
+use oxidation_core::symbols::SymbolTable;
+use oxidation_core::virtual_machine::VirtualMachine;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+type RequestResult = Result<Value, String>;
+
+/// The only thread of execution.
+const THREAD_ID: i64 = 1;
+
+/// The variables reference of the register scope.
+const REGISTERS_REFERENCE: u64 = 1;
+
+/// The variables reference of the memory scope.
+const MEMORY_REFERENCE: u64 = 2;
+
+/// The variables reference of the first memory region. Each region is assigned
+/// a reference equal to this value plus its index.
+const REGION_REFERENCE_BASE: u64 = 1_000;
+
+/// The number of bytes shown in each row of a memory region.
+const ROW_SIZE: u32 = 16;
+
+/// The number of instructions executed between checks for a request to pause or disconnect.
+const RESUME_SLICE: u32 = 10_000;
+
+/// How far execution should proceed before stopping.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Resume {
+    /// Run until a breakpoint is hit or the program halts.
+    Continue,
+    /// Execute a single instruction.
+    StepIn,
+    /// Execute a single instruction, running any subroutine it calls to completion.
+    StepOver,
+    /// Run until the current subroutine returns.
+    StepOut,
+}
+
+/// The progress of a program which has been resumed but has not yet stopped.
+#[derive(Debug, Copy, Clone)]
+struct Running {
+    resume: Resume,
+    /// The number of active subroutines when execution was resumed.
+    depth: usize,
+    /// Whether the next instruction is the first since execution was resumed.
+    first: bool,
+}
+
+/// A program launched by the adapter.
+struct Session {
+    vm: VirtualMachine,
+    /// The line table of the program, taken from its debug information.
+    lines: SourceMap,
+    symbols: SymbolTable,
+    /// The addresses of the breakpoints set within each source, keyed by its path.
+    sources: BTreeMap<String, Vec<u32>>,
+    /// The addresses of the breakpoints set within every source.
+    breakpoints: BTreeSet<u32>,
+    /// The addresses of the call instructions of the active subroutines, outermost first.
+    frames: Vec<u32>,
+    stop_on_entry: bool,
+    running: Option<Running>,
+}
+
+/// A Debug Adapter Protocol server, allowing a virtual machine to be debugged from an editor.
+pub struct Adapter {
+    session: Option<Session>,
+    seq: i64,
+    events: Vec<Value>,
+}
+
+impl Adapter {
+    pub fn new() -> Self {
+        Self {
+            session: None,
+            seq: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Serve requests until the input is exhausted or the client disconnects.
+    ///
+    /// While the program runs, it is executed in slices, between which any request to
+    /// pause it or disconnect is handled. Other requests are answered once it stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - the source of the requests.
+    /// * `output` - the destination of the responses and events.
+    pub fn run<R>(&mut self, input: R, output: &mut dyn Write) -> io::Result<()>
+    where
+        R: BufRead + Send + 'static,
+    {
+        let requests = spawn_reader(input);
+        let mut deferred = VecDeque::new();
+        let mut exhausted = false;
+
+        loop {
+            if exhausted && deferred.is_empty() {
+                break;
+            }
+
+            let received = if exhausted {
+                None
+            } else if self.is_running() {
+                match requests.try_recv() {
+                    Ok(message) => Some(message?),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => Some(None),
+                }
+            } else if deferred.is_empty() {
+                Some(requests.recv().unwrap_or(Ok(None))?)
+            } else {
+                None
+            };
+
+            let request = match received {
+                Some(Some(request)) => request,
+                Some(None) => {
+                    exhausted = true;
+                    continue;
+                }
+                None if self.is_running() => {
+                    let events = self.session.as_mut().unwrap().advance();
+                    self.events.extend(events);
+                    self.write_events(output)?;
+                    continue;
+                }
+                None => deferred.pop_front().unwrap(),
+            };
+
+            let command = request["command"].as_str().unwrap_or_default().to_string();
+            let arguments = &request["arguments"];
+
+            // Requests are answered in order, except a pause, which must reach a running program.
+            let interrupts = match command.as_str() {
+                "pause" => true,
+                "disconnect" | "threads" | "setBreakpoints" => deferred.is_empty(),
+                _ => false,
+            };
+            if self.is_running() && !interrupts {
+                deferred.push_back(request);
+                continue;
+            }
+
+            let result = self.dispatch(&command, arguments);
+            let mut response = json!({
+                "seq": self.next_seq(),
+                "type": "response",
+                "request_seq": request["seq"],
+                "command": command,
+                "success": result.is_ok(),
+            });
+            match result {
+                Ok(body) => response["body"] = body,
+                Err(message) => response["message"] = Value::String(message),
+            }
+            write_message(output, &response)?;
+
+            // Events are sent once the request that raised them has been answered.
+            self.write_events(output)?;
+
+            if command == "disconnect" {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.session.as_ref().is_some_and(|s| s.running.is_some())
+    }
+
+    fn write_events(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        for mut event in std::mem::take(&mut self.events) {
+            event["seq"] = json!(self.next_seq());
+            write_message(output, &event)?;
+        }
+
+        Ok(())
+    }
+
+    fn dispatch(&mut self, command: &str, args: &Value) -> RequestResult {
+        match command {
+            "initialize" => {
+                self.event("initialized", json!({}));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                }))
+            }
+            "launch" => self.launch(args),
+            "disconnect" => {
+                self.session = None;
+                Ok(json!({}))
+            }
+            _ => self.dispatch_session(command, args),
+        }
+    }
+
+    fn dispatch_session(&mut self, command: &str, args: &Value) -> RequestResult {
+        let session = self
+            .session
+            .as_mut()
+            .ok_or_else(|| format!("the '{}' request requires a launched program", command))?;
+
+        let (body, events) = match command {
+            "setBreakpoints" => (session.set_breakpoints(args), vec![]),
+            "configurationDone" => {
+                let events = if session.stop_on_entry {
+                    vec![stopped("entry", None)]
+                } else {
+                    session.resume(Resume::Continue);
+                    vec![]
+                };
+                (json!({}), events)
+            }
+            "threads" => (
+                json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+                vec![],
+            ),
+            "stackTrace" => (session.stack_trace(), vec![]),
+            "scopes" => (
+                json!({
+                    "scopes": [
+                        {
+                            "name": "Registers",
+                            "variablesReference": REGISTERS_REFERENCE,
+                            "expensive": false,
+                        },
+                        {
+                            "name": "Memory",
+                            "variablesReference": MEMORY_REFERENCE,
+                            "expensive": true,
+                        },
+                    ]
+                }),
+                vec![],
+            ),
+            "variables" => (session.variables(args)?, vec![]),
+            "continue" => {
+                session.resume(Resume::Continue);
+                (json!({ "allThreadsContinued": true }), vec![])
+            }
+            "next" => {
+                session.resume(Resume::StepOver);
+                (json!({}), vec![])
+            }
+            "stepIn" => {
+                session.resume(Resume::StepIn);
+                (json!({}), vec![])
+            }
+            "stepOut" => {
+                session.resume(Resume::StepOut);
+                (json!({}), vec![])
+            }
+            "pause" => (json!({}), session.pause()),
+            _ => return Err(format!("the '{}' request is not supported", command)),
+        };
+
+        self.events.extend(events);
+        Ok(body)
+    }
+
+    fn launch(&mut self, args: &Value) -> RequestResult {
+        let program_path = args["program"]
+            .as_str()
+            .ok_or("the launch configuration must specify a 'program'")?;
+        let program = fs::read(program_path)
+            .map_err(|e| format!("failed to read the program '{}': {}", program_path, e))?;
+
+        let symbols = match args["labels"].as_str() {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("failed to read the labels '{}': {}", path, e))?;
+                Some(crate::parse_symbols(&text)?)
+            }
+            None => None,
+        };
+
+        let mut vm = VirtualMachine::new(64_000, 100, false);
+        let embedded = crate::load_program(&mut vm, &program)?;
+        let symbols = symbols.unwrap_or(embedded);
+        let lines = vm
+            .debug_info()
+            .map(|info| info.lines.clone())
+            .unwrap_or_default();
+
+        self.session = Some(Session {
+            vm,
+            lines,
+            symbols,
+            sources: BTreeMap::new(),
+            breakpoints: BTreeSet::new(),
+            frames: Vec::new(),
+            stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false),
+            running: None,
+        });
+
+        Ok(json!({}))
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.events.push(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+}
+
+impl Session {
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        // The requested breakpoints replace those previously set within the same source.
+        let source = args["source"]["path"].as_str().unwrap_or_default();
+        let mut addresses = Vec::new();
+
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let mut breakpoints = Vec::new();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as u32;
+            let address = self
+                .lines
+                .iter()
+                .find(|(_, location)| location.line == line && is_same_file(source, &location.file))
+                .map(|(address, _)| address);
+
+            match address {
+                Some(address) => {
+                    addresses.push(address);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format!("{:#010X}", address),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no instruction was assembled from this line",
+                })),
+            }
+        }
+
+        self.sources.insert(source.to_string(), addresses);
+        self.breakpoints = self.sources.values().flatten().copied().collect();
+
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// Resume execution. The program runs as the adapter calls [`Session::advance`].
+    fn resume(&mut self, resume: Resume) {
+        self.running = Some(Running {
+            resume,
+            depth: self.frames.len(),
+            first: true,
+        });
+    }
+
+    /// Stop a running program, returning the events reporting the stop.
+    fn pause(&mut self) -> Vec<Value> {
+        match self.running.take() {
+            Some(_) => vec![stopped("pause", None)],
+            None => vec![],
+        }
+    }
+
+    /// Execute the next slice of a running program, returning the events reporting
+    /// its stop, if it stopped.
+    fn advance(&mut self) -> Vec<Value> {
+        let mut running = match self.running.take() {
+            Some(running) => running,
+            None => return vec![],
+        };
+
+        for _ in 0..RESUME_SLICE {
+            if let Some(events) = self.execute(&mut running) {
+                return events;
+            }
+        }
+
+        self.running = Some(running);
+        vec![]
+    }
+
+    /// Execute a single instruction, returning the events reporting a stop, if any.
+    fn execute(&mut self, running: &mut Running) -> Option<Vec<Value>> {
+        if self.vm.cpu.is_halted() {
+            return Some(exited());
+        }
+
+        let ip = self.vm.cpu.get_instruction_pointer().unwrap_or_default();
+        if !running.first && self.breakpoints.contains(&ip) {
+            return Some(vec![stopped("breakpoint", None)]);
+        }
+        running.first = false;
+
+        let outcome = self.vm.step();
+        if let Some(e) = outcome.fault {
+            return Some(vec![stopped("exception", Some(e.to_string()))]);
+        }
+        if outcome.halted {
+            return Some(exited());
+        }
+
+        match outcome.instruction {
+            Some(Instruction::CalLit(_)) | Some(Instruction::CalReg(_)) => {
+                self.frames.push(outcome.address)
+            }
+            Some(Instruction::Ret()) => {
+                self.frames.pop();
+            }
+            _ => {}
+        }
+
+        if let Some(hit) = &outcome.watchpoint {
+            let text = format!("watchpoint {} triggered", hit.id);
+            return Some(vec![stopped("data breakpoint", Some(text))]);
+        }
+
+        let done = match running.resume {
+            Resume::Continue => false,
+            Resume::StepIn => true,
+            Resume::StepOver => self.frames.len() <= running.depth,
+            Resume::StepOut => self.frames.len() < running.depth,
+        };
+        if done {
+            return Some(vec![stopped("step", None)]);
+        }
+
+        None
+    }
+
+    fn stack_trace(&self) -> Value {
+        let ip = self.vm.cpu.get_instruction_pointer().unwrap_or_default();
+
+        // The innermost frame is at the current instruction, and each
+        // of the outer frames is at the call that entered the next.
+        let addresses = std::iter::once(ip).chain(self.frames.iter().rev().copied());
+        let frames: Vec<Value> = addresses
+            .enumerate()
+            .map(|(id, address)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": self.symbols.describe(address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#010X}", address),
+                });
+
+                if let Some(location) = self.lines.get(address) {
+                    frame["line"] = json!(location.line);
+                    frame["column"] = json!(1);
+                    frame["source"] = json!({ "path": location.file });
+                }
+
+                frame
+            })
+            .collect();
+
+        json!({
+            "stackFrames": frames,
+            "totalFrames": frames.len(),
+        })
+    }
+
+    fn variables(&self, args: &Value) -> RequestResult {
+        let reference = args["variablesReference"].as_u64().unwrap_or_default();
+
+        let variables = match reference {
+            REGISTERS_REFERENCE => self.registers(),
+            MEMORY_REFERENCE => self.regions(),
+            r if r >= REGION_REFERENCE_BASE => {
+                let start = args["start"].as_u64().unwrap_or(0) as u32;
+                let count = args["count"].as_u64().map(|c| c as u32);
+                self.region_rows((r - REGION_REFERENCE_BASE) as usize, start, count)?
+            }
+            _ => return Err(format!("{} is not a valid variables reference", reference)),
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn registers(&self) -> Vec<Value> {
+        self.vm
+            .cpu
+            .registers
+            .registers
+            .iter()
+            .map(|register| {
+                let value = match register.get_value(SecurityContext::System) {
+                    Ok(v) => format_value(v),
+                    Err(_) => "<inaccessible>".to_string(),
+                };
+
+                json!({
+                    "name": register.register_id.to_string(),
+                    "value": value,
+                    "variablesReference": 0,
+                })
+            })
+            .collect()
+    }
+
+    fn regions(&self) -> Vec<Value> {
+        self.vm
+            .memory
+            .regions()
+            .iter()
+            .enumerate()
+            .map(|(i, region)| {
+                json!({
+                    "name": region.name,
+                    "value": format!(
+                        "{:#010X}..={:#010X} ({})",
+                        region.start,
+                        region.end,
+                        format_access(region.access)
+                    ),
+                    "variablesReference": REGION_REFERENCE_BASE + i as u64,
+                    "indexedVariables": region_rows(region.start, region.end),
+                })
+            })
+            .collect()
+    }
+
+    fn region_rows(
+        &self,
+        index: usize,
+        start: u32,
+        count: Option<u32>,
+    ) -> Result<Vec<Value>, String> {
+        let region = self
+            .vm
+            .memory
+            .regions()
+            .get(index)
+            .ok_or_else(|| format!("there is no memory region with the index {}", index))?;
+
+        let total = region_rows(region.start, region.end);
+        let end = match count {
+            Some(count) => start.saturating_add(count).min(total),
+            None => total,
+        };
+
+        (start..end)
+            .map(|row| {
+                let address = region.start + row * ROW_SIZE;
+                let len = ROW_SIZE.min(region.end - address + 1);
+                let bytes = self
+                    .vm
+                    .memory
+                    .read_bytes(address, len, SecurityContext::System)
+                    .map_err(|e| e.to_string())?;
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+
+                Ok(json!({
+                    "name": format!("{:#010X}", address),
+                    "value": hex.join(" "),
+                    "variablesReference": 0,
+                    "memoryReference": format!("{:#010X}", address),
+                }))
+            })
+            .collect()
+    }
+}
+
+/// Returns the number of rows needed to show the bytes of a memory region.
+fn region_rows(start: u32, end: u32) -> u32 {
+    ((end - start) / ROW_SIZE) + 1
+}
+
+fn stopped(reason: &str, text: Option<String>) -> Value {
+    let mut body = json!({
+        "reason": reason,
+        "threadId": THREAD_ID,
+        "allThreadsStopped": true,
+    });
+    if let Some(text) = text {
+        body["text"] = json!(text);
+    }
+
+    json!({ "type": "event", "event": "stopped", "body": body })
+}
+
+fn exited() -> Vec<Value> {
+    vec![
+        json!({ "type": "event", "event": "exited", "body": { "exitCode": 0 } }),
+        json!({ "type": "event", "event": "terminated", "body": {} }),
+    ]
+}
+
+/// Returns true if a path given by the client refers to a source file named in the
+/// line table, which may be relative to the directory in which it was assembled.
+///
+/// # Arguments
+///
+/// * `requested` - the path given by the client.
+/// * `recorded` - the path named in the line table.
+fn is_same_file(requested: &str, recorded: &str) -> bool {
+    Path::new(requested).ends_with(recorded)
+}
+
+/// Read messages on a separate thread, so that they may be received while the program runs.
+/// The last message received is `None` once the input is exhausted, or an error.
+fn spawn_reader<R>(mut input: R) -> Receiver<io::Result<Option<Value>>>
+where
+    R: BufRead + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || loop {
+        let message = read_message(&mut input);
+        let last = !matches!(message, Ok(Some(_)));
+        if sender.send(message).is_err() || last {
+            break;
+        }
+    });
+
+    receiver
+}
+
+/// Read a message, returning `None` once the input is exhausted.
+fn read_message(input: &mut dyn BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    // The length is not trusted, so the body is read into a buffer that grows as it is filled.
+    let length = length.unwrap_or_default();
+    let mut body = Vec::new();
+    input.take(length as u64).read_to_end(&mut body)?;
+    if body.len() != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut dyn Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxidation_assembler::program::assemble;
+    use std::env;
+    use std::io::Cursor;
+    use std::process;
+
+    fn request(seq: i64, command: &str, arguments: Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let message = json!({
+            "seq": seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        write_message(&mut bytes, &message).unwrap();
+        bytes
+    }
+
+    /// Serve a script of requests, returning every message sent by the adapter.
+    fn exchange(script: Vec<u8>) -> Vec<Value> {
+        let mut output = Vec::new();
+        Adapter::new()
+            .run(Cursor::new(script), &mut output)
+            .unwrap();
+
+        let mut messages = Vec::new();
+        let mut reader = output.as_slice();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message);
+        }
+
+        messages
+    }
+
+    #[test]
+    fn dap_session() {
+        let source = "mov 5, R1\ncall [sub]\nhlt\n.sub sub\nadd 1, R1\nret\n";
+        let program = assemble(source, 0).unwrap();
+
+        let dir = env::temp_dir().join(format!("oxidation-dap-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let program_path = dir.join("program.oxe");
+        program
+            .executable("program.asm")
+            .save(&program_path)
+            .unwrap();
+
+        let script = [
+            request(1, "initialize", json!({ "adapterID": "oxidation" })),
+            request(2, "launch", json!({ "program": program_path })),
+            request(
+                3,
+                "setBreakpoints",
+                json!({
+                    "source": { "path": "program.asm" },
+                    "breakpoints": [{ "line": 5 }, { "line": 4 }],
+                }),
+            ),
+            request(4, "configurationDone", json!({})),
+            request(5, "stackTrace", json!({ "threadId": 1 })),
+            request(6, "variables", json!({ "variablesReference": 1 })),
+            request(7, "variables", json!({ "variablesReference": 2 })),
+            request(
+                8,
+                "variables",
+                json!({ "variablesReference": 1_000, "start": 0, "count": 2 }),
+            ),
+            request(9, "stepOut", json!({ "threadId": 1 })),
+            request(10, "stackTrace", json!({ "threadId": 1 })),
+            request(11, "continue", json!({ "threadId": 1 })),
+            request(12, "disconnect", json!({})),
+        ]
+        .concat();
+
+        let messages = exchange(script);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Every request succeeds.
+        let responses: Vec<&Value> = messages
+            .iter()
+            .filter(|m| m["type"] == "response")
+            .collect();
+        assert_eq!(responses.len(), 12);
+        assert!(responses.iter().all(|r| r["success"] == true));
+
+        let events: Vec<&str> = messages
+            .iter()
+            .filter(|m| m["type"] == "event")
+            .map(|m| m["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            ["initialized", "stopped", "stopped", "exited", "terminated"]
+        );
+
+        // Line 4 holds no instruction, so its breakpoint cannot be verified.
+        let breakpoints = &responses[2]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[1]["verified"], false);
+
+        // The breakpoint within the subroutine is hit from its caller on line 2.
+        let frames = &responses[4]["body"]["stackFrames"];
+        assert_eq!(frames.as_array().unwrap().len(), 2);
+        assert_eq!(frames[0]["line"], 5);
+        assert_eq!(frames[0]["source"]["path"], "program.asm");
+        assert_eq!(frames[1]["line"], 2);
+
+        let registers = &responses[5]["body"]["variables"];
+        assert_eq!(registers[0]["name"], "R1");
+        assert_eq!(registers[0]["value"], "0x00000005 (5)");
+
+        let regions = responses[6]["body"]["variables"].as_array().unwrap();
+        assert_eq!(regions.last().unwrap()["name"], "Executable");
+        assert_eq!(regions.last().unwrap()["indexedVariables"], 2);
+
+        let rows = responses[7]["body"]["variables"].as_array().unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["name"], "0x00000010");
+
+        // Stepping out of the subroutine returns to the halt on line 3.
+        let frames = &responses[9]["body"]["stackFrames"];
+        assert_eq!(frames.as_array().unwrap().len(), 1);
+        assert_eq!(frames[0]["line"], 3);
+    }
+
+    #[test]
+    fn message_lengths_are_not_trusted() {
+        let mut input: &[u8] = b"Content-Length: 18446744073709551615\r\n\r\n{}";
+        let error = read_message(&mut input).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut input: &[u8] = b"Content-Length: 2\r\n\r\n{}";
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+    }
+
+    #[test]
+    fn dap_pauses_running_program() {
+        // A loop which never halts, as the accumulator is never one.
+        let dir = env::temp_dir().join(format!("oxidation-dap-pause-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let program_path = dir.join("program.bin");
+        fs::write(&program_path, Instruction::JmpNotEq(1, 0).encode()).unwrap();
+
+        let script = [
+            request(1, "initialize", json!({ "adapterID": "oxidation" })),
+            request(2, "launch", json!({ "program": program_path })),
+            request(3, "configurationDone", json!({})),
+            request(4, "stackTrace", json!({ "threadId": 1 })),
+            request(5, "pause", json!({ "threadId": 1 })),
+            request(6, "disconnect", json!({})),
+        ]
+        .concat();
+
+        let messages = exchange(script);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The stack trace is answered once the pause has stopped the program.
+        let order: Vec<&str> = messages
+            .iter()
+            .map(|m| {
+                m["command"]
+                    .as_str()
+                    .or_else(|| m["event"].as_str())
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "initialize",
+                "initialized",
+                "launch",
+                "configurationDone",
+                "pause",
+                "stopped",
+                "stackTrace",
+                "disconnect",
+            ]
+        );
+        assert_eq!(messages[5]["body"]["reason"], "pause");
+        assert!(messages.iter().all(|m| m["success"] != false));
+    }
+}
@@ -0,0 +1,603 @@
+use oxidation_core::cpu::CpuError;
+use oxidation_core::execution::RunOutcome;
+use oxidation_core::instructions::enums::Instruction;
+use oxidation_core::memory::MemoryAccess;
+use oxidation_core::registers::{RegisterValue, Registers};
+use oxidation_core::security_context::SecurityContext;
+use oxidation_core::symbols::SymbolTable;
+use oxidation_core::virtual_machine::VirtualMachine;
+use oxidation_core::watchpoints::WatchpointHit;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+
+type CommandResult = Result<String, String>;
+
+const HELP: &str = "\
+break, b <location>        set a breakpoint
+delete, d <location>       clear a breakpoint
+breakpoints, bl            list the breakpoints
+step, s [count]            execute one or more instructions
+continue, c                run until a breakpoint is hit or the program halts
+registers, r               print the registers
+set <register> <value>     modify a register
+dump, x <location> [len]   hex-dump memory
+write, w <location> <byte>...  edit memory
+regions                    list the memory regions
+disassemble, dis [count]   disassemble around the instruction pointer
+quit, q                    exit the debugger
+
+A location is a decimal or 0x-prefixed hexadecimal address, or a label.";
+
+/// Whether the debugger should continue reading commands.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+/// An interactive, line-oriented debugger for a virtual machine.
+pub struct Debugger {
+    vm: VirtualMachine,
+    symbols: SymbolTable,
+    breakpoints: BTreeSet<u32>,
+}
+
+impl Debugger {
+    /// Create a new debugger.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm` - the virtual machine, with a program loaded.
+    /// * `symbols` - the labels that may be used in place of addresses.
+    pub fn new(vm: VirtualMachine, symbols: SymbolTable) -> Self {
+        Self {
+            vm,
+            symbols,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Read and execute commands until the input is exhausted or the user quits.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - the source of the commands.
+    /// * `output` - the destination of the prompts and command output.
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+        let mut line = String::new();
+
+        loop {
+            write!(output, "(oxdb) ")?;
+            output.flush()?;
+
+            line.clear();
+            if input.read_line(&mut line)? == 0 || self.execute(&line, output)? == Flow::Quit {
+                break;
+            }
+        }
+
+        writeln!(output)
+    }
+
+    /// Execute a single command.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - the command line.
+    /// * `output` - the destination of the command output.
+    pub fn execute(&mut self, line: &str, output: &mut dyn Write) -> io::Result<Flow> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match args.split_first() {
+            Some((c, a)) => (*c, a),
+            None => return Ok(Flow::Continue),
+        };
+
+        let result = match command {
+            "help" | "h" | "?" => Ok(HELP.to_string()),
+            "break" | "b" => self.set_breakpoint(args),
+            "delete" | "d" => self.clear_breakpoint(args),
+            "breakpoints" | "bl" => Ok(self.list_breakpoints()),
+            "step" | "s" => self.step(args),
+            "continue" | "c" => Ok(self.resume()),
+            "registers" | "r" => Ok(self.registers()),
+            "set" => self.set_register(args),
+            "dump" | "x" => self.dump(args),
+            "write" | "w" => self.write_memory(args),
+            "regions" => Ok(self.regions()),
+            "disassemble" | "dis" => self.disassemble(args),
+            "quit" | "q" => return Ok(Flow::Quit),
+            _ => Err(format!(
+                "unknown command '{}', type 'help' for a list of commands",
+                command
+            )),
+        };
+
+        match result {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => writeln!(output, "{}", text.trim_end())?,
+            Err(e) => writeln!(output, "error: {}", e)?,
+        }
+
+        Ok(Flow::Continue)
+    }
+
+    fn set_breakpoint(&mut self, args: &[&str]) -> CommandResult {
+        let address = self.location(args.first())?;
+        self.breakpoints.insert(address);
+
+        Ok(format!("breakpoint set at {}", self.describe(address)))
+    }
+
+    fn clear_breakpoint(&mut self, args: &[&str]) -> CommandResult {
+        let address = self.location(args.first())?;
+        if !self.breakpoints.remove(&address) {
+            return Err(format!(
+                "no breakpoint is set at {}",
+                self.describe(address)
+            ));
+        }
+
+        Ok(format!("breakpoint cleared at {}", self.describe(address)))
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "no breakpoints are set".to_string();
+        }
+
+        let mut text = String::new();
+        for (i, address) in self.breakpoints.iter().enumerate() {
+            let _ = writeln!(text, "{}: {}", i, self.describe(*address));
+        }
+
+        text
+    }
+
+    fn step(&mut self, args: &[&str]) -> CommandResult {
+        let count = match args.first() {
+            Some(arg) => parse_number(arg)?,
+            None => 1,
+        };
+
+        for _ in 0..count {
+            let outcome = self.vm.step();
+            if let Some(e) = outcome.fault {
+                return Ok(self.fault_report(&e));
+            }
+            if let Some(hit) = outcome.watchpoint {
+                return Ok(self.watchpoint_report(&hit));
+            }
+            if outcome.halted {
+                return Ok("the program has halted".to_string());
+            }
+        }
+
+        Ok(self.current_line())
+    }
+
+    fn resume(&mut self) -> String {
+        if self.vm.cpu.is_halted() {
+            return "the program has halted".to_string();
+        }
+
+        let breakpoints = &self.breakpoints;
+        let outcome = self
+            .vm
+            .run_until(|cpu, _| match cpu.get_instruction_pointer() {
+                Ok(ip) => breakpoints.contains(&ip),
+                Err(_) => false,
+            });
+
+        match outcome {
+            RunOutcome::Breakpoint => format!("breakpoint hit\n{}", self.current_line()),
+            RunOutcome::Fault(e) => self.fault_report(&e),
+            RunOutcome::Watchpoint(hit) => self.watchpoint_report(&hit),
+            _ => "the program has halted".to_string(),
+        }
+    }
+
+    fn watchpoint_report(&self, hit: &WatchpointHit) -> String {
+        format!(
+            "watchpoint {} triggered by {}\n{}",
+            hit.id,
+            self.describe(hit.address),
+            self.current_line()
+        )
+    }
+
+    fn fault_report(&self, error: &CpuError) -> String {
+        let mut report = Vec::new();
+        let _ = self.vm.capture_fault(error).write_report(&mut report);
+
+        String::from_utf8_lossy(&report).into_owned()
+    }
+
+    fn registers(&self) -> String {
+        let mut text = String::new();
+        for register in &self.vm.cpu.registers.registers {
+            let value = register.get_value(SecurityContext::System);
+            let value = match value {
+                Ok(v) => format_value(v),
+                Err(_) => "<inaccessible>".to_string(),
+            };
+            let _ = writeln!(text, "{:<2} = {}", register.register_id, value);
+        }
+
+        let _ = write!(
+            text,
+            "cycles = {}, instructions = {}",
+            self.vm.cpu.cycles(),
+            self.vm.cpu.instructions_executed()
+        );
+
+        text
+    }
+
+    fn set_register(&mut self, args: &[&str]) -> CommandResult {
+        if args.len() != 2 {
+            return Err("usage: set <register> <value>".to_string());
+        }
+
+        let register = Registers::from_name(args[0])
+            .ok_or_else(|| format!("'{}' is not a register", args[0]))?;
+        let value = parse_value(args[1])?;
+
+        self.vm
+            .cpu
+            .registers
+            .set_register_value(register, RegisterValue::I32(value), SecurityContext::System)
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!(
+            "{} = {}",
+            register,
+            format_value(RegisterValue::I32(value))
+        ))
+    }
+
+    fn dump(&self, args: &[&str]) -> CommandResult {
+        let start = self.location(args.first())?;
+        let len = match args.get(1) {
+            Some(arg) => parse_number(arg)?,
+            None => 64,
+        };
+
+        let available = (self.vm.memory.len() as u32).saturating_sub(start);
+        let bytes = self
+            .vm
+            .memory
+            .read_bytes(start, len.min(available), SecurityContext::System)
+            .map_err(|e| e.to_string())?;
+
+        let mut text = String::new();
+        for (i, chunk) in bytes.chunks(16).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+
+            let _ = writeln!(
+                text,
+                "{:#010X}  {:<47}  {}",
+                start + 16 * i as u32,
+                hex.join(" "),
+                ascii
+            );
+        }
+
+        Ok(text)
+    }
+
+    fn write_memory(&mut self, args: &[&str]) -> CommandResult {
+        if args.len() < 2 {
+            return Err("usage: write <location> <byte>...".to_string());
+        }
+
+        let start = self.location(args.first())?;
+        let bytes = args[1..]
+            .iter()
+            .map(|arg| {
+                let value = parse_number(arg)?;
+                if value > u8::MAX as u32 {
+                    return Err(format!("{} does not fit in a byte", arg));
+                }
+                Ok(value as u8)
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+
+        self.vm
+            .memory
+            .write_bytes(start, &bytes, SecurityContext::System)
+            .map_err(|e| e.to_string())?;
+
+        Ok(format!(
+            "wrote {} byte(s) at {}",
+            bytes.len(),
+            self.describe(start)
+        ))
+    }
+
+    fn regions(&self) -> String {
+        let mut text = format!(
+            "{:>4}  {:>10}  {:>10}  {:<6}  name\n",
+            "id", "start", "end", "access"
+        );
+
+        for region in self.vm.memory.regions() {
+            let _ = writeln!(
+                text,
+                "{:>4}  {:#010X}  {:#010X}  {:<6}  {}",
+                region.seq_id,
+                region.start,
+                region.end,
+                format_access(region.access),
+                region.name
+            );
+        }
+
+        text
+    }
+
+    fn disassemble(&self, args: &[&str]) -> CommandResult {
+        let count = match args.first() {
+            Some(arg) => parse_number(arg)? as usize,
+            None => 5,
+        };
+
+        let ip = self.instruction_pointer();
+
+        // Instructions vary in length, so those preceding the instruction pointer
+        // are found by decoding forwards from the start of the executable region.
+        let mut before = Vec::new();
+        let start = self
+            .vm
+            .cpu
+            .get_executable_region_id()
+            .and_then(|id| self.vm.memory.get_region_by_seq_id(id).ok())
+            .map_or(ip, |r| r.start);
+
+        let mut address = start;
+        while address < ip {
+            match Instruction::decode_at(&self.vm.memory, address) {
+                Ok(ins) => {
+                    before.push(address);
+                    address += ins.size();
+                }
+                Err(_) => break,
+            }
+        }
+
+        let first = if address == ip {
+            before[before.len().saturating_sub(count)..]
+                .first()
+                .copied()
+                .unwrap_or(ip)
+        } else {
+            ip
+        };
+
+        let mut text = String::new();
+        let mut address = first;
+        let mut after = 0;
+        while after <= count {
+            if address >= ip {
+                after += 1;
+            }
+
+            let line = self.format_line(address);
+            let _ = writeln!(text, "{}", line);
+
+            match Instruction::decode_at(&self.vm.memory, address) {
+                Ok(ins) => address += ins.size(),
+                Err(_) => break,
+            }
+        }
+
+        Ok(text)
+    }
+
+    /// Resolve a location, given as an address or a label.
+    fn location(&self, arg: Option<&&str>) -> Result<u32, String> {
+        let arg = arg.ok_or_else(|| "a location is required".to_string())?;
+
+        match self.symbols.address_of(arg) {
+            Some(address) => Ok(address),
+            None => parse_number(arg).map_err(|_| format!("'{}' is not an address or label", arg)),
+        }
+    }
+
+    fn describe(&self, address: u32) -> String {
+        // An address is only described relative to a symbol within the same region.
+        let region = |a| self.vm.memory.get_region_for_address(a).map(|r| r.seq_id);
+        match self.symbols.lookup(address) {
+            Some((start, _)) if region(address).is_some() && region(start) == region(address) => {
+                format!("{:#010X} <{}>", address, self.symbols.describe(address))
+            }
+            _ => format!("{:#010X}", address),
+        }
+    }
+
+    fn instruction_pointer(&self) -> u32 {
+        self.vm.cpu.get_instruction_pointer().unwrap_or_default()
+    }
+
+    fn current_line(&self) -> String {
+        self.format_line(self.instruction_pointer())
+    }
+
+    /// Format the disassembly of the instruction at an address, marking the
+    /// instruction pointer and any breakpoint.
+    fn format_line(&self, address: u32) -> String {
+        let marker = match (
+            address == self.instruction_pointer(),
+            self.breakpoints.contains(&address),
+        ) {
+            (true, true) => "=>*",
+            (true, false) => "=> ",
+            (false, true) => "  *",
+            (false, false) => "   ",
+        };
+
+        let ins = match Instruction::decode_at(&self.vm.memory, address) {
+            Ok(ins) => ins.to_string(),
+            Err(e) => format!("<{}>", e),
+        };
+
+        format!("{} {}: {}", marker, self.describe(address), ins)
+    }
+}
+
+/// Parse a decimal or 0x-prefixed hexadecimal number.
+pub(crate) fn parse_number(arg: &str) -> Result<u32, String> {
+    let result = match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+
+    result.map_err(|_| format!("'{}' is not a valid number", arg))
+}
+
+/// Parse a register value, which may be negative.
+fn parse_value(arg: &str) -> Result<i32, String> {
+    match arg.strip_prefix('-') {
+        Some(rest) => parse_number(rest).map(|v| (v as i32).wrapping_neg()),
+        None => parse_number(arg).map(|v| v as i32),
+    }
+}
+
+pub(crate) fn format_value(value: RegisterValue) -> String {
+    match value {
+        RegisterValue::I16(v) => format!("{:#06X} ({})", v, v),
+        RegisterValue::I32(v) => format!("{:#010X} ({})", v, v),
+        RegisterValue::I64(v) => format!("{:#018X} ({})", v, v),
+        RegisterValue::F32(v) => format!("{}", v),
+    }
+}
+
+pub(crate) fn format_access(access: MemoryAccess) -> String {
+    let flags = [
+        (MemoryAccess::R, 'r'),
+        (MemoryAccess::W, 'w'),
+        (MemoryAccess::PR, 'R'),
+        (MemoryAccess::PW, 'W'),
+    ];
+
+    flags
+        .iter()
+        .map(|(flag, c)| if access.contains(*flag) { *c } else { '-' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> Debugger {
+        let program = [
+            Instruction::MovLitReg(5, Registers::R1),
+            Instruction::CalLit(15),
+            Instruction::HLT(),
+            // sub (15)
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::Ret(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, "main".to_string());
+        symbols.insert(15, "sub".to_string());
+
+        Debugger::new(vm, symbols)
+    }
+
+    fn run(debugger: &mut Debugger, line: &str) -> String {
+        let mut output = Vec::new();
+        debugger.execute(line, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn breakpoints_and_continue() {
+        let mut dbg = debugger();
+
+        assert_eq!(
+            run(&mut dbg, "break sub"),
+            "breakpoint set at 0x0000000F <sub>\n"
+        );
+        assert_eq!(
+            run(&mut dbg, "c"),
+            "breakpoint hit\n=>* 0x0000000F <sub>: add 01, R1\n"
+        );
+        assert_eq!(run(&mut dbg, "s"), "=>  0x00000016 <sub+0x7>: ret\n");
+        assert_eq!(
+            run(&mut dbg, "d 0xF"),
+            "breakpoint cleared at 0x0000000F <sub>\n"
+        );
+        assert_eq!(run(&mut dbg, "bl"), "no breakpoints are set\n");
+        assert_eq!(run(&mut dbg, "continue"), "the program has halted\n");
+        assert!(run(&mut dbg, "d sub").starts_with("error: no breakpoint"));
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let mut dbg = debugger();
+
+        assert_eq!(run(&mut dbg, "set r3 -2"), "R3 = 0xFFFFFFFE (-2)\n");
+        assert!(run(&mut dbg, "r").contains("R3 = 0xFFFFFFFE (-2)\n"));
+        assert!(run(&mut dbg, "set X1 1").starts_with("error:"));
+
+        assert_eq!(
+            run(&mut dbg, "w 500 0x41 0x42 67"),
+            "wrote 3 byte(s) at 0x000001F4\n"
+        );
+        assert_eq!(
+            run(&mut dbg, "x 500 4"),
+            format!("0x000001F4  {:<47}  ABC.\n", "41 42 43 00")
+        );
+        assert!(run(&mut dbg, "w 500 256").starts_with("error:"));
+
+        let regions = run(&mut dbg, "regions");
+        assert!(regions.contains("rw--    Root"));
+        assert!(regions.contains("r--W    Stack"));
+        assert!(regions.contains("r--W    Executable"));
+    }
+
+    #[test]
+    fn disassembles_around_instruction_pointer() {
+        let mut dbg = debugger();
+        run(&mut dbg, "s 2");
+        run(&mut dbg, "b main");
+
+        assert_eq!(
+            run(&mut dbg, "dis 1"),
+            "    0x0000000D <main+0xD>: hlt\n\
+             =>  0x0000000F <sub>: add 01, R1\n\
+             \x20   0x00000016 <sub+0x7>: ret\n"
+        );
+        assert!(run(&mut dbg, "dis").starts_with("  * 0x00000000 <main>: mov 05, R1\n"));
+    }
+
+    #[test]
+    fn run_reads_until_quit() {
+        let mut dbg = debugger();
+        let mut input = "step\nbogus\nquit\nstep\n".as_bytes();
+        let mut output = Vec::new();
+        dbg.run(&mut input, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("error: unknown command 'bogus'"));
+        assert_eq!(output.matches("(oxdb) ").count(), 3);
+        assert_eq!(dbg.vm.cpu.instructions_executed(), 1);
+    }
+}
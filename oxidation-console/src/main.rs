@@ -1,25 +1,213 @@
-use oxidation_core::instructions::enums::Instruction;
-use oxidation_core::registers::Registers;
-use oxidation_core::virtual_machine::*;
+mod dap;
+mod debugger;
+
+use dap::Adapter;
+use debugger::Debugger;
+use log::LevelFilter;
 use oxidation_assembler::*;
-use std::io::{self};
+use oxidation_core::executable::{Executable, ExecutableReader};
+use oxidation_core::gdb::GdbStub;
+use oxidation_core::symbols::SymbolTable;
+use oxidation_core::verifier;
+use oxidation_core::virtual_machine::*;
 use simple_logger::SimpleLogger;
+use std::io::{self};
+use std::{env, fs, process};
 
 fn main() {
-    SimpleLogger::new();
+    let args: Vec<String> = env::args().collect();
+
+    // The adapter communicates over the standard streams, so nothing else may write to them.
+    if args.get(1).map(|a| a.as_str()) == Some("dap") {
+        let stdout = io::stdout();
+        if let Err(e) = Adapter::new().run(io::BufReader::new(io::stdin()), &mut stdout.lock()) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    SimpleLogger::new()
+        .with_level(LevelFilter::Warn)
+        .env()
+        .init()
+        .expect("Failed to initialize the logger.");
+
+    if args.get(1).map(|a| a.as_str()) == Some("debug") {
+        if let Err(e) = debug(&args[2..]) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.get(1).map(|a| a.as_str()) == Some("assemble") {
+        if let Err(e) = assemble(&args[2..]) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if args.get(1).map(|a| a.as_str()) == Some("gdb") {
+        if let Err(e) = gdb(&args[2..]) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
 
-    let ins = vec![/*Instruction::NOP(), */Instruction::AddLitReg(123, Registers::R1)/*, Instruction::HLT()*/];
-    assembler::assemble(ins.as_slice());
+    eprintln!("usage: oxidation-console <assemble|debug|dap|gdb> [arguments]");
+    process::exit(1);
+}
+
+/// Load a program, and optionally its labels, into the interactive debugger.
+///
+/// # Arguments
+///
+/// * `args` - the path to the program binary, followed by the optional path to a
+///   label file in which each line holds an address and a label.
+fn debug(args: &[String]) -> Result<(), String> {
+    let program_path = args
+        .first()
+        .ok_or("usage: oxidation-console debug <program> [labels]")?;
+    let program = fs::read(program_path)
+        .map_err(|e| format!("failed to read the program '{}': {}", program_path, e))?;
 
-    let mut input_string = String::new();
+    let symbols = match args.get(1) {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read the labels '{}': {}", path, e))?;
+            Some(parse_symbols(&text)?)
+        }
+        None => None,
+    };
 
     let mut vm = VirtualMachine::new(64_000, 100, false);
-    //vm.initialize();
-    //vm.run_test();
+    let embedded = load_program(&mut vm, &program)?;
+    let symbols = symbols.unwrap_or(embedded);
 
-    println!("{}", vm.memory.len());
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    Debugger::new(vm, symbols)
+        .run(&mut stdin.lock(), &mut stdout.lock())
+        .map_err(|e| e.to_string())
+}
 
-    match io::stdin().read_line(&mut input_string) {
-        _ => println!(""),
+/// Assemble a source file into an executable, along with its symbols and debug
+/// information.
+///
+/// # Arguments
+///
+/// * `args` - the path to the source file, followed by the path to which the
+///   executable should be written.
+fn assemble(args: &[String]) -> Result<(), String> {
+    let (source_path, output_path) = match args {
+        [source, output, ..] => (source, output),
+        _ => return Err("usage: oxidation-console assemble <source> <output>".to_string()),
     };
+    let source = fs::read_to_string(source_path)
+        .map_err(|e| format!("failed to read the source '{}': {}", source_path, e))?;
+
+    let program = program::assemble(&source, 0).map_err(|errors| {
+        errors
+            .iter()
+            .map(|e| e.describe(source_path))
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+
+    program
+        .executable(source_path)
+        .save(output_path)
+        .map_err(|e| format!("failed to write the executable '{}': {}", output_path, e))
+}
+
+/// Load a program and serve it to a single GDB client.
+///
+/// # Arguments
+///
+/// * `args` - the path to the program binary, followed by the optional address on which
+///   to listen, which defaults to `127.0.0.1:1234`.
+fn gdb(args: &[String]) -> Result<(), String> {
+    let program_path = args
+        .first()
+        .ok_or("usage: oxidation-console gdb <program> [address]")?;
+    let program = fs::read(program_path)
+        .map_err(|e| format!("failed to read the program '{}': {}", program_path, e))?;
+    let address = args.get(1).map(|a| a.as_str()).unwrap_or("127.0.0.1:1234");
+
+    let mut vm = VirtualMachine::new(64_000, 100, false);
+    load_program(&mut vm, &program)?;
+
+    eprintln!("Waiting for a GDB connection on {}", address);
+    GdbStub::new(&mut vm)
+        .serve_tcp(address)
+        .map_err(|e| e.to_string())
+}
+
+/// Verify and load a program, which is either an executable or a raw sequence of
+/// instructions to be loaded at address zero. Returns the symbols held within the
+/// executable, if any.
+///
+/// # Arguments
+///
+/// * `vm` - the virtual machine into which the program should be loaded.
+/// * `program` - the bytes of the program.
+fn load_program(vm: &mut VirtualMachine, program: &[u8]) -> Result<SymbolTable, String> {
+    if !Executable::is_executable(program) {
+        verify_program(program, 0)?;
+        vm.load_program(program, 0)
+            .map_err(|e| format!("failed to load the program: {}", e))?;
+        return Ok(SymbolTable::new());
+    }
+
+    let executable = ExecutableReader::new(&mut &program[..])
+        .read()
+        .map_err(|e| format!("failed to read the executable: {}", e))?;
+    let code = executable.code().unwrap();
+    verify_program(&code.bytes, code.address)?;
+    vm.load_executable(&executable)
+        .map_err(|e| format!("failed to load the program: {}", e))?;
+
+    Ok(executable.symbols)
+}
+
+/// Verify a program, describing every problem found.
+///
+/// # Arguments
+///
+/// * `program` - the bytes of the program.
+/// * `base` - the address at which the program is to be loaded.
+fn verify_program(program: &[u8], base: u32) -> Result<(), String> {
+    verifier::verify(program, base).map_err(|problems| {
+        let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        format!("the program failed verification: {}", problems.join("; "))
+    })
+}
+
+/// Parse a label file, in which each line holds an address and a label.
+///
+/// # Arguments
+///
+/// * `text` - the contents of the label file.
+fn parse_symbols(text: &str) -> Result<SymbolTable, String> {
+    let mut symbols = SymbolTable::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+        let (address, name) = match (parts.next(), parts.next()) {
+            (Some(a), Some(n)) => (a, n),
+            (None, _) => continue,
+            _ => return Err(format!("line {} of the label file is invalid", i + 1)),
+        };
+
+        let address = match address.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => address.parse(),
+        }
+        .map_err(|_| format!("line {} of the label file has an invalid address", i + 1))?;
+
+        symbols.insert(address, name.to_string());
+    }
+
+    Ok(symbols)
 }
@@ -0,0 +1,54 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use oxidation_core::instructions::enums::Instruction;
+
+mod programs;
+
+/// The minimum size of the binary to be decoded.
+const BINARY_SIZE: usize = 1 << 20;
+
+/// Returns a large binary made up of copies of each of the workloads.
+fn large_binary() -> Vec<u8> {
+    let programs = [
+        programs::memory().program(),
+        programs::calls().program(),
+        programs::sort().program(),
+        programs::sieve().program(),
+        programs::strings().program(),
+    ];
+
+    let mut binary = Vec::with_capacity(BINARY_SIZE);
+    while binary.len() < BINARY_SIZE {
+        for program in &programs {
+            binary.extend_from_slice(program);
+        }
+    }
+
+    binary
+}
+
+/// Decode every instruction within a binary, returning the number decoded.
+fn decode_all(binary: &[u8]) -> usize {
+    let mut offset = 0;
+    let mut count = 0;
+    while offset < binary.len() {
+        let ins = Instruction::decode(&binary[offset..]).unwrap();
+        offset += ins.size() as usize;
+        count += 1;
+    }
+
+    count
+}
+
+fn decode(c: &mut Criterion) {
+    let binary = large_binary();
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Bytes(binary.len() as u64));
+    group.bench_function("large_binary", |b| {
+        b.iter(|| decode_all(black_box(&binary)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
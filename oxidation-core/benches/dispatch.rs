@@ -0,0 +1,73 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use oxidation_core::execution::RunOutcome;
+use oxidation_core::instructions::enums::Instruction;
+use oxidation_core::registers::Registers;
+use oxidation_core::virtual_machine::VirtualMachine;
+
+/// The number of iterations performed by the guest program.
+const ITERATIONS: i32 = 10_000;
+
+/// Returns a tight loop of short instructions, along with the number of
+/// instructions executed by it.
+fn loop_program() -> (Vec<u8>, u64) {
+    let setup = [Instruction::MovLitReg(0, Registers::R1)];
+    let loop_start: u32 = setup.iter().map(|i| i.encode().len() as u32).sum();
+
+    let body = [
+        Instruction::NOP(),
+        Instruction::MovRegReg(Registers::R1, Registers::R2),
+        Instruction::AddLitReg(1, Registers::R1),
+        Instruction::MovRegReg(Registers::AC, Registers::R1),
+        Instruction::JmpNotEq(ITERATIONS, loop_start),
+    ];
+    let tail = [Instruction::HLT()];
+
+    let executed = setup.len() + body.len() * ITERATIONS as usize + tail.len();
+    let program = setup
+        .iter()
+        .chain(&body)
+        .chain(&tail)
+        .flat_map(|i| i.encode())
+        .collect();
+
+    (program, executed as u64)
+}
+
+fn dispatch(c: &mut Criterion) {
+    let (program, executed) = loop_program();
+
+    let mut group = c.benchmark_group("dispatch");
+    group.throughput(Throughput::Elements(executed));
+
+    let mut configurations = vec![("cached", true, false), ("uncached", false, false)];
+    if cfg!(feature = "jit") {
+        configurations.push(("compiled", true, true));
+    }
+
+    for (name, cached, compiled) in configurations {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || {
+                    let mut vm = VirtualMachine::new(1_000, 10, false);
+                    vm.cpu.decode_cache.set_enabled(cached);
+                    #[cfg(feature = "jit")]
+                    vm.cpu.jit.set_enabled(compiled);
+                    #[cfg(not(feature = "jit"))]
+                    let _ = compiled;
+                    vm.load_program(&program, 0).unwrap();
+                    vm
+                },
+                |mut vm| {
+                    assert!(matches!(vm.run_for(u64::MAX), RunOutcome::Halted));
+                    assert_eq!(vm.cpu.instructions_executed(), executed);
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+; Repeatedly call a chain of nested subroutines that use the stack.
+
+.sub main
+    mov 0, R1
+loop:
+    call [first]
+    add 1, R1
+    mov AC, R1
+    jne 0d5000, [loop]
+    hlt
+
+.sub first
+    push R1
+    call [second]
+    pop R2
+    ret
+
+.sub second
+    push 7
+    call [third]
+    pop R3
+    ret
+
+.sub third
+    call [fourth]
+    ret
+
+.sub fourth
+    add 1, R4
+    mov AC, R4
+    ret
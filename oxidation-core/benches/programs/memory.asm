@@ -0,0 +1,25 @@
+; Repeatedly store to and load from a block of memory.
+
+.sub main
+    mov 0, R1
+loop:
+    mov R1, [10000]
+    mov R1, [10004]
+    mov R1, [10008]
+    mov R1, [1000C]
+    mov R1, [10010]
+    mov R1, [10014]
+    mov R1, [10018]
+    mov R1, [1001C]
+    mov [10000], R2
+    mov [10004], R2
+    mov [10008], R2
+    mov [1000C], R2
+    mov [10010], R2
+    mov [10014], R2
+    mov [10018], R2
+    mov [1001C], R2
+    add 1, R1
+    mov AC, R1
+    jne 0d5000, [loop]
+    hlt
@@ -0,0 +1,162 @@
+//! Guest programs shared by the benchmarks.
+//!
+//! Each workload is assembled from a source file within this directory, and is
+//! checked against the results expected of it before being measured.
+
+#![allow(dead_code)]
+
+use oxidation_assembler::program::{self, Program};
+use oxidation_core::execution::RunOutcome;
+use oxidation_core::registers::{RegisterValue, Registers, Registers::*};
+use oxidation_core::security_context::SecurityContext;
+use oxidation_core::virtual_machine::VirtualMachine;
+
+/// The size of the main memory of the virtual machine running a workload.
+const MEMORY_SIZE: u32 = 0x2_0000;
+
+/// The address at which the data of a workload is placed.
+const DATA: u32 = 0x1_0000;
+
+/// The number of iterations made by the memory and call workloads.
+const ITERATIONS: i32 = 5_000;
+
+/// Checks the state of a virtual machine that has run a program.
+type Check = Box<dyn Fn(&Program, &VirtualMachine)>;
+
+/// A guest program, along with the data upon which it operates.
+pub struct Workload {
+    pub program: Program,
+    check: Check,
+}
+
+impl Workload {
+    /// Assemble the source of a workload.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - the assembly source.
+    /// * `check` - checks the state of a virtual machine that has run the workload.
+    fn new<F>(source: &str, check: F) -> Self
+    where
+        F: Fn(&Program, &VirtualMachine) + 'static,
+    {
+        Workload {
+            program: program::assemble(source, 0).unwrap(),
+            check: Box::new(check),
+        }
+    }
+
+    /// Returns the encoded program.
+    pub fn program(&self) -> Vec<u8> {
+        self.program.text.clone()
+    }
+
+    /// Returns a virtual machine with the program and its data loaded.
+    pub fn load(&self) -> VirtualMachine {
+        let mut vm = VirtualMachine::new(MEMORY_SIZE, 16, false);
+        for (address, bytes) in &self.program.data {
+            vm.memory
+                .write_bytes(*address, bytes, SecurityContext::System)
+                .unwrap();
+        }
+        vm.load_program(&self.program.text, self.program.text_address)
+            .unwrap();
+
+        vm
+    }
+
+    /// Run the program to completion, panicking if it does not produce the expected
+    /// results. Returns the number of instructions executed.
+    pub fn verify(&self) -> u64 {
+        let mut vm = self.load();
+        assert!(matches!(vm.run_for(u64::MAX), RunOutcome::Halted));
+        (self.check)(&self.program, &vm);
+
+        vm.cpu.instructions_executed()
+    }
+}
+
+/// Returns the words held within the data of a program.
+fn data_words(program: &Program) -> Vec<i32> {
+    program.data[0]
+        .1
+        .chunks(4)
+        .map(|c| i32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+fn read_words(vm: &VirtualMachine, address: u32, count: usize) -> Vec<i32> {
+    (0..count as u32)
+        .map(|i| {
+            vm.memory
+                .read_i32(address + i * 4, SecurityContext::System)
+                .unwrap()
+        })
+        .collect()
+}
+
+fn register(vm: &VirtualMachine, reg: Registers) -> i32 {
+    match vm
+        .cpu
+        .registers
+        .get_register_value(reg, SecurityContext::System)
+        .unwrap()
+    {
+        RegisterValue::I32(v) => v,
+        v => panic!("unexpected register value {:?}", v),
+    }
+}
+
+/// Repeatedly store to and load from a block of memory.
+pub fn memory() -> Workload {
+    Workload::new(include_str!("memory.asm"), |_, vm| {
+        assert_eq!(read_words(vm, DATA, 8), vec![ITERATIONS - 1; 8]);
+        assert_eq!(register(vm, R2), ITERATIONS - 1);
+    })
+}
+
+/// Repeatedly call a chain of nested subroutines that use the stack.
+pub fn calls() -> Workload {
+    Workload::new(include_str!("calls.asm"), |_, vm| {
+        assert_eq!(register(vm, R2), ITERATIONS - 1);
+        assert_eq!(register(vm, R3), 7);
+        assert_eq!(register(vm, R4), ITERATIONS);
+    })
+}
+
+/// Bubble sort an array of values.
+pub fn sort() -> Workload {
+    Workload::new(include_str!("sort.asm"), |program, vm| {
+        let mut sorted = data_words(program);
+        sorted.sort_unstable();
+        assert_eq!(read_words(vm, DATA, sorted.len()), sorted);
+    })
+}
+
+/// Count the primes below a limit with the sieve of Eratosthenes.
+pub fn sieve() -> Workload {
+    let limit = 250;
+    let primes = (2..limit)
+        .filter(|n| (2..*n).take_while(|d| d * d <= *n).all(|d| n % d != 0))
+        .count() as i32;
+
+    Workload::new(include_str!("sieve.asm"), move |_, vm| {
+        assert_eq!(register(vm, R6), primes)
+    })
+}
+
+/// Reverse a string and count the occurrences of a character within it.
+pub fn strings() -> Workload {
+    Workload::new(include_str!("strings.asm"), |program, vm| {
+        // The string is terminated by a zero, and is followed by the output.
+        let mut text = data_words(program);
+        text.pop();
+        let reversed: Vec<i32> = text.iter().rev().cloned().collect();
+        let count = text.iter().filter(|c| **c == 'a' as i32).count() as i32;
+
+        assert_eq!(register(vm, R2), text.len() as i32);
+        assert_eq!(register(vm, R6), count);
+        let output = DATA + (text.len() as u32 + 1) * 4;
+        assert_eq!(read_words(vm, output, text.len()), reversed);
+    })
+}
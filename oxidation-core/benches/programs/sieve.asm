@@ -0,0 +1,591 @@
+; Count the primes below 250 with the sieve of Eratosthenes, leaving the count in R6.
+;
+; The instruction set has neither indexed addressing nor any comparison other than
+; equality. Arrays are therefore accessed through tables of subroutines, one for each
+; element, that are called through a computed address, and values are ordered by
+; counting from one towards the other. Each entry of a table is nine bytes long.
+;
+; R1 holds the offset of the entry for the current candidate, R2 the candidate,
+; R3 the offset of the entry for the current multiple and R6 the number of primes.
+; Marking a multiple beyond the limit instead signals the end of the multiples.
+
+.macro load_r5 address
+    mov [address], R5
+    ret
+.endm
+
+.macro mark_r4 address
+    mov R4, [address]
+    ret
+.endm
+
+.macro beyond
+    mov 1, R7
+    ret
+.endm
+
+.macro beyond_10
+    beyond
+    beyond
+    beyond
+    beyond
+    beyond
+    beyond
+    beyond
+    beyond
+    beyond
+    beyond
+.endm
+
+.macro beyond_50
+    beyond_10
+    beyond_10
+    beyond_10
+    beyond_10
+    beyond_10
+.endm
+
+.sub main
+    mov 1, R4
+    mov 2, R2
+    mov 0d18, R1
+    mov 0, R6
+candidate:
+    add load, R1
+    call AC
+    mov R5, AC
+    jeq 1, [next]
+    add 1, R6
+    mov AC, R6
+    mov R1, R3
+multiple:
+    ; Advance to the next multiple by stepping over as many entries as the candidate.
+    mov 0, R8
+step:
+    add 9, R3
+    mov AC, R3
+    add 1, R8
+    mov AC, R8
+    jne R2, [step]
+    add mark, R3
+    call AC
+    mov R7, AC
+    jeq 0, [multiple]
+    mov 0, R7
+next:
+    add 9, R1
+    mov AC, R1
+    add 1, R2
+    mov AC, R2
+    jne 0d250, [candidate]
+    hlt
+
+load:
+    load_r5 10000
+    load_r5 10004
+    load_r5 10008
+    load_r5 1000C
+    load_r5 10010
+    load_r5 10014
+    load_r5 10018
+    load_r5 1001C
+    load_r5 10020
+    load_r5 10024
+    load_r5 10028
+    load_r5 1002C
+    load_r5 10030
+    load_r5 10034
+    load_r5 10038
+    load_r5 1003C
+    load_r5 10040
+    load_r5 10044
+    load_r5 10048
+    load_r5 1004C
+    load_r5 10050
+    load_r5 10054
+    load_r5 10058
+    load_r5 1005C
+    load_r5 10060
+    load_r5 10064
+    load_r5 10068
+    load_r5 1006C
+    load_r5 10070
+    load_r5 10074
+    load_r5 10078
+    load_r5 1007C
+    load_r5 10080
+    load_r5 10084
+    load_r5 10088
+    load_r5 1008C
+    load_r5 10090
+    load_r5 10094
+    load_r5 10098
+    load_r5 1009C
+    load_r5 100A0
+    load_r5 100A4
+    load_r5 100A8
+    load_r5 100AC
+    load_r5 100B0
+    load_r5 100B4
+    load_r5 100B8
+    load_r5 100BC
+    load_r5 100C0
+    load_r5 100C4
+    load_r5 100C8
+    load_r5 100CC
+    load_r5 100D0
+    load_r5 100D4
+    load_r5 100D8
+    load_r5 100DC
+    load_r5 100E0
+    load_r5 100E4
+    load_r5 100E8
+    load_r5 100EC
+    load_r5 100F0
+    load_r5 100F4
+    load_r5 100F8
+    load_r5 100FC
+    load_r5 10100
+    load_r5 10104
+    load_r5 10108
+    load_r5 1010C
+    load_r5 10110
+    load_r5 10114
+    load_r5 10118
+    load_r5 1011C
+    load_r5 10120
+    load_r5 10124
+    load_r5 10128
+    load_r5 1012C
+    load_r5 10130
+    load_r5 10134
+    load_r5 10138
+    load_r5 1013C
+    load_r5 10140
+    load_r5 10144
+    load_r5 10148
+    load_r5 1014C
+    load_r5 10150
+    load_r5 10154
+    load_r5 10158
+    load_r5 1015C
+    load_r5 10160
+    load_r5 10164
+    load_r5 10168
+    load_r5 1016C
+    load_r5 10170
+    load_r5 10174
+    load_r5 10178
+    load_r5 1017C
+    load_r5 10180
+    load_r5 10184
+    load_r5 10188
+    load_r5 1018C
+    load_r5 10190
+    load_r5 10194
+    load_r5 10198
+    load_r5 1019C
+    load_r5 101A0
+    load_r5 101A4
+    load_r5 101A8
+    load_r5 101AC
+    load_r5 101B0
+    load_r5 101B4
+    load_r5 101B8
+    load_r5 101BC
+    load_r5 101C0
+    load_r5 101C4
+    load_r5 101C8
+    load_r5 101CC
+    load_r5 101D0
+    load_r5 101D4
+    load_r5 101D8
+    load_r5 101DC
+    load_r5 101E0
+    load_r5 101E4
+    load_r5 101E8
+    load_r5 101EC
+    load_r5 101F0
+    load_r5 101F4
+    load_r5 101F8
+    load_r5 101FC
+    load_r5 10200
+    load_r5 10204
+    load_r5 10208
+    load_r5 1020C
+    load_r5 10210
+    load_r5 10214
+    load_r5 10218
+    load_r5 1021C
+    load_r5 10220
+    load_r5 10224
+    load_r5 10228
+    load_r5 1022C
+    load_r5 10230
+    load_r5 10234
+    load_r5 10238
+    load_r5 1023C
+    load_r5 10240
+    load_r5 10244
+    load_r5 10248
+    load_r5 1024C
+    load_r5 10250
+    load_r5 10254
+    load_r5 10258
+    load_r5 1025C
+    load_r5 10260
+    load_r5 10264
+    load_r5 10268
+    load_r5 1026C
+    load_r5 10270
+    load_r5 10274
+    load_r5 10278
+    load_r5 1027C
+    load_r5 10280
+    load_r5 10284
+    load_r5 10288
+    load_r5 1028C
+    load_r5 10290
+    load_r5 10294
+    load_r5 10298
+    load_r5 1029C
+    load_r5 102A0
+    load_r5 102A4
+    load_r5 102A8
+    load_r5 102AC
+    load_r5 102B0
+    load_r5 102B4
+    load_r5 102B8
+    load_r5 102BC
+    load_r5 102C0
+    load_r5 102C4
+    load_r5 102C8
+    load_r5 102CC
+    load_r5 102D0
+    load_r5 102D4
+    load_r5 102D8
+    load_r5 102DC
+    load_r5 102E0
+    load_r5 102E4
+    load_r5 102E8
+    load_r5 102EC
+    load_r5 102F0
+    load_r5 102F4
+    load_r5 102F8
+    load_r5 102FC
+    load_r5 10300
+    load_r5 10304
+    load_r5 10308
+    load_r5 1030C
+    load_r5 10310
+    load_r5 10314
+    load_r5 10318
+    load_r5 1031C
+    load_r5 10320
+    load_r5 10324
+    load_r5 10328
+    load_r5 1032C
+    load_r5 10330
+    load_r5 10334
+    load_r5 10338
+    load_r5 1033C
+    load_r5 10340
+    load_r5 10344
+    load_r5 10348
+    load_r5 1034C
+    load_r5 10350
+    load_r5 10354
+    load_r5 10358
+    load_r5 1035C
+    load_r5 10360
+    load_r5 10364
+    load_r5 10368
+    load_r5 1036C
+    load_r5 10370
+    load_r5 10374
+    load_r5 10378
+    load_r5 1037C
+    load_r5 10380
+    load_r5 10384
+    load_r5 10388
+    load_r5 1038C
+    load_r5 10390
+    load_r5 10394
+    load_r5 10398
+    load_r5 1039C
+    load_r5 103A0
+    load_r5 103A4
+    load_r5 103A8
+    load_r5 103AC
+    load_r5 103B0
+    load_r5 103B4
+    load_r5 103B8
+    load_r5 103BC
+    load_r5 103C0
+    load_r5 103C4
+    load_r5 103C8
+    load_r5 103CC
+    load_r5 103D0
+    load_r5 103D4
+    load_r5 103D8
+    load_r5 103DC
+    load_r5 103E0
+    load_r5 103E4
+
+mark:
+    mark_r4 10000
+    mark_r4 10004
+    mark_r4 10008
+    mark_r4 1000C
+    mark_r4 10010
+    mark_r4 10014
+    mark_r4 10018
+    mark_r4 1001C
+    mark_r4 10020
+    mark_r4 10024
+    mark_r4 10028
+    mark_r4 1002C
+    mark_r4 10030
+    mark_r4 10034
+    mark_r4 10038
+    mark_r4 1003C
+    mark_r4 10040
+    mark_r4 10044
+    mark_r4 10048
+    mark_r4 1004C
+    mark_r4 10050
+    mark_r4 10054
+    mark_r4 10058
+    mark_r4 1005C
+    mark_r4 10060
+    mark_r4 10064
+    mark_r4 10068
+    mark_r4 1006C
+    mark_r4 10070
+    mark_r4 10074
+    mark_r4 10078
+    mark_r4 1007C
+    mark_r4 10080
+    mark_r4 10084
+    mark_r4 10088
+    mark_r4 1008C
+    mark_r4 10090
+    mark_r4 10094
+    mark_r4 10098
+    mark_r4 1009C
+    mark_r4 100A0
+    mark_r4 100A4
+    mark_r4 100A8
+    mark_r4 100AC
+    mark_r4 100B0
+    mark_r4 100B4
+    mark_r4 100B8
+    mark_r4 100BC
+    mark_r4 100C0
+    mark_r4 100C4
+    mark_r4 100C8
+    mark_r4 100CC
+    mark_r4 100D0
+    mark_r4 100D4
+    mark_r4 100D8
+    mark_r4 100DC
+    mark_r4 100E0
+    mark_r4 100E4
+    mark_r4 100E8
+    mark_r4 100EC
+    mark_r4 100F0
+    mark_r4 100F4
+    mark_r4 100F8
+    mark_r4 100FC
+    mark_r4 10100
+    mark_r4 10104
+    mark_r4 10108
+    mark_r4 1010C
+    mark_r4 10110
+    mark_r4 10114
+    mark_r4 10118
+    mark_r4 1011C
+    mark_r4 10120
+    mark_r4 10124
+    mark_r4 10128
+    mark_r4 1012C
+    mark_r4 10130
+    mark_r4 10134
+    mark_r4 10138
+    mark_r4 1013C
+    mark_r4 10140
+    mark_r4 10144
+    mark_r4 10148
+    mark_r4 1014C
+    mark_r4 10150
+    mark_r4 10154
+    mark_r4 10158
+    mark_r4 1015C
+    mark_r4 10160
+    mark_r4 10164
+    mark_r4 10168
+    mark_r4 1016C
+    mark_r4 10170
+    mark_r4 10174
+    mark_r4 10178
+    mark_r4 1017C
+    mark_r4 10180
+    mark_r4 10184
+    mark_r4 10188
+    mark_r4 1018C
+    mark_r4 10190
+    mark_r4 10194
+    mark_r4 10198
+    mark_r4 1019C
+    mark_r4 101A0
+    mark_r4 101A4
+    mark_r4 101A8
+    mark_r4 101AC
+    mark_r4 101B0
+    mark_r4 101B4
+    mark_r4 101B8
+    mark_r4 101BC
+    mark_r4 101C0
+    mark_r4 101C4
+    mark_r4 101C8
+    mark_r4 101CC
+    mark_r4 101D0
+    mark_r4 101D4
+    mark_r4 101D8
+    mark_r4 101DC
+    mark_r4 101E0
+    mark_r4 101E4
+    mark_r4 101E8
+    mark_r4 101EC
+    mark_r4 101F0
+    mark_r4 101F4
+    mark_r4 101F8
+    mark_r4 101FC
+    mark_r4 10200
+    mark_r4 10204
+    mark_r4 10208
+    mark_r4 1020C
+    mark_r4 10210
+    mark_r4 10214
+    mark_r4 10218
+    mark_r4 1021C
+    mark_r4 10220
+    mark_r4 10224
+    mark_r4 10228
+    mark_r4 1022C
+    mark_r4 10230
+    mark_r4 10234
+    mark_r4 10238
+    mark_r4 1023C
+    mark_r4 10240
+    mark_r4 10244
+    mark_r4 10248
+    mark_r4 1024C
+    mark_r4 10250
+    mark_r4 10254
+    mark_r4 10258
+    mark_r4 1025C
+    mark_r4 10260
+    mark_r4 10264
+    mark_r4 10268
+    mark_r4 1026C
+    mark_r4 10270
+    mark_r4 10274
+    mark_r4 10278
+    mark_r4 1027C
+    mark_r4 10280
+    mark_r4 10284
+    mark_r4 10288
+    mark_r4 1028C
+    mark_r4 10290
+    mark_r4 10294
+    mark_r4 10298
+    mark_r4 1029C
+    mark_r4 102A0
+    mark_r4 102A4
+    mark_r4 102A8
+    mark_r4 102AC
+    mark_r4 102B0
+    mark_r4 102B4
+    mark_r4 102B8
+    mark_r4 102BC
+    mark_r4 102C0
+    mark_r4 102C4
+    mark_r4 102C8
+    mark_r4 102CC
+    mark_r4 102D0
+    mark_r4 102D4
+    mark_r4 102D8
+    mark_r4 102DC
+    mark_r4 102E0
+    mark_r4 102E4
+    mark_r4 102E8
+    mark_r4 102EC
+    mark_r4 102F0
+    mark_r4 102F4
+    mark_r4 102F8
+    mark_r4 102FC
+    mark_r4 10300
+    mark_r4 10304
+    mark_r4 10308
+    mark_r4 1030C
+    mark_r4 10310
+    mark_r4 10314
+    mark_r4 10318
+    mark_r4 1031C
+    mark_r4 10320
+    mark_r4 10324
+    mark_r4 10328
+    mark_r4 1032C
+    mark_r4 10330
+    mark_r4 10334
+    mark_r4 10338
+    mark_r4 1033C
+    mark_r4 10340
+    mark_r4 10344
+    mark_r4 10348
+    mark_r4 1034C
+    mark_r4 10350
+    mark_r4 10354
+    mark_r4 10358
+    mark_r4 1035C
+    mark_r4 10360
+    mark_r4 10364
+    mark_r4 10368
+    mark_r4 1036C
+    mark_r4 10370
+    mark_r4 10374
+    mark_r4 10378
+    mark_r4 1037C
+    mark_r4 10380
+    mark_r4 10384
+    mark_r4 10388
+    mark_r4 1038C
+    mark_r4 10390
+    mark_r4 10394
+    mark_r4 10398
+    mark_r4 1039C
+    mark_r4 103A0
+    mark_r4 103A4
+    mark_r4 103A8
+    mark_r4 103AC
+    mark_r4 103B0
+    mark_r4 103B4
+    mark_r4 103B8
+    mark_r4 103BC
+    mark_r4 103C0
+    mark_r4 103C4
+    mark_r4 103C8
+    mark_r4 103CC
+    mark_r4 103D0
+    mark_r4 103D4
+    mark_r4 103D8
+    mark_r4 103DC
+    mark_r4 103E0
+    mark_r4 103E4
+    ; The entries beyond the limit.
+    beyond_50
+    beyond_50
+    beyond_50
+    beyond_50
+    beyond_50
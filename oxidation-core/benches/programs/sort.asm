@@ -0,0 +1,221 @@
+; Bubble sort an array of 32 values, each of which is less than 64.
+;
+; The instruction set has neither indexed addressing nor any comparison other than
+; equality. Arrays are therefore accessed through tables of subroutines, one for each
+; element, that are called through a computed address, and values are ordered by
+; counting from one towards the other. Each entry of a table is nine bytes long.
+;
+; R1 holds the offset of the entry for the current element within each table. The
+; tables that access the following element begin at the second element.
+
+; Jump to a label unconditionally, overwriting the accumulator.
+.macro jump target
+    mov 0, AC
+    jne 1, [target]
+.endm
+
+.macro load_r5 address
+    mov [address], R5
+    ret
+.endm
+
+.macro load_r6 address
+    mov [address], R6
+    ret
+.endm
+
+.macro store_r5 address
+    mov R5, [address]
+    ret
+.endm
+
+.macro store_r6 address
+    mov R6, [address]
+    ret
+.endm
+
+.sub main
+    mov 0, R3
+pass:
+    mov 0, R1
+    mov 0, R2
+compare:
+    add load_first, R1
+    call AC
+    add load_second, R1
+    call AC
+    call [greater]
+    mov R7, AC
+    jeq 0, [next]
+    add store_first, R1
+    call AC
+    add store_second, R1
+    call AC
+next:
+    add 9, R1
+    mov AC, R1
+    add 1, R2
+    mov AC, R2
+    jne 0d31, [compare]
+    add 1, R3
+    mov AC, R3
+    jne 0d31, [pass]
+    hlt
+
+; Set R7 to one if R5 is greater than R6, by counting upwards from R5.
+.sub greater
+    mov R5, R8
+count:
+    mov R8, AC
+    jeq R6, [not_greater]
+    jeq 0d64, [is_greater]
+    add 1, R8
+    mov AC, R8
+    jump count
+is_greater:
+    mov 1, R7
+    ret
+not_greater:
+    mov 0, R7
+    ret
+
+load_first:
+    load_r5 10000
+    load_r5 10004
+    load_r5 10008
+    load_r5 1000C
+    load_r5 10010
+    load_r5 10014
+    load_r5 10018
+    load_r5 1001C
+    load_r5 10020
+    load_r5 10024
+    load_r5 10028
+    load_r5 1002C
+    load_r5 10030
+    load_r5 10034
+    load_r5 10038
+    load_r5 1003C
+    load_r5 10040
+    load_r5 10044
+    load_r5 10048
+    load_r5 1004C
+    load_r5 10050
+    load_r5 10054
+    load_r5 10058
+    load_r5 1005C
+    load_r5 10060
+    load_r5 10064
+    load_r5 10068
+    load_r5 1006C
+    load_r5 10070
+    load_r5 10074
+    load_r5 10078
+
+load_second:
+    load_r6 10004
+    load_r6 10008
+    load_r6 1000C
+    load_r6 10010
+    load_r6 10014
+    load_r6 10018
+    load_r6 1001C
+    load_r6 10020
+    load_r6 10024
+    load_r6 10028
+    load_r6 1002C
+    load_r6 10030
+    load_r6 10034
+    load_r6 10038
+    load_r6 1003C
+    load_r6 10040
+    load_r6 10044
+    load_r6 10048
+    load_r6 1004C
+    load_r6 10050
+    load_r6 10054
+    load_r6 10058
+    load_r6 1005C
+    load_r6 10060
+    load_r6 10064
+    load_r6 10068
+    load_r6 1006C
+    load_r6 10070
+    load_r6 10074
+    load_r6 10078
+    load_r6 1007C
+
+; The values are swapped by storing the second to the current element.
+store_first:
+    store_r6 10000
+    store_r6 10004
+    store_r6 10008
+    store_r6 1000C
+    store_r6 10010
+    store_r6 10014
+    store_r6 10018
+    store_r6 1001C
+    store_r6 10020
+    store_r6 10024
+    store_r6 10028
+    store_r6 1002C
+    store_r6 10030
+    store_r6 10034
+    store_r6 10038
+    store_r6 1003C
+    store_r6 10040
+    store_r6 10044
+    store_r6 10048
+    store_r6 1004C
+    store_r6 10050
+    store_r6 10054
+    store_r6 10058
+    store_r6 1005C
+    store_r6 10060
+    store_r6 10064
+    store_r6 10068
+    store_r6 1006C
+    store_r6 10070
+    store_r6 10074
+    store_r6 10078
+
+store_second:
+    store_r5 10004
+    store_r5 10008
+    store_r5 1000C
+    store_r5 10010
+    store_r5 10014
+    store_r5 10018
+    store_r5 1001C
+    store_r5 10020
+    store_r5 10024
+    store_r5 10028
+    store_r5 1002C
+    store_r5 10030
+    store_r5 10034
+    store_r5 10038
+    store_r5 1003C
+    store_r5 10040
+    store_r5 10044
+    store_r5 10048
+    store_r5 1004C
+    store_r5 10050
+    store_r5 10054
+    store_r5 10058
+    store_r5 1005C
+    store_r5 10060
+    store_r5 10064
+    store_r5 10068
+    store_r5 1006C
+    store_r5 10070
+    store_r5 10074
+    store_r5 10078
+    store_r5 1007C
+
+.data
+.org 10000
+values:
+    .i32 0x1C, 0x04, 0x25, 0x2A, 0x1F, 0x2D, 0x1D, 0x1A
+    .i32 0x1A, 0x25, 0x2C, 0x1B, 0x1E, 0x1F, 0x13, 0x30
+    .i32 0x39, 0x2C, 0x3D, 0x10, 0x3F, 0x19, 0x2F, 0x20
+    .i32 0x1D, 0x04, 0x2C, 0x34, 0x1D, 0x02, 0x2B, 0x06
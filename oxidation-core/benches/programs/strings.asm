@@ -0,0 +1,343 @@
+; Reverse a string of 128 characters, counting the occurrences of 'a' within it.
+;
+; The instruction set has neither indexed addressing nor any comparison other than
+; equality. Arrays are therefore accessed through tables of subroutines, one for each
+; element, that are called through a computed address, and values are ordered by
+; counting from one towards the other. Each entry of a table is nine bytes long.
+;
+; R1 holds the offset of the entry for the output character, R2 the length of the
+; string, R3 the offset of the entry for the input character and R6 the count.
+
+; Jump to a label unconditionally, overwriting the accumulator.
+.macro jump target
+    mov 0, AC
+    jne 1, [target]
+.endm
+
+.macro load_r5 address
+    mov [address], R5
+    ret
+.endm
+
+.macro store_r5 address
+    mov R5, [address]
+    ret
+.endm
+
+.sub main
+    mov 0, R1
+    mov 0, R2
+length:
+    add load, R1
+    call AC
+    mov R5, AC
+    jeq 0, [reverse]
+    add 9, R1
+    mov AC, R1
+    add 1, R2
+    mov AC, R2
+    jump length
+reverse:
+    mov 0, R3
+    mov 0, R4
+    mov 0, R6
+character:
+    add -9, R1
+    mov AC, R1
+    add load, R3
+    call AC
+    add store, R1
+    call AC
+    mov R5, AC
+    jne 'a', [skip]
+    add 1, R6
+    mov AC, R6
+skip:
+    add 9, R3
+    mov AC, R3
+    add 1, R4
+    mov AC, R4
+    jne R2, [character]
+    hlt
+
+load:
+    load_r5 10000
+    load_r5 10004
+    load_r5 10008
+    load_r5 1000C
+    load_r5 10010
+    load_r5 10014
+    load_r5 10018
+    load_r5 1001C
+    load_r5 10020
+    load_r5 10024
+    load_r5 10028
+    load_r5 1002C
+    load_r5 10030
+    load_r5 10034
+    load_r5 10038
+    load_r5 1003C
+    load_r5 10040
+    load_r5 10044
+    load_r5 10048
+    load_r5 1004C
+    load_r5 10050
+    load_r5 10054
+    load_r5 10058
+    load_r5 1005C
+    load_r5 10060
+    load_r5 10064
+    load_r5 10068
+    load_r5 1006C
+    load_r5 10070
+    load_r5 10074
+    load_r5 10078
+    load_r5 1007C
+    load_r5 10080
+    load_r5 10084
+    load_r5 10088
+    load_r5 1008C
+    load_r5 10090
+    load_r5 10094
+    load_r5 10098
+    load_r5 1009C
+    load_r5 100A0
+    load_r5 100A4
+    load_r5 100A8
+    load_r5 100AC
+    load_r5 100B0
+    load_r5 100B4
+    load_r5 100B8
+    load_r5 100BC
+    load_r5 100C0
+    load_r5 100C4
+    load_r5 100C8
+    load_r5 100CC
+    load_r5 100D0
+    load_r5 100D4
+    load_r5 100D8
+    load_r5 100DC
+    load_r5 100E0
+    load_r5 100E4
+    load_r5 100E8
+    load_r5 100EC
+    load_r5 100F0
+    load_r5 100F4
+    load_r5 100F8
+    load_r5 100FC
+    load_r5 10100
+    load_r5 10104
+    load_r5 10108
+    load_r5 1010C
+    load_r5 10110
+    load_r5 10114
+    load_r5 10118
+    load_r5 1011C
+    load_r5 10120
+    load_r5 10124
+    load_r5 10128
+    load_r5 1012C
+    load_r5 10130
+    load_r5 10134
+    load_r5 10138
+    load_r5 1013C
+    load_r5 10140
+    load_r5 10144
+    load_r5 10148
+    load_r5 1014C
+    load_r5 10150
+    load_r5 10154
+    load_r5 10158
+    load_r5 1015C
+    load_r5 10160
+    load_r5 10164
+    load_r5 10168
+    load_r5 1016C
+    load_r5 10170
+    load_r5 10174
+    load_r5 10178
+    load_r5 1017C
+    load_r5 10180
+    load_r5 10184
+    load_r5 10188
+    load_r5 1018C
+    load_r5 10190
+    load_r5 10194
+    load_r5 10198
+    load_r5 1019C
+    load_r5 101A0
+    load_r5 101A4
+    load_r5 101A8
+    load_r5 101AC
+    load_r5 101B0
+    load_r5 101B4
+    load_r5 101B8
+    load_r5 101BC
+    load_r5 101C0
+    load_r5 101C4
+    load_r5 101C8
+    load_r5 101CC
+    load_r5 101D0
+    load_r5 101D4
+    load_r5 101D8
+    load_r5 101DC
+    load_r5 101E0
+    load_r5 101E4
+    load_r5 101E8
+    load_r5 101EC
+    load_r5 101F0
+    load_r5 101F4
+    load_r5 101F8
+    load_r5 101FC
+    load_r5 10200
+
+store:
+    store_r5 10204
+    store_r5 10208
+    store_r5 1020C
+    store_r5 10210
+    store_r5 10214
+    store_r5 10218
+    store_r5 1021C
+    store_r5 10220
+    store_r5 10224
+    store_r5 10228
+    store_r5 1022C
+    store_r5 10230
+    store_r5 10234
+    store_r5 10238
+    store_r5 1023C
+    store_r5 10240
+    store_r5 10244
+    store_r5 10248
+    store_r5 1024C
+    store_r5 10250
+    store_r5 10254
+    store_r5 10258
+    store_r5 1025C
+    store_r5 10260
+    store_r5 10264
+    store_r5 10268
+    store_r5 1026C
+    store_r5 10270
+    store_r5 10274
+    store_r5 10278
+    store_r5 1027C
+    store_r5 10280
+    store_r5 10284
+    store_r5 10288
+    store_r5 1028C
+    store_r5 10290
+    store_r5 10294
+    store_r5 10298
+    store_r5 1029C
+    store_r5 102A0
+    store_r5 102A4
+    store_r5 102A8
+    store_r5 102AC
+    store_r5 102B0
+    store_r5 102B4
+    store_r5 102B8
+    store_r5 102BC
+    store_r5 102C0
+    store_r5 102C4
+    store_r5 102C8
+    store_r5 102CC
+    store_r5 102D0
+    store_r5 102D4
+    store_r5 102D8
+    store_r5 102DC
+    store_r5 102E0
+    store_r5 102E4
+    store_r5 102E8
+    store_r5 102EC
+    store_r5 102F0
+    store_r5 102F4
+    store_r5 102F8
+    store_r5 102FC
+    store_r5 10300
+    store_r5 10304
+    store_r5 10308
+    store_r5 1030C
+    store_r5 10310
+    store_r5 10314
+    store_r5 10318
+    store_r5 1031C
+    store_r5 10320
+    store_r5 10324
+    store_r5 10328
+    store_r5 1032C
+    store_r5 10330
+    store_r5 10334
+    store_r5 10338
+    store_r5 1033C
+    store_r5 10340
+    store_r5 10344
+    store_r5 10348
+    store_r5 1034C
+    store_r5 10350
+    store_r5 10354
+    store_r5 10358
+    store_r5 1035C
+    store_r5 10360
+    store_r5 10364
+    store_r5 10368
+    store_r5 1036C
+    store_r5 10370
+    store_r5 10374
+    store_r5 10378
+    store_r5 1037C
+    store_r5 10380
+    store_r5 10384
+    store_r5 10388
+    store_r5 1038C
+    store_r5 10390
+    store_r5 10394
+    store_r5 10398
+    store_r5 1039C
+    store_r5 103A0
+    store_r5 103A4
+    store_r5 103A8
+    store_r5 103AC
+    store_r5 103B0
+    store_r5 103B4
+    store_r5 103B8
+    store_r5 103BC
+    store_r5 103C0
+    store_r5 103C4
+    store_r5 103C8
+    store_r5 103CC
+    store_r5 103D0
+    store_r5 103D4
+    store_r5 103D8
+    store_r5 103DC
+    store_r5 103E0
+    store_r5 103E4
+    store_r5 103E8
+    store_r5 103EC
+    store_r5 103F0
+    store_r5 103F4
+    store_r5 103F8
+    store_r5 103FC
+    store_r5 10400
+
+.data
+.org 10000
+text:
+    .i32 't', 'h', 'e', ' ', 'q', 'u', 'i', 'c'
+    .i32 'k', ' ', 'b', 'r', 'o', 'w', 'n', ' '
+    .i32 'f', 'o', 'x', ' ', 'j', 'u', 'm', 'p'
+    .i32 's', ' ', 'o', 'v', 'e', 'r', ' ', 'a'
+    .i32 ' ', 'l', 'a', 'z', 'y', ' ', 'd', 'o'
+    .i32 'g', ',', ' ', 'a', 'g', 'a', 'i', 'n'
+    .i32 ' ', 'a', 'n', 'd', ' ', 'a', 'g', 'a'
+    .i32 'i', 'n', '.', ' ', 't', 'h', 'e', ' '
+    .i32 'q', 'u', 'i', 'c', 'k', ' ', 'b', 'r'
+    .i32 'o', 'w', 'n', ' ', 'f', 'o', 'x', ' '
+    .i32 'j', 'u', 'm', 'p', 's', ' ', 'o', 'v'
+    .i32 'e', 'r', ' ', 'a', ' ', 'l', 'a', 'z'
+    .i32 'y', ' ', 'd', 'o', 'g', ',', ' ', 'a'
+    .i32 'g', 'a', 'i', 'n', ' ', 'a', 'n', 'd'
+    .i32 ' ', 'a', 'g', 'a', 'i', 'n', '.', ' '
+    .i32 't', 'h', 'e', ' ', 'q', 'u', 'i', 'c'
+    .i32 0
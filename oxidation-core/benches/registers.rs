@@ -0,0 +1,137 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use oxidation_core::cpu::RegisterCollection;
+use oxidation_core::execution::RunOutcome;
+use oxidation_core::instructions::enums::Instruction;
+use oxidation_core::registers::{Register, RegisterValue, Registers};
+use oxidation_core::security_context::SecurityContext;
+use oxidation_core::virtual_machine::VirtualMachine;
+
+/// The number of iterations performed by the arithmetic guest program.
+const ITERATIONS: i32 = 10_000;
+
+/// Returns a program that repeatedly updates a set of running totals, touching a
+/// register on almost every instruction.
+fn arithmetic_program() -> Vec<u8> {
+    let setup = [
+        Instruction::MovLitReg(0, Registers::R1),
+        Instruction::MovLitReg(0, Registers::R2),
+        Instruction::MovLitReg(0, Registers::R8),
+    ];
+    let loop_start: u32 = setup.iter().map(|i| i.encode().len() as u32).sum();
+
+    let body = [
+        Instruction::AddLitReg(3, Registers::R2),
+        Instruction::MovRegReg(Registers::AC, Registers::R2),
+        Instruction::AddLitReg(-1, Registers::R8),
+        Instruction::MovRegReg(Registers::AC, Registers::R8),
+        Instruction::MovRegReg(Registers::R2, Registers::R3),
+        Instruction::MovRegReg(Registers::R8, Registers::R4),
+        Instruction::AddLitReg(1, Registers::R1),
+        Instruction::MovRegReg(Registers::AC, Registers::R1),
+        Instruction::JmpNotEq(ITERATIONS, loop_start),
+        Instruction::HLT(),
+    ];
+
+    setup.iter().chain(&body).flat_map(|i| i.encode()).collect()
+}
+
+/// A reference register file that scans a `Vec` for each lookup, as the register
+/// collection did before it was indexed by register ID.
+struct VecRegisters {
+    registers: Vec<Register>,
+}
+
+impl VecRegisters {
+    fn new() -> Self {
+        Self {
+            registers: RegisterCollection::new().registers.to_vec(),
+        }
+    }
+
+    fn get_register_value(
+        &self,
+        register_id: Registers,
+        security_context: SecurityContext,
+    ) -> Option<RegisterValue> {
+        self.registers
+            .iter()
+            .find(|r| r.register_id == register_id)?
+            .get_value(security_context)
+            .ok()
+    }
+
+    fn set_register_value(
+        &mut self,
+        register_id: Registers,
+        value: RegisterValue,
+        security_context: SecurityContext,
+    ) -> Option<()> {
+        self.registers
+            .iter_mut()
+            .find(|r| r.register_id == register_id)?
+            .set_value(value, security_context)
+            .ok()
+    }
+}
+
+fn register_access(c: &mut Criterion) {
+    let all: Vec<Registers> = (0..=u8::MAX).map_while(Registers::from_u8).collect();
+    let mut group = c.benchmark_group("register_access");
+
+    let mut registers = RegisterCollection::new();
+    group.bench_function("array", |b| {
+        b.iter(|| {
+            for &reg in &all {
+                let value = registers
+                    .get_register_value(black_box(reg), SecurityContext::System)
+                    .unwrap();
+                registers
+                    .set_register_value(reg, value, SecurityContext::System)
+                    .unwrap();
+            }
+        })
+    });
+
+    let mut reference = VecRegisters::new();
+    group.bench_function("vec_find", |b| {
+        b.iter(|| {
+            for &reg in &all {
+                let value = reference
+                    .get_register_value(black_box(reg), SecurityContext::System)
+                    .unwrap();
+                reference
+                    .set_register_value(reg, value, SecurityContext::System)
+                    .unwrap();
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn arithmetic(c: &mut Criterion) {
+    let program = arithmetic_program();
+
+    c.bench_function("arithmetic_loop", |b| {
+        b.iter_batched(
+            || {
+                let mut vm = VirtualMachine::new(1_000, 10, false);
+                vm.load_program(&program, 0).unwrap();
+                vm
+            },
+            |mut vm| {
+                assert!(matches!(vm.run_for(u64::MAX), RunOutcome::Halted));
+                let r1 = vm
+                    .cpu
+                    .registers
+                    .get_register_value(Registers::R1, SecurityContext::User)
+                    .unwrap();
+                assert_eq!(r1, RegisterValue::I32(ITERATIONS));
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, register_access, arithmetic);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BatchSize, BenchmarkGroup, Criterion, Throughput,
+};
+use oxidation_core::execution::RunOutcome;
+
+mod programs;
+use programs::Workload;
+
+/// Benchmark running a workload to completion, after checking that it produces
+/// the expected results.
+fn bench_workload(group: &mut BenchmarkGroup<WallTime>, name: &str, workload: &Workload) {
+    let executed = workload.verify();
+    group.throughput(Throughput::Elements(executed));
+    group.bench_function(name, |b| {
+        b.iter_batched(
+            || workload.load(),
+            |mut vm| assert!(matches!(vm.run_for(u64::MAX), RunOutcome::Halted)),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn memory_access(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memory");
+    bench_workload(&mut group, "load_store", &programs::memory());
+    group.finish();
+}
+
+fn call_return(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calls");
+    bench_workload(&mut group, "nested", &programs::calls());
+    group.finish();
+}
+
+fn workloads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("workloads");
+    bench_workload(&mut group, "sort", &programs::sort());
+    bench_workload(&mut group, "sieve", &programs::sieve());
+    bench_workload(&mut group, "strings", &programs::strings());
+    group.finish();
+}
+
+criterion_group!(benches, memory_access, call_return, workloads);
+criterion_main!(benches);
@@ -0,0 +1,253 @@
+//! Code coverage of guest programs.
+//!
+//! Coverage records the number of times each instruction address was executed and,
+//! for every conditional branch, the number of times the branch was taken and not
+//! taken. Combined with a [`SourceMap`], the results may be written in the lcov
+//! tracefile format.
+
+use crate::cpu::CPU;
+use crate::execution::StepOutcome;
+use crate::instructions::enums::Instruction;
+use crate::memory::Memory;
+use crate::source_map::SourceMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+/// The number of times a conditional branch went in each direction.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct BranchCoverage {
+    /// The number of times the branch jumped to its target.
+    pub taken: u64,
+    /// The number of times the branch fell through to the next instruction.
+    pub not_taken: u64,
+}
+
+/// The execution counts of a single source line.
+#[derive(Debug, Default)]
+struct LineCoverage {
+    hits: u64,
+    branches: Vec<Option<BranchCoverage>>,
+}
+
+/// Records the instructions and branch directions executed by a virtual machine.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    hits: HashMap<u32, u64>,
+    branches: HashMap<u32, BranchCoverage>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a step.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - the CPU, in the state following the step.
+    /// * `outcome` - the outcome of the step.
+    pub fn record(&mut self, cpu: &CPU, outcome: &StepOutcome) {
+        if !outcome.executed() {
+            return;
+        }
+
+        *self.hits.entry(outcome.address).or_insert(0) += 1;
+
+        if let Some(ins) = &outcome.instruction {
+            if ins.is_conditional_branch() {
+                let fall_through = outcome.address + ins.size();
+                let branch = self.branches.entry(outcome.address).or_default();
+                match cpu.get_instruction_pointer() {
+                    Ok(ip) if ip != fall_through => branch.taken += 1,
+                    _ => branch.not_taken += 1,
+                }
+            }
+        }
+    }
+
+    /// Discard every count recorded so far.
+    pub fn reset(&mut self) {
+        self.hits.clear();
+        self.branches.clear();
+    }
+
+    /// Returns the number of times the instruction at an address was executed.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the instruction.
+    pub fn hits(&self, address: u32) -> u64 {
+        *self.hits.get(&address).unwrap_or(&0)
+    }
+
+    /// Returns the directions taken by the conditional branch at an address, if it was executed.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the branch instruction.
+    pub fn branch(&self, address: u32) -> Option<BranchCoverage> {
+        self.branches.get(&address).copied()
+    }
+
+    /// Returns the addresses of every executed instruction, in ascending order.
+    pub fn executed_addresses(&self) -> Vec<u32> {
+        let mut addresses: Vec<u32> = self.hits.keys().copied().collect();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    /// Write the coverage of every mapped source line as an lcov tracefile.
+    ///
+    /// Conditional branches are identified by decoding the mapped instructions from memory,
+    /// so that branches which were never executed are also reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `map` - the mapping between instruction addresses and source lines.
+    /// * `mem` - the memory holding the program.
+    /// * `test_name` - the name of the test, written to the `TN` record.
+    /// * `writer` - the destination of the tracefile.
+    pub fn write_lcov(
+        &self,
+        map: &SourceMap,
+        mem: &Memory,
+        test_name: &str,
+        writer: &mut dyn Write,
+    ) -> io::Result<()> {
+        let mut files: BTreeMap<&str, BTreeMap<u32, LineCoverage>> = BTreeMap::new();
+
+        for (address, location) in map.iter() {
+            let line = files
+                .entry(&location.file)
+                .or_default()
+                .entry(location.line)
+                .or_default();
+
+            // A line is executed whenever any of its instructions are.
+            line.hits = line.hits.max(self.hits(address));
+
+            if Instruction::decode_at(mem, address).is_ok_and(|i| i.is_conditional_branch()) {
+                line.branches.push(self.branch(address));
+            }
+        }
+
+        for (file, lines) in files {
+            writeln!(writer, "TN:{}", test_name)?;
+            writeln!(writer, "SF:{}", file)?;
+
+            let (mut found, mut hit) = (0, 0);
+            for (number, line) in &lines {
+                for (block, branch) in line.branches.iter().enumerate() {
+                    found += 2;
+                    match branch {
+                        Some(b) => {
+                            hit += (b.taken > 0) as u32 + (b.not_taken > 0) as u32;
+                            writeln!(writer, "BRDA:{},{},0,{}", number, block, b.taken)?;
+                            writeln!(writer, "BRDA:{},{},1,{}", number, block, b.not_taken)?;
+                        }
+                        None => {
+                            writeln!(writer, "BRDA:{},{},0,-", number, block)?;
+                            writeln!(writer, "BRDA:{},{},1,-", number, block)?;
+                        }
+                    }
+                }
+            }
+            writeln!(writer, "BRF:{}", found)?;
+            writeln!(writer, "BRH:{}", hit)?;
+
+            for (number, line) in &lines {
+                writeln!(writer, "DA:{},{}", number, line.hits)?;
+            }
+            writeln!(writer, "LF:{}", lines.len())?;
+            writeln!(
+                writer,
+                "LH:{}",
+                lines.values().filter(|l| l.hits > 0).count()
+            )?;
+            writeln!(writer, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registers::Registers;
+    use crate::virtual_machine::VirtualMachine;
+
+    #[test]
+    fn coverage_writes_lcov() {
+        // Each instruction is found on its own line of "test.asm", starting from line 1.
+        let program = [
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::JeqLit(1, 26),
+            Instruction::JeqReg(Registers::R1, 0),
+            Instruction::HLT(),
+            // 26
+            Instruction::JeqReg(Registers::R1, 0),
+            Instruction::HLT(),
+        ];
+
+        let mut map = SourceMap::new();
+        let mut address = 0;
+        for (i, ins) in program.iter().enumerate() {
+            map.insert(address, "test.asm", i as u32 + 1);
+            address += ins.size();
+        }
+
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+        vm.attach_coverage(Coverage::new());
+        vm.run_for(100);
+
+        let coverage = vm.detach_coverage().unwrap();
+        assert_eq!(coverage.executed_addresses(), vec![0, 7, 26, 33]);
+        assert_eq!(
+            coverage.branch(7),
+            Some(BranchCoverage {
+                taken: 1,
+                not_taken: 0
+            })
+        );
+        assert_eq!(coverage.branch(17), None);
+        assert_eq!(
+            coverage.branch(26),
+            Some(BranchCoverage {
+                taken: 0,
+                not_taken: 1
+            })
+        );
+
+        let mut lcov = Vec::new();
+        coverage
+            .write_lcov(&map, &vm.memory, "unit", &mut lcov)
+            .unwrap();
+
+        // The branch on line 3 is jumped over, so it is reported as never
+        // having been executed rather than as zero in either direction.
+        let expected = "TN:unit\n\
+                        SF:test.asm\n\
+                        BRDA:2,0,0,1\n\
+                        BRDA:2,0,1,0\n\
+                        BRDA:3,0,0,-\n\
+                        BRDA:3,0,1,-\n\
+                        BRDA:5,0,0,0\n\
+                        BRDA:5,0,1,1\n\
+                        BRF:6\n\
+                        BRH:2\n\
+                        DA:1,1\n\
+                        DA:2,1\n\
+                        DA:3,0\n\
+                        DA:4,0\n\
+                        DA:5,1\n\
+                        DA:6,1\n\
+                        LF:6\n\
+                        LH:4\n\
+                        end_of_record\n";
+        assert_eq!(String::from_utf8(lcov).unwrap(), expected);
+    }
+}
@@ -1,13 +1,22 @@
-use crate::instructions::enums::Instruction;
+use crate::decode_cache::DecodeCache;
+use crate::devices::IoBus;
+use crate::execution::{CostTable, RunOutcome, StepOutcome};
+use crate::fault::FaultKind;
+use crate::instructions::enums::{Instruction, OpCode};
 use crate::instructions::implementations as ins_imps;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+use crate::jit::Jit;
+use crate::memory::{Memory, MemoryError};
 use crate::registers::*;
 use crate::security_context::SecurityContext;
+use crate::snapshot::{self, SnapshotReader, SnapshotWriter};
 use log::trace;
-use snafu::{ensure, Snafu};
+use snafu::{ensure, ResultExt, Snafu};
 
 type Result<T, E = CpuError> = std::result::Result<T, E>;
 
 #[derive(Debug, Snafu)]
+#[snafu(visibility = "pub(crate)")]
 pub enum CpuError {
     #[snafu(display("no memory sequence ID was specified"))]
     MemorySequenceIdNotSet,
@@ -19,28 +28,81 @@ pub enum CpuError {
         "an attempt was made to set a register with a value type that it cannot support"
     ))]
     InvalidRegisterValueType,
+    #[snafu(display("an invalid opcode ({:#06X}) was encountered", opcode))]
+    InvalidOpCode { opcode: i16 },
+    #[snafu(display("the opcode {:?} is not supported by the CPU", opcode))]
+    UnimplementedOpCode { opcode: OpCode },
+    #[snafu(display("an instruction extended beyond the end of the executable region"))]
+    TruncatedInstruction,
+    #[snafu(display(
+        "the instruction pointer ({:#010X}) lies outside of the executable region",
+        address
+    ))]
+    InstructionPointerOutOfBounds { address: u32 },
+    #[snafu(display("{}", source))]
+    MemoryFault { source: MemoryError },
+    #[snafu(display("the stack overflowed while pushing to address {:#010X}", address))]
+    StackOverflow { address: u32 },
+    #[snafu(display("an attempt was made to pop from an empty stack"))]
+    StackUnderflow,
+    #[snafu(display("no device is attached to the I/O port {:#04X}", port))]
+    InvalidDevicePort { port: u8 },
+    #[snafu(display(
+        "the replay diverged from the recording at instruction {} ({:#010X}): {}",
+        instruction,
+        address,
+        reason
+    ))]
+    ReplayDivergence {
+        instruction: u64,
+        address: u32,
+        reason: String,
+    },
+    #[snafu(display("a fault occurred while handling a fault: {}", source))]
+    DoubleFault { source: Box<CpuError> },
+}
+
+bitflags! {
+    /// The flags held within the flags register.
+    #[derive(Default)]
+    pub struct CpuFlags: u32 {
+        /// Set while a fault is being handled by the fault handler.
+        const FAULT = 1 << 0;
+    }
 }
 
 #[derive(Debug)]
 pub struct RegisterCollection {
-    pub registers: Vec<Register>,
+    /// The registers, each held at the index given by its register ID.
+    pub registers: [Register; Registers::COUNT],
+    write_log: Option<Vec<RegisterWrite>>,
 }
 
 pub struct CPU {
     exec_mem_seq_id: i16,
     is_halted: bool,
     pub registers: RegisterCollection,
+    pub cost_table: CostTable,
+    pub io: IoBus,
+    pub decode_cache: DecodeCache,
+    #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+    pub jit: Jit,
+    cycles: u64,
+    instructions_executed: u64,
+}
+
+impl Default for RegisterCollection {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RegisterCollection {
     pub fn new() -> Self {
-        let mut rc = Self {
-            registers: Vec::new(),
-        };
-
-        rc.initialize_registers();
-
-        rc
+        Self {
+            registers: Self::initial_registers(),
+            write_log: None,
+        }
     }
 
     /// Returns a reference to the value of the specific register.
@@ -54,16 +116,12 @@ impl RegisterCollection {
         register_id: Registers,
         security_context: SecurityContext,
     ) -> Result<&RegisterValue> {
-        match self.get_register_ref(register_id) {
-            Err(e) => Err(e),
-            Ok(r) => match r.get_value_ref(security_context) {
-                Ok(val) => {
-                    return Ok(&val);
-                }
-                Err(_) => {
-                    return Err(CpuError::RegisterAccessViolation);
-                }
-            },
+        match self
+            .get_register_ref(register_id)
+            .get_value_ref(security_context)
+        {
+            Ok(val) => Ok(val),
+            Err(_) => Err(CpuError::RegisterAccessViolation),
         }
     }
 
@@ -78,16 +136,12 @@ impl RegisterCollection {
         register_id: Registers,
         security_context: SecurityContext,
     ) -> Result<RegisterValue> {
-        match self.get_register_ref(register_id) {
-            Err(e) => Err(e),
-            Ok(r) => match r.get_value(security_context) {
-                Ok(val) => {
-                    return Ok(val);
-                }
-                Err(_) => {
-                    return Err(CpuError::RegisterAccessViolation);
-                }
-            },
+        match self
+            .get_register_ref(register_id)
+            .get_value(security_context)
+        {
+            Ok(val) => Ok(val),
+            Err(_) => Err(CpuError::RegisterAccessViolation),
         }
     }
 
@@ -104,73 +158,158 @@ impl RegisterCollection {
         value: RegisterValue,
         security_context: SecurityContext,
     ) -> Result<()> {
-        match self.get_register_mut_ref(register_id) {
-            Err(e) => Err(e),
-            Ok(r) => match r.set_value(value, security_context) {
-                Ok(_) => {
-                    return Ok(());
-                }
-                Err(_) => {
-                    return Err(CpuError::RegisterAccessViolation);
-                }
-            },
+        let register = self.get_register_mut_ref(register_id);
+        let old_value = register.get_value_unchecked();
+        if register.set_value(value, security_context).is_err() {
+            return Err(CpuError::RegisterAccessViolation);
+        }
+
+        if let Some(log) = &mut self.write_log {
+            log.push(RegisterWrite {
+                register_id,
+                old_value,
+                new_value: value,
+            });
         }
+
+        Ok(())
+    }
+
+    /// Sets the value of the specific register without validating access or recording the write.
+    ///
+    /// # Arguments
+    ///
+    /// * `register_id` - the ID of the register.
+    /// * `value` - the value to which the register should be set.
+    pub(crate) fn restore_register_value(&mut self, register_id: Registers, value: RegisterValue) {
+        self.get_register_mut_ref(register_id)
+            .set_value_unchecked(value);
+    }
+
+    /// Begin recording every write made to a register, discarding any previous records.
+    pub fn start_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stop recording writes made to the registers, returning those that were recorded.
+    pub fn take_write_log(&mut self) -> Vec<RegisterWrite> {
+        self.write_log.take().unwrap_or_default()
     }
 
     /// Returns a reference to the register with the ID field that matches the specified ID.
+    /// Every register is held within the collection, so this cannot fail.
     ///
     /// # Arguments
     ///
     /// * `register_id` - the ID of the register.
-    pub fn get_register_ref(&self, register_id: Registers) -> Result<&Register> {
-        let reg = self.registers.iter().find(|r| r.register_id == register_id);
-        if let Some(r) = reg {
-            Ok(r)
-        } else {
-            Err(CpuError::InvalidRegisterId)
-        }
+    pub fn get_register_ref(&self, register_id: Registers) -> &Register {
+        &self.registers[register_id as usize]
     }
 
     /// Returns a mutable reference to the register with the ID field that matches the specified ID.
+    /// Every register is held within the collection, so this cannot fail.
     ///
     /// # Arguments
     ///
     /// * `register_id` - the ID of the register.
-    pub fn get_register_mut_ref(&mut self, register_id: Registers) -> Result<&mut Register> {
-        let reg = self
-            .registers
-            .iter_mut()
-            .find(|r| r.register_id == register_id);
-        if let Some(r) = reg {
-            Ok(r)
-        } else {
-            Err(CpuError::InvalidRegisterId)
+    pub fn get_register_mut_ref(&mut self, register_id: Registers) -> &mut Register {
+        &mut self.registers[register_id as usize]
+    }
+
+    /// Write the complete state of the registers into a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the snapshot writer.
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) -> snapshot::Result<()> {
+        writer.write_u32(self.registers.len() as u32)?;
+        for reg in &self.registers {
+            writer.write_u8(reg.register_id as u8)?;
+            writer.write_u8(reg.get_access_flags().bits())?;
+            writer.write_register_value(reg.get_value_unchecked())?;
+        }
+
+        Ok(())
+    }
+
+    /// Create a register collection from the state held within a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - the snapshot reader.
+    pub(crate) fn read_snapshot(reader: &mut SnapshotReader) -> snapshot::Result<Self> {
+        let count = reader.read_u32()?;
+        if count as usize != Registers::COUNT {
+            return snapshot::invalid(format!("expected {} registers", Registers::COUNT));
+        }
+
+        let mut registers = Self::initial_registers();
+        let mut restored = [false; Registers::COUNT];
+        for _ in 0..count {
+            let register_id = match Registers::from_u8(reader.read_u8()?) {
+                Some(r) => r,
+                None => return snapshot::invalid("invalid register ID".to_string()),
+            };
+            let access = match RegisterAccess::from_bits(reader.read_u8()?) {
+                Some(a) => a,
+                None => return snapshot::invalid("invalid register access flags".to_string()),
+            };
+            let value = reader.read_register_value()?;
+
+            if restored[register_id as usize] {
+                return snapshot::invalid(format!("duplicate register {}", register_id));
+            }
+            // The access flags of a register never change, so they must match its initial flags.
+            if access != registers[register_id as usize].get_access_flags() {
+                return snapshot::invalid(format!(
+                    "invalid access flags for register {}",
+                    register_id
+                ));
+            }
+            restored[register_id as usize] = true;
+            registers[register_id as usize] = Register::new(access, register_id, value);
         }
+
+        Ok(Self {
+            registers,
+            write_log: None,
+        })
     }
 
-    /// Load and initialize all of the registers required by the CPU.
-    fn initialize_registers(&mut self) {
+    /// Returns all of the registers required by the CPU, in their initial state.
+    fn initial_registers() -> [Register; Registers::COUNT] {
         let rw = RegisterAccess::R | RegisterAccess::W;
+        // The flags, instruction pointer and stack pointer may only be modified by the system.
+        let prw = RegisterAccess::R | RegisterAccess::PW;
+
+        let registers = [
+            Register::new(rw, Registers::R1, RegisterValue::I32(0)),
+            Register::new(rw, Registers::R2, RegisterValue::I32(0)),
+            Register::new(rw, Registers::R3, RegisterValue::I32(0)),
+            Register::new(rw, Registers::R4, RegisterValue::I32(0)),
+            Register::new(rw, Registers::R5, RegisterValue::I32(0)),
+            Register::new(rw, Registers::R6, RegisterValue::I32(0)),
+            Register::new(rw, Registers::R7, RegisterValue::I32(0)),
+            Register::new(rw, Registers::R8, RegisterValue::I32(0)),
+            Register::new(rw, Registers::AC, RegisterValue::I32(0)),
+            Register::new(prw, Registers::FL, RegisterValue::I32(0)),
+            Register::new(prw, Registers::IP, RegisterValue::I32(0)),
+            Register::new(prw, Registers::SP, RegisterValue::I32(0)),
+            // The address of the fault handler, or zero if no handler is installed.
+            Register::new(rw, Registers::FH, RegisterValue::I32(0)),
+        ];
+        debug_assert!(registers
+            .iter()
+            .enumerate()
+            .all(|(i, r)| r.register_id as usize == i));
 
-        self.registers
-            .push(Register::new(rw, Registers::R1, RegisterValue::I32(0)));
-        self.registers
-            .push(Register::new(rw, Registers::R2, RegisterValue::I32(0)));
-        self.registers
-            .push(Register::new(rw, Registers::R3, RegisterValue::I32(0)));
-        self.registers
-            .push(Register::new(rw, Registers::R4, RegisterValue::I32(0)));
-        self.registers
-            .push(Register::new(rw, Registers::R5, RegisterValue::I32(0)));
-        self.registers
-            .push(Register::new(rw, Registers::R6, RegisterValue::I32(0)));
-        self.registers
-            .push(Register::new(rw, Registers::R7, RegisterValue::I32(0)));
-        self.registers
-            .push(Register::new(rw, Registers::R8, RegisterValue::I32(0)));
-
-        self.registers
-            .push(Register::new(rw, Registers::AC, RegisterValue::I32(0)));
+        registers
+    }
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -180,54 +319,649 @@ impl CPU {
             exec_mem_seq_id: -1,
             is_halted: false,
             registers: RegisterCollection::new(),
+            cost_table: CostTable::default(),
+            io: IoBus::new(),
+            decode_cache: DecodeCache::new(),
+            #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+            jit: Jit::new(),
+            cycles: 0,
+            instructions_executed: 0,
         }
     }
 
     /// Initialize the CPU.
     pub fn initialize(&mut self) {}
 
+    /// Set the memory region from which instructions are to be executed.
+    /// The instruction pointer is moved to the start of the region and
+    /// the stack pointer is moved to the top of the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory in which the region resides.
+    /// * `seq_id` - the sequence ID of the executable memory region.
+    pub fn set_executable_region(&mut self, mem: &Memory, seq_id: u32) -> Result<()> {
+        let start = mem.get_region_by_seq_id(seq_id).context(MemoryFault)?.start;
+
+        self.exec_mem_seq_id = seq_id as i16;
+        self.is_halted = false;
+        self.set_stack_pointer(mem.stack_end())?;
+        self.set_instruction_pointer(start)
+    }
+
+    /// Returns the sequence ID of the executable memory region, if one has been set.
+    pub fn get_executable_region_id(&self) -> Option<u32> {
+        if self.exec_mem_seq_id < 0 {
+            None
+        } else {
+            Some(self.exec_mem_seq_id as u32)
+        }
+    }
+
+    /// Write the complete state of the CPU into a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the snapshot writer.
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) -> snapshot::Result<()> {
+        writer.write_i16(self.exec_mem_seq_id)?;
+        writer.write_bool(self.is_halted)?;
+        writer.write_u64(self.cycles)?;
+        writer.write_u64(self.instructions_executed)?;
+
+        self.registers.write_snapshot(writer)
+    }
+
+    /// Create a CPU from the state held within a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - the snapshot reader.
+    pub(crate) fn read_snapshot(reader: &mut SnapshotReader) -> snapshot::Result<Self> {
+        let mut cpu = CPU::new();
+        cpu.exec_mem_seq_id = reader.read_i16()?;
+        cpu.is_halted = reader.read_bool()?;
+        cpu.cycles = reader.read_u64()?;
+        cpu.instructions_executed = reader.read_u64()?;
+        cpu.registers = RegisterCollection::read_snapshot(reader)?;
+
+        Ok(cpu)
+    }
+
+    /// Returns true if the CPU has been halted.
+    pub fn is_halted(&self) -> bool {
+        self.is_halted
+    }
+
+    /// Restore the halt state and counters of the CPU to earlier values.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_halted` - true if the CPU should be halted.
+    /// * `cycles` - the total number of cycles consumed.
+    /// * `instructions_executed` - the total number of instructions executed.
+    pub(crate) fn restore_state(
+        &mut self,
+        is_halted: bool,
+        cycles: u64,
+        instructions_executed: u64,
+    ) {
+        self.is_halted = is_halted;
+        self.cycles = cycles;
+        self.instructions_executed = instructions_executed;
+    }
+
+    /// Returns the total number of cycles consumed by the CPU.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the total number of instructions executed by the CPU.
+    pub fn instructions_executed(&self) -> u64 {
+        self.instructions_executed
+    }
+
+    /// Returns the current value of the instruction pointer.
+    pub fn get_instruction_pointer(&self) -> Result<u32> {
+        match self
+            .registers
+            .get_register_value(Registers::IP, SecurityContext::System)?
+        {
+            RegisterValue::I32(ip) => Ok(ip as u32),
+            _ => Err(CpuError::InvalidRegisterValueType),
+        }
+    }
+
+    /// Sets the value of the instruction pointer.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the new value of the instruction pointer.
+    pub fn set_instruction_pointer(&mut self, address: u32) -> Result<()> {
+        self.registers.set_register_value(
+            Registers::IP,
+            RegisterValue::I32(address as i32),
+            SecurityContext::System,
+        )
+    }
+
+    /// Returns the current value of the flags register.
+    pub fn get_flags(&self) -> Result<CpuFlags> {
+        match self
+            .registers
+            .get_register_value(Registers::FL, SecurityContext::System)?
+        {
+            RegisterValue::I32(fl) => Ok(CpuFlags::from_bits_truncate(fl as u32)),
+            _ => Err(CpuError::InvalidRegisterValueType),
+        }
+    }
+
+    /// Sets the value of the flags register.
+    ///
+    /// # Arguments
+    ///
+    /// * `flags` - the new flags.
+    pub fn set_flags(&mut self, flags: CpuFlags) -> Result<()> {
+        self.registers.set_register_value(
+            Registers::FL,
+            RegisterValue::I32(flags.bits() as i32),
+            SecurityContext::System,
+        )
+    }
+
+    /// Returns the current value of the stack pointer.
+    pub fn get_stack_pointer(&self) -> Result<u32> {
+        match self
+            .registers
+            .get_register_value(Registers::SP, SecurityContext::System)?
+        {
+            RegisterValue::I32(sp) => Ok(sp as u32),
+            _ => Err(CpuError::InvalidRegisterValueType),
+        }
+    }
+
+    /// Sets the value of the stack pointer.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the new value of the stack pointer.
+    pub fn set_stack_pointer(&mut self, address: u32) -> Result<()> {
+        self.registers.set_register_value(
+            Registers::SP,
+            RegisterValue::I32(address as i32),
+            SecurityContext::System,
+        )
+    }
+
+    /// Push a value onto the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory in which the stack resides.
+    /// * `value` - the value to be pushed.
+    pub fn push_stack(&mut self, mem: &mut Memory, value: i32) -> Result<()> {
+        let sp = self.get_stack_pointer()?;
+        let address = sp.wrapping_sub(4);
+        ensure!(
+            mem.stack_start()
+                .checked_add(4)
+                .is_some_and(|min| sp >= min)
+                && sp <= mem.stack_end(),
+            StackOverflow { address }
+        );
+
+        mem.write_i32(address, value, SecurityContext::System)
+            .context(MemoryFault)?;
+        self.set_stack_pointer(address)
+    }
+
+    /// Pop a value from the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory in which the stack resides.
+    pub fn pop_stack(&mut self, mem: &Memory) -> Result<i32> {
+        let sp = self.get_stack_pointer()?;
+        ensure!(
+            sp >= mem.stack_start() && sp.checked_add(4).is_some_and(|top| top <= mem.stack_end()),
+            StackUnderflow
+        );
+
+        let value = mem
+            .read_i32(sp, SecurityContext::System)
+            .context(MemoryFault)?;
+        self.set_stack_pointer(sp + 4)?;
+
+        Ok(value)
+    }
+
     /// Run the CPU until the program execution is complete.
-    pub fn run(&mut self) -> Result<bool> {
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    pub fn run(&mut self, mem: &mut Memory) -> RunOutcome {
         trace!("Currently in cpu::run.");
-        ensure!(self.exec_mem_seq_id > -1, MemorySequenceIdNotSet);
+
+        self.run_with(mem, None, |_, _| false, |_, _, _| None)
+    }
+
+    /// Run the CPU until the program execution is complete or until
+    /// executing the next instruction would exceed the cycle budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    /// * `budget` - the maximum number of cycles that may be consumed.
+    pub fn run_for(&mut self, mem: &mut Memory, budget: u64) -> RunOutcome {
+        trace!("Currently in cpu::run_for.");
+
+        self.run_with(mem, Some(budget), |_, _| false, |_, _, _| None)
+    }
+
+    /// Run the CPU until the program execution is complete or until the
+    /// predicate is satisfied.
+    ///
+    /// The predicate is evaluated before each instruction other than the first,
+    /// allowing execution to be resumed from a location at which it previously stopped.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    /// * `predicate` - the stop predicate.
+    pub fn run_until<F>(&mut self, mem: &mut Memory, predicate: F) -> RunOutcome
+    where
+        F: FnMut(&CPU, &Memory) -> bool,
+    {
+        trace!("Currently in cpu::run_until.");
+
+        self.run_with(mem, None, predicate, |_, _, _| None)
+    }
+
+    /// Run the CPU until the program execution is complete, the cycle budget
+    /// is exhausted, the predicate is satisfied or the observer requests a stop.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    /// * `budget` - the maximum number of cycles that may be consumed, if any.
+    /// * `predicate` - the stop predicate, see [`CPU::run_until`].
+    /// * `observer` - called after each step, returning an outcome if execution should stop.
+    pub fn run_with<P, O>(
+        &mut self,
+        mem: &mut Memory,
+        budget: Option<u64>,
+        mut predicate: P,
+        mut observer: O,
+    ) -> RunOutcome
+    where
+        P: FnMut(&CPU, &Memory) -> bool,
+        O: FnMut(&CPU, &Memory, &StepOutcome) -> Option<RunOutcome>,
+    {
+        if self.exec_mem_seq_id < 0 {
+            return RunOutcome::Fault(CpuError::MemorySequenceIdNotSet);
+        }
+
+        let mut consumed = 0;
+        let mut first = true;
 
         while !self.is_halted {
-            // TODO - execute instruction here.
-            self.is_halted = true;
+            if !first && predicate(self, mem) {
+                return RunOutcome::Breakpoint;
+            }
+            first = false;
+
+            let mut outcome = match self.budgeted_step(mem, budget.map(|b| b - consumed)) {
+                Some(outcome) => outcome,
+                None => return RunOutcome::BudgetExhausted,
+            };
+            consumed += outcome.cycles;
+
+            let stop = observer(self, mem, &outcome);
+            if let Some(e) = outcome.fault.take() {
+                return RunOutcome::Fault(e);
+            }
+            if let Some(stop) = stop {
+                return stop;
+            }
         }
 
-        Ok(true)
+        RunOutcome::Halted
+    }
+
+    /// Execute exactly one instruction, returning a description of its effects.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    pub fn step(&mut self, mem: &mut Memory) -> StepOutcome {
+        trace!("Currently in cpu::step.");
+
+        if self.is_halted {
+            let address = self.get_instruction_pointer().unwrap_or_default();
+            return StepOutcome::without_instruction(address, true, None);
+        }
+
+        // Without a budget, the instruction is always executed.
+        self.budgeted_step(mem, None)
+            .unwrap_or_else(|| unreachable!())
+    }
+
+    /// Run the CPU until the program is complete, the cycle budget is exhausted
+    /// or a fault occurs, executing hot blocks of instructions as compiled code.
+    /// Individual steps cannot be observed.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    /// * `budget` - the maximum number of cycles that may be consumed, if any.
+    #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+    pub fn run_compiled(&mut self, mem: &mut Memory, budget: Option<u64>) -> RunOutcome {
+        trace!("Currently in cpu::run_compiled.");
+
+        if self.exec_mem_seq_id < 0 {
+            return RunOutcome::Fault(CpuError::MemorySequenceIdNotSet);
+        }
+
+        let mut consumed = 0;
+        while !self.is_halted {
+            let remaining = budget.map(|b| b - consumed);
+
+            let exit = self.jit.run_block(
+                &mut self.decode_cache,
+                &mut self.registers,
+                &self.cost_table,
+                mem,
+                self.exec_mem_seq_id as u32,
+                remaining,
+            );
+            if let Some(exit) = exit {
+                self.cycles += exit.cycles;
+                self.instructions_executed += exit.instructions;
+                consumed += exit.cycles;
+                continue;
+            }
+
+            let mut outcome = match self.budgeted_step(mem, remaining) {
+                Some(outcome) => outcome,
+                None => return RunOutcome::BudgetExhausted,
+            };
+            consumed += outcome.cycles;
+
+            if let Some(e) = outcome.fault.take() {
+                return RunOutcome::Fault(e);
+            }
+        }
+
+        RunOutcome::Halted
+    }
+
+    /// Execute one instruction and deliver any fault it raises, unless the cost of the
+    /// instruction exceeds the remaining budget, in which case nothing is executed.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    /// * `remaining` - the number of cycles that may be consumed, if limited.
+    fn budgeted_step(&mut self, mem: &mut Memory, remaining: Option<u64>) -> Option<StepOutcome> {
+        let mut outcome = match self.fetch_decode(mem) {
+            Ok((address, ins)) => {
+                let cost = self.cost_table.get_cost(ins.opcode());
+                if remaining.is_some_and(|r| cost > r) {
+                    return None;
+                }
+
+                self.execute_step(mem, address, ins)
+            }
+            Err(e) => {
+                self.is_halted = true;
+
+                let address = self.get_instruction_pointer().unwrap_or_default();
+                StepOutcome::without_instruction(address, true, Some(e))
+            }
+        };
+
+        self.handle_fault(mem, &mut outcome);
+        Some(outcome)
+    }
+
+    /// Deliver the fault raised by a step, if any, to the fault handler.
+    ///
+    /// The registers and memory written while delivering the fault are
+    /// appended to those written by the step, so that it may be undone.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory in which the stack resides.
+    /// * `outcome` - the outcome of the step.
+    fn handle_fault(&mut self, mem: &mut Memory, outcome: &mut StepOutcome) {
+        let error = match outcome.fault.take() {
+            Some(e) => e,
+            None => return,
+        };
+
+        self.registers.start_write_log();
+        mem.start_write_log();
+
+        let result = self.deliver_fault(mem, outcome.address, error);
+
+        outcome
+            .register_writes
+            .extend(self.registers.take_write_log());
+        outcome.memory_writes.extend(mem.take_write_log());
+
+        match result {
+            Ok(e) => {
+                self.is_halted = false;
+                outcome.halted = false;
+                outcome.handled_fault = Some(e);
+            }
+            Err(e) => {
+                self.is_halted = true;
+                outcome.halted = true;
+                outcome.fault = Some(e);
+            }
+        }
     }
 
-    pub fn run_test(&mut self) -> Result<bool> {
+    /// Transfer control to the fault handler, returning the error if the fault
+    /// was delivered, or the error that should halt the CPU if it was not.
+    ///
+    /// The fault handler is found at the address held in the FH register, where zero
+    /// indicates that no handler is installed. The address of the faulting instruction,
+    /// the address associated with the fault (or zero) and the fault code are pushed
+    /// onto the stack, in that order, before the handler is entered. The handler should
+    /// pop the fault code and address before returning with `iret`.
+    ///
+    /// A fault that occurs while a fault is being handled is a double fault, which
+    /// always halts the CPU.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory in which the stack resides.
+    /// * `address` - the address of the faulting instruction.
+    /// * `error` - the error that caused the fault.
+    fn deliver_fault(
+        &mut self,
+        mem: &mut Memory,
+        address: u32,
+        error: CpuError,
+    ) -> Result<CpuError, CpuError> {
+        let (kind, _) = FaultKind::from_error(&error);
+        let code = match kind.code() {
+            Some(c) => c,
+            None => return Err(error),
+        };
+
+        let handler = match self
+            .registers
+            .get_register_value(Registers::FH, SecurityContext::System)
+        {
+            Ok(RegisterValue::I32(h)) if h != 0 => h as u32,
+            _ => return Err(error),
+        };
+
+        let flags = match self.get_flags() {
+            Ok(f) => f,
+            Err(_) => return Err(error),
+        };
+        if flags.contains(CpuFlags::FAULT) {
+            return Err(CpuError::DoubleFault {
+                source: Box::new(error),
+            });
+        }
+
+        // A stack overflow leaves no room for the fault frame,
+        // so the handler is instead given an empty stack.
+        if let FaultKind::StackOverflow { .. } = kind {
+            if let Err(e) = self.set_stack_pointer(mem.stack_end()) {
+                return Err(CpuError::DoubleFault {
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        let frame = [
+            address as i32,
+            kind.address().unwrap_or_default() as i32,
+            code as i32,
+        ];
+        for value in frame.iter() {
+            if let Err(e) = self.push_stack(mem, *value) {
+                return Err(CpuError::DoubleFault {
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        let entered = self
+            .set_flags(flags | CpuFlags::FAULT)
+            .and_then(|_| self.set_instruction_pointer(handler));
+        if let Err(e) = entered {
+            return Err(CpuError::DoubleFault {
+                source: Box::new(e),
+            });
+        }
+
+        Ok(error)
+    }
+
+    pub fn run_test(&mut self, mem: &mut Memory) -> Result<bool> {
         trace!("Currently in cpu::run.");
         //ensure!(self.exec_mem_seq_id > -1, MemorySequenceIdNotSet);
 
         let ins = Instruction::AddLitReg(123, Registers::R1);
 
-        println!("{:#?}", self.registers);
-        self.execute(ins);
-        println!("{:#?}", self.registers);
+        let ip = self.get_instruction_pointer()?;
+
+        self.execute(mem, ip, ins)?;
 
         Ok(true)
     }
 
-    fn fetch_decode(&mut self) {}
+    /// Fetch and decode the instruction found at the instruction pointer,
+    /// returning the address of the instruction along with the instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the instruction is to be read.
+    fn fetch_decode(&mut self, mem: &mut Memory) -> Result<(u32, Instruction)> {
+        ensure!(self.exec_mem_seq_id > -1, MemorySequenceIdNotSet);
 
-    fn execute(&mut self, ins: Instruction) -> Result<bool> {
+        let seq_id = self.exec_mem_seq_id as u32;
+        let region = mem.get_region_by_seq_id(seq_id).context(MemoryFault)?;
+        let ip = self.get_instruction_pointer()?;
+        ensure!(
+            region.contains(ip),
+            InstructionPointerOutOfBounds { address: ip }
+        );
+
+        Ok((ip, self.decode_cache.fetch(mem, seq_id, ip)?))
+    }
+
+    /// Execute a decoded instruction, recording the registers and memory written by it.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the program is to be executed.
+    /// * `address` - the address from which the instruction was decoded.
+    /// * `ins` - the instruction to be executed.
+    fn execute_step(&mut self, mem: &mut Memory, address: u32, ins: Instruction) -> StepOutcome {
+        // The instruction pointer is advanced before execution so that
+        // any instruction that modifies it will not be overridden.
+        if let Err(e) = self.set_instruction_pointer(address + ins.size()) {
+            self.is_halted = true;
+            return StepOutcome::without_instruction(address, true, Some(e));
+        }
+
+        self.registers.start_write_log();
+        mem.start_read_log();
+        mem.start_write_log();
+
+        let result = self.execute(mem, address, ins.clone());
+
+        let register_writes = self.registers.take_write_log();
+        let memory_reads = mem.take_read_log();
+        let memory_writes = mem.take_write_log();
+
+        let (cycles, fault) = match result {
+            Ok(_) => {
+                let cost = self.cost_table.get_cost(ins.opcode());
+                self.cycles += cost;
+                self.instructions_executed += 1;
+                (cost, None)
+            }
+            Err(e) => {
+                // The instruction pointer should identify the faulting instruction.
+                let _ = self.set_instruction_pointer(address);
+                (0, Some(e))
+            }
+        };
+
+        StepOutcome {
+            address,
+            instruction: Some(ins),
+            register_writes,
+            memory_reads,
+            memory_writes,
+            cycles,
+            halted: self.is_halted,
+            fault,
+            handled_fault: None,
+            watchpoint: None,
+        }
+    }
+
+    fn execute(&mut self, mem: &mut Memory, address: u32, ins: Instruction) -> Result<bool> {
         trace!("Currently in cpu::execute.");
-        println!("Executing: {:?}", ins);
+        trace!("Executing: {}", ins);
+
         let halt: Result<bool, CpuError> = match ins {
             Instruction::NOP() => Ok(false),
+            Instruction::MovLitReg(lit, reg) => ins_imps::mov_lit_reg(self, lit, reg),
+            Instruction::MovRegReg(src, dst) => ins_imps::mov_reg_reg(self, src, dst),
+            Instruction::MovRegMem(reg, addr) => ins_imps::mov_reg_mem(self, mem, reg, addr),
+            Instruction::MovMemReg(addr, reg) => ins_imps::mov_mem_reg(self, mem, addr, reg),
             Instruction::AddLitReg(lit, reg) => ins_imps::add_lit_reg(self, lit, reg),
+            Instruction::Out(reg, port) => ins_imps::out_reg_port(self, reg, port),
+            Instruction::In(port, reg) => ins_imps::in_port_reg(self, address, port, reg),
+            Instruction::PshLit(lit) => ins_imps::psh_lit(self, mem, lit),
+            Instruction::PshReg(reg) => ins_imps::psh_reg(self, mem, reg),
+            Instruction::Pop(reg) => ins_imps::pop_reg(self, mem, reg),
+            Instruction::CalLit(addr) => ins_imps::cal_lit(self, mem, addr),
+            Instruction::CalReg(reg) => ins_imps::cal_reg(self, mem, reg),
+            Instruction::Ret() => ins_imps::ret(self, mem),
+            Instruction::IRet() => ins_imps::iret(self, mem),
+            Instruction::JmpNotEq(lit, addr) => ins_imps::jmp_lit(self, lit, addr, false),
+            Instruction::JneReg(reg, addr) => ins_imps::jmp_reg(self, reg, addr, false),
+            Instruction::JeqLit(lit, addr) => ins_imps::jmp_lit(self, lit, addr, true),
+            Instruction::JeqReg(reg, addr) => ins_imps::jmp_reg(self, reg, addr, true),
             Instruction::HLT() => Ok(true),
         };
 
-        // In the event of an error we will instruct the CPU to halt.
-        self.is_halted = match halt {
-            Ok(b) => b,
-            Err(_) => true,
-        };
+        // In the event of an error we will instruct the CPU to halt. The error
+        // is returned so that it may be reported through the step outcome.
+        self.is_halted = !matches!(halt, Ok(false));
 
         halt
     }
@@ -0,0 +1,137 @@
+//! Debug information, relating the addresses of a program to its source.
+//!
+//! Debug information comprises the line table of a program, mapping the address
+//! of each instruction to the source line from which it was assembled, along with
+//! the labels and subroutines that were declared within the source.
+
+use crate::source_map::{SourceLocation, SourceMap};
+use crate::symbols::SymbolTable;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// The source line and function to which an address belongs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugLocation {
+    /// The source line from which the instruction was assembled, if known.
+    pub source: Option<SourceLocation>,
+    /// The address and name of the subroutine in which the address lies, if any.
+    pub function: Option<(u32, String)>,
+    /// The address being described.
+    pub address: u32,
+}
+
+impl fmt::Display for DebugLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.function {
+            Some((start, name)) if *start == self.address => write!(f, "{}", name)?,
+            Some((start, name)) => write!(f, "{}+{:#X}", name, self.address - start)?,
+            None => write!(f, "{:#010X}", self.address)?,
+        }
+        if let Some(source) = &self.source {
+            write!(f, " ({}:{})", source.file, source.line)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// The line table of the program.
+    pub lines: SourceMap,
+    /// The labels declared within the source.
+    pub labels: SymbolTable,
+    /// The subroutines declared within the source.
+    pub subroutines: SymbolTable,
+}
+
+impl DebugInfo {
+    pub fn new() -> Self {
+        Self {
+            lines: SourceMap::new(),
+            labels: SymbolTable::new(),
+            subroutines: SymbolTable::new(),
+        }
+    }
+
+    /// Returns the names of the source files referred to by the line table, in
+    /// ascending order.
+    pub fn files(&self) -> Vec<&str> {
+        let files: BTreeSet<&str> = self.lines.iter().map(|(_, l)| l.file.as_str()).collect();
+        files.into_iter().collect()
+    }
+
+    /// Returns the source line and function to which an address belongs. An address
+    /// within an instruction belongs to the line of the instruction, while an address
+    /// outside of the code of the program belongs to neither a line nor a function.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address to be described.
+    /// * `code` - the addresses of the code of the program.
+    pub fn locate(&self, address: u32, code: &RangeInclusive<u32>) -> DebugLocation {
+        if !code.contains(&address) {
+            return DebugLocation {
+                source: None,
+                function: None,
+                address,
+            };
+        }
+
+        DebugLocation {
+            source: self
+                .lines
+                .lookup(address)
+                .filter(|(a, _)| code.contains(a))
+                .map(|(_, l)| l.clone()),
+            function: self
+                .subroutines
+                .lookup(address)
+                .filter(|(a, _)| code.contains(a))
+                .map(|(a, name)| (a, name.to_string())),
+            address,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_located() {
+        let mut info = DebugInfo::new();
+        info.lines.insert(0, "main.asm", 3);
+        info.lines.insert(7, "main.asm", 4);
+        info.lines.insert(20, "lib.asm", 10);
+        info.subroutines.insert(0, "main".to_string());
+        info.subroutines.insert(20, "helper".to_string());
+        info.labels.insert(7, "loop".to_string());
+
+        assert_eq!(info.files(), vec!["lib.asm", "main.asm"]);
+
+        let code = 0..=29;
+        let location = info.locate(9, &code);
+        assert_eq!(
+            location.source,
+            Some(SourceLocation {
+                file: "main.asm".to_string(),
+                line: 4
+            })
+        );
+        assert_eq!(location.function, Some((0, "main".to_string())));
+        assert_eq!(location.to_string(), "main+0x9 (main.asm:4)");
+        assert_eq!(info.locate(20, &code).to_string(), "helper (lib.asm:10)");
+
+        // Addresses beyond the end of the code are not blamed on the last line.
+        let wild = info.locate(u32::MAX, &code);
+        assert_eq!(wild.source, None);
+        assert_eq!(wild.function, None);
+        assert_eq!(wild.to_string(), "0xFFFFFFFF");
+
+        let empty = DebugInfo::new().locate(5, &code);
+        assert_eq!(empty.source, None);
+        assert_eq!(empty.to_string(), "0x00000005");
+    }
+}
@@ -0,0 +1,307 @@
+//! A cache of pre-decoded instructions.
+//!
+//! The executable region is decoded once into a table of instructions indexed by
+//! address, from which the CPU dispatches directly. Any write that touches the region
+//! invalidates the instructions it overlaps, which are decoded again when next fetched.
+
+use crate::cpu::{CpuError, MemoryFault};
+use crate::instructions::encoding::MAX_INSTRUCTION_SIZE;
+use crate::instructions::enums::Instruction;
+use crate::memory::Memory;
+use crate::security_context::SecurityContext;
+use snafu::ResultExt;
+
+type Result<T, E = CpuError> = std::result::Result<T, E>;
+
+/// The executable region described by the contents of a cache.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct CachedRegion {
+    pub seq_id: u32,
+    pub start: u32,
+    pub end: u32,
+    /// The number of memory regions, since adding a region may change whether the
+    /// executable region can be read.
+    region_count: usize,
+}
+
+#[derive(Debug)]
+pub struct DecodeCache {
+    enabled: bool,
+    region: Option<CachedRegion>,
+    entries: Vec<Option<Instruction>>,
+    generation: u64,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            region: None,
+            entries: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// Returns true if instructions are dispatched from the cache.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable the cache. When disabled, every instruction is decoded
+    /// from memory each time it is fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true if the cache should be used.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    /// Discard every cached instruction.
+    pub fn clear(&mut self) {
+        self.region = None;
+        self.entries.clear();
+        self.generation += 1;
+    }
+
+    /// Returns a number that changes whenever any cached instruction is discarded.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the cached instruction at an address, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the instruction.
+    pub fn get(&self, address: u32) -> Option<&Instruction> {
+        let start = self.region?.start;
+        self.entries
+            .get(address.checked_sub(start)? as usize)?
+            .as_ref()
+    }
+
+    /// Fetch the instruction at an address within the executable region, decoding
+    /// it from memory only if no valid decoded copy is held.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory holding the executable region.
+    /// * `seq_id` - the sequence ID of the executable region.
+    /// * `address` - the address of the instruction, which must lie within the region.
+    pub(crate) fn fetch(
+        &mut self,
+        mem: &mut Memory,
+        seq_id: u32,
+        address: u32,
+    ) -> Result<Instruction> {
+        let region = self.sync(mem, seq_id)?;
+        if !self.enabled {
+            return decode(mem, address, region.end);
+        }
+
+        let index = (address - region.start) as usize;
+        if let Some(ins) = &self.entries[index] {
+            return Ok(ins.clone());
+        }
+
+        let ins = decode(mem, address, region.end)?;
+        self.entries[index] = Some(ins.clone());
+
+        Ok(ins)
+    }
+
+    /// Bring the cache up to date with the executable region and any writes
+    /// made to it, returning the region.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory holding the executable region.
+    /// * `seq_id` - the sequence ID of the executable region.
+    pub(crate) fn sync(&mut self, mem: &mut Memory, seq_id: u32) -> Result<CachedRegion> {
+        let region = mem.get_region_by_seq_id(seq_id).context(MemoryFault)?;
+        let region = CachedRegion {
+            seq_id,
+            start: region.start,
+            end: region.end,
+            region_count: mem.regions().len(),
+        };
+
+        if !self.enabled {
+            // Nothing is cached, so there is nothing to invalidate.
+            mem.take_code_writes();
+        } else if self.region != Some(region) {
+            self.rebuild(mem, region);
+        } else {
+            for (start, end) in mem.take_code_writes() {
+                self.invalidate(start, end);
+            }
+        }
+
+        Ok(region)
+    }
+
+    /// Decode the executable region from its start, stopping at the first
+    /// sequence of bytes that is not a valid instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory holding the executable region.
+    /// * `region` - the executable region.
+    fn rebuild(&mut self, mem: &mut Memory, region: CachedRegion) {
+        mem.watch_code(region.start, region.end);
+
+        self.generation += 1;
+        self.region = Some(region);
+        self.entries.clear();
+        self.entries
+            .resize((region.end - region.start) as usize + 1, None);
+
+        let mut address = region.start;
+        while address <= region.end {
+            let ins = match decode(mem, address, region.end) {
+                Ok(ins) => ins,
+                Err(_) => break,
+            };
+
+            let size = ins.size();
+            self.entries[(address - region.start) as usize] = Some(ins);
+            address = match address.checked_add(size) {
+                Some(a) => a,
+                None => break,
+            };
+        }
+    }
+
+    /// Discard every cached instruction that overlaps a range of written addresses.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte written.
+    /// * `end` - the address of the last byte written.
+    fn invalidate(&mut self, start: u32, end: u32) {
+        let region = match self.region {
+            Some(r) => r,
+            None => return,
+        };
+
+        // An instruction beginning before the write may extend into it.
+        let first = start
+            .saturating_sub(MAX_INSTRUCTION_SIZE - 1)
+            .max(region.start);
+        let last = end.min(region.end);
+        if first > last {
+            return;
+        }
+
+        for entry in
+            &mut self.entries[(first - region.start) as usize..=(last - region.start) as usize]
+        {
+            *entry = None;
+        }
+    }
+}
+
+/// Decode the instruction at an address, which may not extend beyond the end of
+/// the executable region.
+///
+/// # Arguments
+///
+/// * `mem` - the memory holding the executable region.
+/// * `address` - the address of the instruction.
+/// * `end` - the address of the last byte of the executable region.
+fn decode(mem: &Memory, address: u32, end: u32) -> Result<Instruction> {
+    let len = (end - address + 1).min(MAX_INSTRUCTION_SIZE);
+    let bytes = mem
+        .read_bytes(address, len, SecurityContext::User)
+        .context(MemoryFault)?;
+
+    Instruction::decode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::RunOutcome;
+    use crate::registers::{RegisterValue, Registers};
+    use crate::virtual_machine::VirtualMachine;
+
+    fn r1(vm: &VirtualMachine) -> RegisterValue {
+        vm.cpu
+            .registers
+            .get_register_value(Registers::R1, SecurityContext::User)
+            .unwrap()
+    }
+
+    #[test]
+    fn writes_to_code_invalidate_cached_instructions() {
+        let program = [
+            Instruction::MovLitReg(1, Registers::R1),
+            Instruction::NOP(),
+            Instruction::HLT(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+        vm.step();
+
+        // The whole region was decoded when the first instruction was fetched.
+        assert_eq!(vm.cpu.decode_cache.get(0), Some(&program[0]));
+        assert_eq!(vm.cpu.decode_cache.get(7), Some(&program[1]));
+        assert_eq!(vm.cpu.decode_cache.get(9), Some(&program[2]));
+        assert_eq!(vm.cpu.decode_cache.get(1), None);
+
+        // Overwrite the literal of the first instruction and run it again.
+        vm.memory
+            .write_bytes(2, &5i32.to_le_bytes(), SecurityContext::System)
+            .unwrap();
+        vm.cpu.set_instruction_pointer(0).unwrap();
+        vm.step();
+        assert_eq!(r1(&vm), RegisterValue::I32(5));
+        assert_eq!(
+            vm.cpu.decode_cache.get(0),
+            Some(&Instruction::MovLitReg(5, Registers::R1))
+        );
+        assert_eq!(vm.cpu.decode_cache.get(9), Some(&program[2]));
+
+        // Replacing an instruction entirely is also observed.
+        vm.memory
+            .write_bytes(0, &Instruction::NOP().encode(), SecurityContext::System)
+            .unwrap();
+        vm.cpu.set_instruction_pointer(0).unwrap();
+        assert_eq!(vm.step().instruction, Some(Instruction::NOP()));
+    }
+
+    #[test]
+    fn cached_and_uncached_dispatch_agree() {
+        let program = [
+            Instruction::MovLitReg(0, Registers::R1),
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::MovRegReg(Registers::AC, Registers::R1),
+            Instruction::JmpNotEq(100, 7),
+            Instruction::HLT(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut results = Vec::new();
+        for &enabled in &[true, false] {
+            let mut vm = VirtualMachine::new(1_000, 10, false);
+            vm.cpu.decode_cache.set_enabled(enabled);
+            vm.load_program(&bytes, 0).unwrap();
+            assert!(matches!(vm.run_for(10_000), RunOutcome::Halted));
+            assert_eq!(vm.cpu.decode_cache.get(0).is_some(), enabled);
+
+            results.push((r1(&vm), vm.cpu.cycles(), vm.cpu.instructions_executed()));
+        }
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[0].0, RegisterValue::I32(100));
+    }
+}
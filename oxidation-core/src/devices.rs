@@ -0,0 +1,190 @@
+use crate::cpu::*;
+use crate::replay::{InputEvent, InputKind, InputLog};
+use snafu::OptionExt;
+use std::collections::HashMap;
+
+type Result<T, E = CpuError> = std::result::Result<T, E>;
+
+/// A device that may be attached to an I/O port of the CPU.
+pub trait Device {
+    /// Read a value from the device.
+    fn read(&mut self) -> i32;
+
+    /// Write a value to the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - the value to be written.
+    fn write(&mut self, value: i32);
+}
+
+/// The source of the values supplied by non-deterministic inputs.
+enum InputMode {
+    /// Inputs are read from the attached devices.
+    Live,
+    /// Inputs are read from the attached devices and recorded.
+    Recording(InputLog),
+    /// Inputs are supplied by a previously recorded log.
+    Replaying(InputLog, usize),
+}
+
+/// The collection of devices attached to the I/O ports of the CPU.
+pub struct IoBus {
+    devices: HashMap<u8, Box<dyn Device>>,
+    mode: InputMode,
+}
+
+impl Default for IoBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoBus {
+    pub fn new() -> Self {
+        Self {
+            devices: HashMap::new(),
+            mode: InputMode::Live,
+        }
+    }
+
+    /// Attach a device to a port, returning any device previously attached to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - the port to which the device should be attached.
+    /// * `device` - the device.
+    pub fn attach(&mut self, port: u8, device: Box<dyn Device>) -> Option<Box<dyn Device>> {
+        self.devices.insert(port, device)
+    }
+
+    /// Detach the device attached to a port, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - the port from which the device should be detached.
+    pub fn detach(&mut self, port: u8) -> Option<Box<dyn Device>> {
+        self.devices.remove(&port)
+    }
+
+    /// Begin recording every non-deterministic input.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_instruction` - the number of instructions executed when recording began.
+    pub fn start_recording(&mut self, start_instruction: u64) {
+        self.mode = InputMode::Recording(InputLog::new(start_instruction));
+    }
+
+    /// Begin supplying non-deterministic inputs from a previously recorded log.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - the recorded log.
+    pub fn start_replay(&mut self, log: InputLog) {
+        self.mode = InputMode::Replaying(log, 0);
+    }
+
+    /// Return to reading inputs from the attached devices, returning the
+    /// log that was being recorded or replayed, if any.
+    pub fn stop(&mut self) -> Option<InputLog> {
+        match std::mem::replace(&mut self.mode, InputMode::Live) {
+            InputMode::Live => None,
+            InputMode::Recording(log) | InputMode::Replaying(log, _) => Some(log),
+        }
+    }
+
+    /// Returns the next recorded event that has yet to be replayed, if any.
+    pub fn pending_replay_event(&self) -> Option<&InputEvent> {
+        match &self.mode {
+            InputMode::Replaying(log, position) => log.events.get(*position),
+            _ => None,
+        }
+    }
+
+    /// Read a value from the device attached to a port.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - the port from which the value should be read.
+    /// * `instruction` - the number of instructions executed prior to the read.
+    /// * `address` - the address of the instruction performing the read.
+    pub(crate) fn read(&mut self, port: u8, instruction: u64, address: u32) -> Result<i32> {
+        let kind = InputKind::DeviceRead { port };
+
+        if let InputMode::Replaying(log, position) = &mut self.mode {
+            let event = match log.events.get(*position) {
+                Some(e) => e,
+                None => {
+                    return ReplayDivergence {
+                        instruction,
+                        address,
+                        reason: "the recorded log contains no further inputs".to_string(),
+                    }
+                    .fail()
+                }
+            };
+
+            if event.instruction != instruction || event.address != address || event.kind != kind {
+                // The first mismatching instruction is whichever came first:
+                // the recorded input that did not happen, or this unexpected one.
+                let (instruction, address) = if event.instruction < instruction {
+                    (event.instruction, event.address)
+                } else {
+                    (instruction, address)
+                };
+
+                return ReplayDivergence {
+                    instruction,
+                    address,
+                    reason: format!(
+                        "expected {:?} at instruction {} ({:#010X}), found {:?} at {:#010X}",
+                        event.kind, event.instruction, event.address, kind, address
+                    ),
+                }
+                .fail();
+            }
+
+            *position += 1;
+            return Ok(event.value);
+        }
+
+        let value = self
+            .devices
+            .get_mut(&port)
+            .context(InvalidDevicePort { port })?
+            .read();
+
+        if let InputMode::Recording(log) = &mut self.mode {
+            log.events.push(InputEvent {
+                instruction,
+                address,
+                kind,
+                value,
+            });
+        }
+
+        Ok(value)
+    }
+
+    /// Write a value to the device attached to a port.
+    ///
+    /// While replaying, writes to ports without an attached device are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - the port to which the value should be written.
+    /// * `value` - the value to be written.
+    pub(crate) fn write(&mut self, port: u8, value: i32) -> Result<()> {
+        match self.devices.get_mut(&port) {
+            Some(device) => device.write(value),
+            None => {
+                if !matches!(self.mode, InputMode::Replaying(_, _)) {
+                    return InvalidDevicePort { port }.fail();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,644 @@
+//! A versioned container format for executable programs.
+//!
+//! All values are little-endian. An executable consists of the magic number,
+//! the format version, the entry point and the number of sections, followed by
+//! the sections themselves. Each section begins with its kind, the access flags
+//! with which it is to be loaded, its address and its size. The contents of code
+//! and data sections follow, while zero-filled sections have none. The symbols
+//! section holds the number of symbols, followed by the address and length-prefixed
+//! UTF-8 name of each. The optional debug section holds the names of the source
+//! files, the line table, in which each line refers to its file by index, and then
+//! the labels and subroutines, each encoded as in the symbols section.
+
+use crate::cpu::CpuError;
+use crate::debug_info::DebugInfo;
+use crate::memory::MemoryAccess;
+use crate::symbols::SymbolTable;
+use snafu::{ensure, ResultExt, Snafu};
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+pub type Result<T, E = ExecutableError> = std::result::Result<T, E>;
+
+/// The magic number found at the start of every executable file.
+pub const EXECUTABLE_MAGIC: [u8; 4] = *b"OXEX";
+
+/// The version of the executable format written by this library.
+pub const EXECUTABLE_VERSION: u16 = 1;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility = "pub(crate)")]
+pub enum ExecutableError {
+    #[snafu(display("an I/O error occurred while processing an executable: {}", source))]
+    ExecutableIo { source: std::io::Error },
+    #[snafu(display("the file is not an executable"))]
+    InvalidExecutableMagic,
+    #[snafu(display("the executable version {} is not supported", version))]
+    UnsupportedExecutableVersion { version: u16 },
+    #[snafu(display("the executable is invalid: {}", reason))]
+    InvalidExecutable { reason: String },
+    #[snafu(display("the executable could not be loaded: {}", source))]
+    LoadExecutable { source: CpuError },
+}
+
+/// The kinds of section that may be held within an executable.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SectionKind {
+    /// Instructions, from which the executable region is created.
+    Code = 0,
+    /// Initialised data.
+    Data = 1,
+    /// Data that is filled with zeros when loaded.
+    Zero = 2,
+    /// The symbol table, which is not loaded into memory.
+    Symbols = 3,
+    /// The debug information, which is not loaded into memory.
+    Debug = 4,
+}
+
+impl SectionKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        let kind = match value {
+            0 => SectionKind::Code,
+            1 => SectionKind::Data,
+            2 => SectionKind::Zero,
+            3 => SectionKind::Symbols,
+            4 => SectionKind::Debug,
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+}
+
+/// A section of an executable that is loaded into memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub kind: SectionKind,
+    /// The address at which the section is loaded.
+    pub address: u32,
+    /// The size, in bytes, of the section once loaded.
+    pub size: u32,
+    /// The access flags of the memory region created for the section.
+    pub access: MemoryAccess,
+    /// The contents of the section, which is empty for zero-filled sections.
+    pub bytes: Vec<u8>,
+}
+
+impl Section {
+    /// Create a code section, which is loaded with the same access flags as
+    /// any other executable region.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address at which the section is loaded.
+    /// * `bytes` - the instructions held within the section.
+    pub fn code(address: u32, bytes: Vec<u8>) -> Self {
+        Self {
+            kind: SectionKind::Code,
+            address,
+            size: bytes.len() as u32,
+            access: MemoryAccess::R | MemoryAccess::PW,
+            bytes,
+        }
+    }
+
+    /// Create a section of initialised data.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address at which the section is loaded.
+    /// * `bytes` - the data held within the section.
+    /// * `access` - the access flags of the memory region created for the section.
+    pub fn data(address: u32, bytes: Vec<u8>, access: MemoryAccess) -> Self {
+        Self {
+            kind: SectionKind::Data,
+            address,
+            size: bytes.len() as u32,
+            access,
+            bytes,
+        }
+    }
+
+    /// Create a section that is filled with zeros when loaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address at which the section is loaded.
+    /// * `size` - the size, in bytes, of the section.
+    /// * `access` - the access flags of the memory region created for the section.
+    pub fn zero(address: u32, size: u32, access: MemoryAccess) -> Self {
+        Self {
+            kind: SectionKind::Zero,
+            address,
+            size,
+            access,
+            bytes: Vec::new(),
+        }
+    }
+
+    /// Returns the address of the last byte of the section.
+    pub fn end(&self) -> u32 {
+        self.address + self.size - 1
+    }
+
+    /// Returns the name given to the memory region created for the section.
+    pub fn region_name(&self) -> &'static str {
+        match self.kind {
+            SectionKind::Code => "Executable",
+            SectionKind::Data => "Data",
+            SectionKind::Zero => "Zero",
+            SectionKind::Symbols => "Symbols",
+            SectionKind::Debug => "Debug",
+        }
+    }
+}
+
+/// An executable program, comprising a single code section along with any
+/// data sections and symbols.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Executable {
+    /// The address of the first instruction to be executed.
+    pub entry_point: u32,
+    pub sections: Vec<Section>,
+    pub symbols: SymbolTable,
+    pub debug_info: Option<DebugInfo>,
+}
+
+impl Executable {
+    /// Create an executable whose entry point is the start of its code section.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - the code section.
+    pub fn new(code: Section) -> Self {
+        Self {
+            entry_point: code.address,
+            sections: vec![code],
+            symbols: SymbolTable::new(),
+            debug_info: None,
+        }
+    }
+
+    /// Returns the code section, if any.
+    pub fn code(&self) -> Option<&Section> {
+        self.sections.iter().find(|s| s.kind == SectionKind::Code)
+    }
+
+    /// Check that the executable holds exactly one code section, that the entry
+    /// point lies within it and that no two sections overlap.
+    pub fn validate(&self) -> Result<()> {
+        let code_sections = self
+            .sections
+            .iter()
+            .filter(|s| s.kind == SectionKind::Code)
+            .count();
+        if code_sections != 1 {
+            return invalid(format!(
+                "{} code sections were found, but exactly one is required",
+                code_sections
+            ));
+        }
+
+        for (i, section) in self.sections.iter().enumerate() {
+            if section.kind == SectionKind::Symbols || section.kind == SectionKind::Debug {
+                return invalid(format!(
+                    "the {:?} section cannot be held as a loaded section",
+                    section.kind
+                ));
+            }
+            if section.size == 0 || section.address.checked_add(section.size - 1).is_none() {
+                return invalid(format!(
+                    "the section at {:#010X} has an invalid size",
+                    section.address
+                ));
+            }
+            let expected = match section.kind {
+                SectionKind::Zero => 0,
+                _ => section.size as usize,
+            };
+            if section.bytes.len() != expected {
+                return invalid(format!(
+                    "the contents of the section at {:#010X} do not match its size",
+                    section.address
+                ));
+            }
+
+            let overlapping = self.sections[..i]
+                .iter()
+                .any(|s| s.address <= section.end() && section.address <= s.end());
+            if overlapping {
+                return invalid(format!(
+                    "the section at {:#010X} overlaps another section",
+                    section.address
+                ));
+            }
+        }
+
+        let code = self.code().unwrap();
+        if self.entry_point < code.address || self.entry_point > code.end() {
+            return invalid(format!(
+                "the entry point {:#010X} does not lie within the code section",
+                self.entry_point
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Save the executable to a file. The file is only created once the executable
+    /// has been validated and encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut bytes = Vec::new();
+        ExecutableWriter::new(&mut bytes).write(self)?;
+        fs::write(path, bytes).context(ExecutableIo)
+    }
+
+    /// Load an executable from a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path).context(ExecutableIo)?;
+        ExecutableReader::new(&mut BufReader::new(file)).read()
+    }
+
+    /// Returns true if a sequence of bytes begins with the executable magic number.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - the bytes to be checked.
+    pub fn is_executable(bytes: &[u8]) -> bool {
+        bytes.starts_with(&EXECUTABLE_MAGIC)
+    }
+}
+
+/// Writes an executable in the container format.
+pub struct ExecutableWriter<'a> {
+    writer: &'a mut dyn Write,
+}
+
+impl<'a> ExecutableWriter<'a> {
+    pub fn new(writer: &'a mut dyn Write) -> Self {
+        Self { writer }
+    }
+
+    /// Validate and write an executable.
+    ///
+    /// # Arguments
+    ///
+    /// * `executable` - the executable to be written.
+    pub fn write(&mut self, executable: &Executable) -> Result<()> {
+        executable.validate()?;
+
+        self.write_bytes(&EXECUTABLE_MAGIC)?;
+        self.write_u16(EXECUTABLE_VERSION)?;
+        self.write_u32(executable.entry_point)?;
+
+        let section_count =
+            executable.sections.len() + 1 + executable.debug_info.is_some() as usize;
+        if section_count > u16::MAX as usize {
+            return invalid("too many sections were specified".to_string());
+        }
+        self.write_u16(section_count as u16)?;
+
+        for section in &executable.sections {
+            self.write_u8(section.kind as u8)?;
+            self.write_u8(section.access.bits())?;
+            self.write_u32(section.address)?;
+            self.write_u32(section.size)?;
+            self.write_bytes(&section.bytes)?;
+        }
+
+        let mut symbols = Vec::new();
+        ExecutableWriter::new(&mut symbols).write_symbols(&executable.symbols)?;
+        self.write_unloaded_section(SectionKind::Symbols, &symbols)?;
+
+        if let Some(info) = &executable.debug_info {
+            let mut debug = Vec::new();
+            ExecutableWriter::new(&mut debug).write_debug_info(info)?;
+            self.write_unloaded_section(SectionKind::Debug, &debug)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a section that is not loaded into memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - the kind of the section.
+    /// * `bytes` - the contents of the section.
+    fn write_unloaded_section(&mut self, kind: SectionKind, bytes: &[u8]) -> Result<()> {
+        self.write_u8(kind as u8)?;
+        self.write_u8(MemoryAccess::N.bits())?;
+        self.write_u32(0)?;
+        self.write_u32(bytes.len() as u32)?;
+        self.write_bytes(bytes)
+    }
+
+    fn write_symbols(&mut self, symbols: &SymbolTable) -> Result<()> {
+        self.write_u32(symbols.len() as u32)?;
+        for (address, name) in symbols.iter() {
+            self.write_u32(address)?;
+            self.write_string(name)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_debug_info(&mut self, info: &DebugInfo) -> Result<()> {
+        let files = info.files();
+        self.write_u32(files.len() as u32)?;
+        for file in &files {
+            self.write_string(file)?;
+        }
+
+        // Each line refers to its file by the index of the file within the list.
+        self.write_u32(info.lines.len() as u32)?;
+        for (address, location) in info.lines.iter() {
+            self.write_u32(address)?;
+            self.write_u32(files.binary_search(&location.file.as_str()).unwrap() as u32)?;
+            self.write_u32(location.line)?;
+        }
+
+        self.write_symbols(&info.labels)?;
+        self.write_symbols(&info.subroutines)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer.write_all(bytes).context(ExecutableIo)
+    }
+
+    fn write_u8(&mut self, value: u8) -> Result<()> {
+        self.write_bytes(&[value])
+    }
+
+    fn write_u16(&mut self, value: u16) -> Result<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, value: u32) -> Result<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Write a length-prefixed UTF-8 string.
+    fn write_string(&mut self, value: &str) -> Result<()> {
+        self.write_u32(value.len() as u32)?;
+        self.write_bytes(value.as_bytes())
+    }
+}
+
+/// Reads an executable in the container format.
+pub struct ExecutableReader<'a> {
+    reader: &'a mut dyn Read,
+}
+
+impl<'a> ExecutableReader<'a> {
+    pub fn new(reader: &'a mut dyn Read) -> Self {
+        Self { reader }
+    }
+
+    /// Read and validate an executable.
+    pub fn read(&mut self) -> Result<Executable> {
+        ensure!(
+            self.read_array::<4>()? == EXECUTABLE_MAGIC,
+            InvalidExecutableMagic
+        );
+
+        let version = self.read_u16()?;
+        ensure!(
+            version == EXECUTABLE_VERSION,
+            UnsupportedExecutableVersion { version }
+        );
+
+        let entry_point = self.read_u32()?;
+        let section_count = self.read_u16()?;
+
+        let mut sections = Vec::new();
+        let mut symbols = None;
+        let mut debug_info = None;
+        for _ in 0..section_count {
+            let kind = self.read_u8()?;
+            let kind = match SectionKind::from_u8(kind) {
+                Some(k) => k,
+                None => return invalid(format!("{} is not a valid section kind", kind)),
+            };
+            let access = self.read_u8()?;
+            let access = match MemoryAccess::from_bits(access) {
+                Some(a) => a,
+                None => return invalid(format!("{:#04X} is not a valid access flag", access)),
+            };
+            let address = self.read_u32()?;
+            let size = self.read_u32()?;
+
+            match kind {
+                SectionKind::Symbols => {
+                    if symbols.is_some() {
+                        return invalid("more than one symbol table was found".to_string());
+                    }
+                    let bytes = self.read_bytes(size as usize)?;
+                    symbols = Some(ExecutableReader::new(&mut &bytes[..]).read_symbols()?);
+                }
+                SectionKind::Debug => {
+                    if debug_info.is_some() {
+                        return invalid("more than one debug section was found".to_string());
+                    }
+                    let bytes = self.read_bytes(size as usize)?;
+                    debug_info = Some(ExecutableReader::new(&mut &bytes[..]).read_debug_info()?);
+                }
+                SectionKind::Zero => sections.push(Section::zero(address, size, access)),
+                _ => {
+                    let bytes = self.read_bytes(size as usize)?;
+                    sections.push(Section {
+                        kind,
+                        address,
+                        size,
+                        access,
+                        bytes,
+                    });
+                }
+            }
+        }
+
+        let executable = Executable {
+            entry_point,
+            sections,
+            symbols: symbols.unwrap_or_default(),
+            debug_info,
+        };
+        executable.validate()?;
+
+        Ok(executable)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        (&mut *self.reader)
+            .take(len as u64)
+            .read_to_end(&mut buffer)
+            .context(ExecutableIo)?;
+        if buffer.len() != len {
+            return invalid("the executable is truncated".to_string());
+        }
+
+        Ok(buffer)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buffer = [0u8; N];
+        self.reader.read_exact(&mut buffer).context(ExecutableIo)?;
+        Ok(buffer)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    /// Read a length-prefixed UTF-8 string.
+    fn read_string(&mut self) -> Result<String> {
+        let len = self.read_u32()? as usize;
+        match String::from_utf8(self.read_bytes(len)?) {
+            Ok(s) => Ok(s),
+            Err(_) => invalid("a string is not valid UTF-8".to_string()),
+        }
+    }
+
+    fn read_symbols(&mut self) -> Result<SymbolTable> {
+        let mut symbols = SymbolTable::new();
+        for _ in 0..self.read_u32()? {
+            let address = self.read_u32()?;
+            symbols.insert(address, self.read_string()?);
+        }
+
+        Ok(symbols)
+    }
+
+    fn read_debug_info(&mut self) -> Result<DebugInfo> {
+        let mut files = Vec::new();
+        for _ in 0..self.read_u32()? {
+            files.push(self.read_string()?);
+        }
+
+        let mut info = DebugInfo::new();
+        for _ in 0..self.read_u32()? {
+            let address = self.read_u32()?;
+            let file = match files.get(self.read_u32()? as usize) {
+                Some(f) => f,
+                None => return invalid("a line refers to a file that does not exist".to_string()),
+            };
+            let line = self.read_u32()?;
+            info.lines.insert(address, file, line);
+        }
+        info.labels = self.read_symbols()?;
+        info.subroutines = self.read_symbols()?;
+
+        Ok(info)
+    }
+}
+
+/// Returns an error indicating that the executable is invalid.
+///
+/// # Arguments
+///
+/// * `reason` - a description of the problem.
+fn invalid<T>(reason: String) -> Result<T> {
+    InvalidExecutable { reason }.fail()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Executable {
+        let mut executable = Executable::new(Section::code(0x100, vec![0x00, 0x00, 0x13, 0x00]));
+        executable.entry_point = 0x102;
+        executable
+            .sections
+            .push(Section::data(0x200, vec![1, 2, 3, 4], MemoryAccess::R));
+        executable.sections.push(Section::zero(
+            0x300,
+            0x40,
+            MemoryAccess::R | MemoryAccess::W,
+        ));
+        executable.symbols.insert(0x100, "main".to_string());
+        executable.symbols.insert(0x200, "table".to_string());
+        executable
+    }
+
+    #[test]
+    fn executables_round_trip() {
+        let mut info = DebugInfo::new();
+        info.lines.insert(0x100, "main.asm", 1);
+        info.lines.insert(0x102, "lib.asm", 7);
+        info.labels.insert(0x102, "start".to_string());
+        info.subroutines.insert(0x100, "main".to_string());
+
+        let mut with_debug_info = sample();
+        with_debug_info.debug_info = Some(info);
+
+        for executable in &[sample(), with_debug_info] {
+            let mut bytes = Vec::new();
+            ExecutableWriter::new(&mut bytes).write(executable).unwrap();
+            assert!(Executable::is_executable(&bytes));
+
+            let read = ExecutableReader::new(&mut bytes.as_slice()).read().unwrap();
+            assert_eq!(&read, executable);
+        }
+    }
+
+    #[test]
+    fn invalid_executables_are_rejected() {
+        let mut bytes = Vec::new();
+        ExecutableWriter::new(&mut bytes).write(&sample()).unwrap();
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] = b'X';
+        assert!(matches!(
+            ExecutableReader::new(&mut wrong_magic.as_slice()).read(),
+            Err(ExecutableError::InvalidExecutableMagic)
+        ));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 99;
+        assert!(matches!(
+            ExecutableReader::new(&mut wrong_version.as_slice()).read(),
+            Err(ExecutableError::UnsupportedExecutableVersion { version: 99 })
+        ));
+
+        let truncated = &bytes[..bytes.len() - 3];
+        assert!(matches!(
+            ExecutableReader::new(&mut &truncated[..]).read(),
+            Err(ExecutableError::InvalidExecutable { .. })
+        ));
+
+        let mut overlapping = sample();
+        overlapping.sections[1].address = 0x102;
+        assert!(overlapping.validate().is_err());
+
+        let mut outside = sample();
+        outside.entry_point = 0x200;
+        assert!(outside.validate().is_err());
+
+        // Nothing is written when saving an invalid executable.
+        let path =
+            std::env::temp_dir().join(format!("oxidation-{}-invalid.oxe", std::process::id()));
+        assert!(outside.save(&path).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::cpu::CpuError;
+use crate::instructions::enums::{Instruction, OpCode};
+use crate::memory::{MemoryRead, MemoryWrite};
+use crate::registers::RegisterWrite;
+use crate::watchpoints::WatchpointHit;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The source of the identifiers of the contents of cost tables.
+static NEXT_COST_TABLE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// The reason for which a bounded execution of the CPU stopped.
+#[derive(Debug)]
+pub enum RunOutcome {
+    /// The CPU executed a halt instruction.
+    Halted,
+    /// The execution budget would have been exceeded by the next instruction.
+    BudgetExhausted,
+    /// An error occurred while fetching, decoding or executing an instruction.
+    Fault(CpuError),
+    /// The stop predicate was satisfied before the next instruction was executed.
+    Breakpoint,
+    /// A watchpoint was triggered by the instruction that was just executed.
+    Watchpoint(WatchpointHit),
+}
+
+/// A description of the effects of executing a single instruction.
+#[derive(Debug)]
+pub struct StepOutcome {
+    /// The address from which the instruction was decoded.
+    pub address: u32,
+    /// The decoded instruction, if decoding was successful.
+    pub instruction: Option<Instruction>,
+    /// The registers written by the instruction, in the order they were written.
+    pub register_writes: Vec<RegisterWrite>,
+    /// The memory read by the instruction, in the order it was read.
+    pub memory_reads: Vec<MemoryRead>,
+    /// The memory written by the instruction, in the order it was written.
+    pub memory_writes: Vec<MemoryWrite>,
+    /// The number of cycles consumed by the instruction.
+    pub cycles: u64,
+    /// True if the CPU was halted after the step.
+    pub halted: bool,
+    /// The error that occurred while executing the step, if any.
+    pub fault: Option<CpuError>,
+    /// The error that occurred while executing the step and was delivered to the
+    /// fault handler, rather than halting the CPU, if any.
+    pub handled_fault: Option<CpuError>,
+    /// The watchpoint triggered by the step, if any. This is only determined when
+    /// stepping a virtual machine, which holds the watchpoints.
+    pub watchpoint: Option<WatchpointHit>,
+}
+
+impl StepOutcome {
+    /// Returns true if an instruction was executed to completion.
+    pub fn executed(&self) -> bool {
+        self.instruction.is_some() && self.fault.is_none() && self.handled_fault.is_none()
+    }
+
+    /// Create a step outcome for a step in which no instruction was executed.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the value of the instruction pointer.
+    /// * `halted` - true if the CPU is halted.
+    /// * `fault` - the error that prevented the instruction from being executed, if any.
+    pub fn without_instruction(address: u32, halted: bool, fault: Option<CpuError>) -> Self {
+        Self {
+            address,
+            instruction: None,
+            register_writes: Vec::new(),
+            memory_reads: Vec::new(),
+            memory_writes: Vec::new(),
+            cycles: 0,
+            halted,
+            fault,
+            handled_fault: None,
+            watchpoint: None,
+        }
+    }
+}
+
+/// The number of cycles consumed by the execution of each instruction.
+#[derive(Debug, Clone)]
+pub struct CostTable {
+    costs: HashMap<OpCode, u64>,
+    default_cost: u64,
+    id: u64,
+}
+
+impl CostTable {
+    /// Create a new cost table in which every instruction has the same cost.
+    ///
+    /// # Arguments
+    ///
+    /// * `default_cost` - the cost of any instruction without an explicit entry.
+    pub fn new(default_cost: u64) -> Self {
+        Self {
+            costs: HashMap::new(),
+            default_cost,
+            id: NEXT_COST_TABLE_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Returns an identifier of the contents of the table, which changes whenever a
+    /// cost is set, allowing costs derived from the table to be cached.
+    #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the number of cycles consumed by the specified opcode.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - the opcode of the instruction.
+    pub fn get_cost(&self, opcode: OpCode) -> u64 {
+        *self.costs.get(&opcode).unwrap_or(&self.default_cost)
+    }
+
+    /// Sets the number of cycles consumed by the specified opcode.
+    ///
+    /// # Arguments
+    ///
+    /// * `opcode` - the opcode of the instruction.
+    /// * `cost` - the number of cycles consumed by the instruction.
+    pub fn set_cost(&mut self, opcode: OpCode, cost: u64) {
+        self.costs.insert(opcode, cost);
+        self.id = NEXT_COST_TABLE_ID.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        let mut table = Self::new(1);
+        table.set_cost(OpCode::MovRegMem, 3);
+        table.set_cost(OpCode::MovMemReg, 3);
+        table.set_cost(OpCode::AddLitReg, 2);
+        table.set_cost(OpCode::Out, 4);
+        table.set_cost(OpCode::In, 4);
+        table.set_cost(OpCode::PshLit, 2);
+        table.set_cost(OpCode::PshReg, 2);
+        table.set_cost(OpCode::Pop, 2);
+        table.set_cost(OpCode::CalLit, 3);
+        table.set_cost(OpCode::CalReg, 3);
+        table.set_cost(OpCode::Ret, 3);
+        table.set_cost(OpCode::IRet, 3);
+
+        table
+    }
+}
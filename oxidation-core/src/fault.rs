@@ -0,0 +1,526 @@
+//! Detailed reports of the faults that stop the execution of a program.
+
+use crate::cpu::{CpuError, CPU};
+use crate::debug_info::DebugLocation;
+use crate::instructions::enums::{Instruction, OpCode};
+use crate::memory::{Memory, MemoryError};
+use crate::registers::{RegisterValue, Registers};
+use crate::security_context::SecurityContext;
+use std::fmt;
+use std::io::{self, Write};
+
+/// The kind of a fault, identifying its cause.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaultKind {
+    /// Memory was accessed with insufficient permissions.
+    AccessViolation { address: u32, region: String },
+    /// Memory outside of every region was accessed.
+    InvalidAddress { address: u32 },
+    /// A register was accessed with insufficient permissions.
+    RegisterAccessViolation,
+    /// A register that does not exist was accessed.
+    InvalidRegister,
+    /// A register was given a value of a type that it cannot hold.
+    TypeMismatch,
+    /// An instruction had an opcode that does not exist.
+    InvalidOpCode { opcode: i16 },
+    /// An instruction had an opcode that the CPU does not support.
+    UnimplementedOpCode { opcode: OpCode },
+    /// An instruction extended beyond the end of the executable region.
+    TruncatedInstruction,
+    /// The instruction pointer lay outside of the executable region.
+    InstructionPointerOutOfBounds { address: u32 },
+    /// A value was pushed onto a full stack.
+    StackOverflow { address: u32 },
+    /// A value was popped from an empty stack.
+    StackUnderflow,
+    /// A device was accessed through a port to which none is attached.
+    InvalidDevicePort { port: u8 },
+    /// A replayed execution diverged from its recording.
+    ReplayDivergence { reason: String },
+    /// The CPU was run without an executable region.
+    NoExecutableRegion,
+    /// A fault occurred while a fault was being handled.
+    DoubleFault { fault: Box<FaultKind> },
+}
+
+impl FaultKind {
+    /// Returns the kind of fault caused by an error, along with the security
+    /// context of the access that caused it, if known.
+    pub(crate) fn from_error(error: &CpuError) -> (Self, Option<SecurityContext>) {
+        let kind = match error {
+            CpuError::MemoryFault { source } => {
+                return match source {
+                    MemoryError::MemoryAccessViolation {
+                        address,
+                        region,
+                        security_context,
+                    } => (
+                        FaultKind::AccessViolation {
+                            address: *address,
+                            region: region.clone(),
+                        },
+                        Some(*security_context),
+                    ),
+                    MemoryError::InvalidMemoryAddress { address } => {
+                        (FaultKind::InvalidAddress { address: *address }, None)
+                    }
+                    MemoryError::InvalidMemorySequenceId { .. } => {
+                        (FaultKind::NoExecutableRegion, None)
+                    }
+                }
+            }
+            CpuError::MemorySequenceIdNotSet => FaultKind::NoExecutableRegion,
+            CpuError::InvalidRegisterId => FaultKind::InvalidRegister,
+            CpuError::RegisterAccessViolation => FaultKind::RegisterAccessViolation,
+            CpuError::InvalidRegisterValueType => FaultKind::TypeMismatch,
+            CpuError::InvalidOpCode { opcode } => FaultKind::InvalidOpCode { opcode: *opcode },
+            CpuError::UnimplementedOpCode { opcode } => {
+                FaultKind::UnimplementedOpCode { opcode: *opcode }
+            }
+            CpuError::TruncatedInstruction => FaultKind::TruncatedInstruction,
+            CpuError::InstructionPointerOutOfBounds { address } => {
+                FaultKind::InstructionPointerOutOfBounds { address: *address }
+            }
+            CpuError::StackOverflow { address } => FaultKind::StackOverflow { address: *address },
+            CpuError::StackUnderflow => FaultKind::StackUnderflow,
+            CpuError::InvalidDevicePort { port } => FaultKind::InvalidDevicePort { port: *port },
+            CpuError::ReplayDivergence { reason, .. } => FaultKind::ReplayDivergence {
+                reason: reason.clone(),
+            },
+            CpuError::DoubleFault { source } => {
+                let (fault, security_context) = FaultKind::from_error(source);
+                return (
+                    FaultKind::DoubleFault {
+                        fault: Box::new(fault),
+                    },
+                    security_context,
+                );
+            }
+        };
+
+        (kind, None)
+    }
+
+    /// Returns the code by which the fault is identified to a fault handler, or
+    /// `None` if the fault cannot be handled by the guest.
+    ///
+    /// | Code | Fault                                                        |
+    /// |------|--------------------------------------------------------------|
+    /// | 1    | access violation                                             |
+    /// | 2    | invalid memory address                                       |
+    /// | 3    | invalid, unimplemented or truncated instruction              |
+    /// | 4    | instruction pointer outside of the executable region         |
+    /// | 5    | stack overflow                                               |
+    /// | 6    | stack underflow                                              |
+    /// | 7    | invalid device port                                          |
+    /// | 8    | invalid register, register access violation or type mismatch |
+    pub fn code(&self) -> Option<u32> {
+        let code = match self {
+            FaultKind::AccessViolation { .. } => 1,
+            FaultKind::InvalidAddress { .. } => 2,
+            FaultKind::InvalidOpCode { .. }
+            | FaultKind::UnimplementedOpCode { .. }
+            | FaultKind::TruncatedInstruction => 3,
+            FaultKind::InstructionPointerOutOfBounds { .. } => 4,
+            FaultKind::StackOverflow { .. } => 5,
+            FaultKind::StackUnderflow => 6,
+            FaultKind::InvalidDevicePort { .. } => 7,
+            FaultKind::RegisterAccessViolation
+            | FaultKind::InvalidRegister
+            | FaultKind::TypeMismatch => 8,
+            FaultKind::ReplayDivergence { .. }
+            | FaultKind::NoExecutableRegion
+            | FaultKind::DoubleFault { .. } => return None,
+        };
+
+        Some(code)
+    }
+
+    /// Returns the address associated with the fault, if any. For faults caused by
+    /// devices, this is the port to which the device should have been attached.
+    pub fn address(&self) -> Option<u32> {
+        match self {
+            FaultKind::AccessViolation { address, .. }
+            | FaultKind::InvalidAddress { address }
+            | FaultKind::InstructionPointerOutOfBounds { address }
+            | FaultKind::StackOverflow { address } => Some(*address),
+            FaultKind::InvalidDevicePort { port } => Some(*port as u32),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultKind::AccessViolation { address, region } => write!(
+                f,
+                "access violation at {:#010X} in region '{}'",
+                address, region
+            ),
+            FaultKind::InvalidAddress { address } => {
+                write!(f, "invalid memory address {:#010X}", address)
+            }
+            FaultKind::RegisterAccessViolation => write!(f, "register access violation"),
+            FaultKind::InvalidRegister => write!(f, "invalid register"),
+            FaultKind::TypeMismatch => write!(f, "register type mismatch"),
+            FaultKind::InvalidOpCode { opcode } => write!(f, "invalid opcode {:#06X}", opcode),
+            FaultKind::UnimplementedOpCode { opcode } => {
+                write!(f, "unimplemented opcode {:?}", opcode)
+            }
+            FaultKind::TruncatedInstruction => write!(f, "truncated instruction"),
+            FaultKind::InstructionPointerOutOfBounds { address } => write!(
+                f,
+                "instruction pointer {:#010X} outside of the executable region",
+                address
+            ),
+            FaultKind::StackOverflow { address } => {
+                write!(f, "stack overflow at {:#010X}", address)
+            }
+            FaultKind::StackUnderflow => write!(f, "stack underflow"),
+            FaultKind::InvalidDevicePort { port } => write!(f, "invalid device port {:#04X}", port),
+            FaultKind::ReplayDivergence { reason } => write!(f, "replay divergence: {}", reason),
+            FaultKind::NoExecutableRegion => write!(f, "no executable region"),
+            FaultKind::DoubleFault { fault } => write!(f, "double fault: {}", fault),
+        }
+    }
+}
+
+/// A fault that stopped the execution of a program, together with the state of the
+/// CPU at the time of the fault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmFault {
+    /// The address of the faulting instruction.
+    pub address: u32,
+    /// The faulting instruction, if it could be decoded.
+    pub instruction: Option<Instruction>,
+    /// The kind of the fault.
+    pub kind: FaultKind,
+    /// The security context of the access that caused the fault. Faults that were not
+    /// caused by an access are attributed to the user context, in which programs run.
+    pub security_context: SecurityContext,
+    /// The values of the registers at the time of the fault.
+    pub registers: Vec<(Registers, RegisterValue)>,
+    /// The number of instructions executed prior to the fault.
+    pub instructions_executed: u64,
+    /// The message of the underlying error.
+    pub message: String,
+    /// The location of the faulting instruction within the source of the program,
+    /// if debug information was available.
+    pub location: Option<Box<DebugLocation>>,
+}
+
+impl VmFault {
+    /// Capture the state of a CPU that has faulted.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - the CPU, in the state following the fault.
+    /// * `mem` - the memory from which the program was executed.
+    /// * `error` - the error that caused the fault.
+    pub fn capture(cpu: &CPU, mem: &Memory, error: &CpuError) -> Self {
+        let (kind, security_context) = FaultKind::from_error(error);
+
+        // The instruction pointer identifies the faulting instruction.
+        let address = cpu.get_instruction_pointer().unwrap_or_default();
+        let instruction = match kind {
+            FaultKind::InvalidOpCode { .. }
+            | FaultKind::TruncatedInstruction
+            | FaultKind::InstructionPointerOutOfBounds { .. } => None,
+            _ => Instruction::decode_at(mem, address).ok(),
+        };
+
+        let registers = cpu
+            .registers
+            .registers
+            .iter()
+            .filter_map(|r| {
+                r.get_value(SecurityContext::System)
+                    .ok()
+                    .map(|v| (r.register_id, v))
+            })
+            .collect();
+
+        Self {
+            address,
+            instruction,
+            kind,
+            security_context: security_context.unwrap_or(SecurityContext::User),
+            registers,
+            instructions_executed: cpu.instructions_executed(),
+            message: error.to_string(),
+            location: None,
+        }
+    }
+
+    /// Write a human-readable, multi-line report of the fault.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the destination of the report.
+    pub fn write_report(&self, writer: &mut dyn Write) -> io::Result<()> {
+        writeln!(writer, "fault: {}", self.kind)?;
+        writeln!(writer, "  {}", self.message)?;
+        writeln!(writer)?;
+
+        match &self.instruction {
+            Some(ins) => writeln!(writer, "  instruction: {:#010X}  {}", self.address, ins)?,
+            None => writeln!(writer, "  instruction: {:#010X}  <invalid>", self.address)?,
+        }
+        if let Some(location) = &self.location {
+            if let Some((_, function)) = &location.function {
+                writeln!(writer, "  function:    {}", function)?;
+            }
+            if let Some(source) = &location.source {
+                writeln!(writer, "  source:      {}:{}", source.file, source.line)?;
+            }
+        }
+        writeln!(writer, "  context:     {:?}", self.security_context)?;
+        writeln!(writer, "  executed:    {}", self.instructions_executed)?;
+        writeln!(writer)?;
+
+        writeln!(writer, "  registers:")?;
+        for (register, value) in &self.registers {
+            let value = match value {
+                RegisterValue::I16(v) => format!("{:#06X} ({})", v, v),
+                RegisterValue::I32(v) => format!("{:#010X} ({})", v, v),
+                RegisterValue::I64(v) => format!("{:#018X} ({})", v, v),
+                RegisterValue::F32(v) => format!("{}", v),
+            };
+            writeln!(writer, "    {:<2} = {}", register, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for VmFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {:#010X}", self.kind, self.address)?;
+        if let Some(ins) = &self.instruction {
+            write!(f, " ({})", ins)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for VmFault {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CpuFlags;
+    use crate::virtual_machine::VirtualMachine;
+
+    #[test]
+    fn faults_are_reported() {
+        let program = [
+            Instruction::MovLitReg(5, Registers::R1),
+            Instruction::PshLit(1),
+            Instruction::PshLit(2),
+            Instruction::HLT(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 1, false);
+        vm.load_program(&bytes, 0).unwrap();
+
+        let fault = vm.run().unwrap_err();
+        assert_eq!(fault.address, 13);
+        assert_eq!(fault.instruction, Some(Instruction::PshLit(2)));
+        assert_eq!(
+            fault.kind,
+            FaultKind::StackOverflow {
+                address: vm.memory.stack_start() - 4
+            }
+        );
+        assert_eq!(fault.security_context, SecurityContext::User);
+        assert_eq!(fault.instructions_executed, 2);
+        assert!(fault
+            .registers
+            .contains(&(Registers::R1, RegisterValue::I32(5))));
+        assert_eq!(
+            fault.to_string(),
+            format!(
+                "stack overflow at {:#010X} at 0x0000000D (push 02)",
+                vm.memory.stack_start() - 4
+            )
+        );
+
+        let mut report = Vec::new();
+        fault.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("  instruction: 0x0000000D  push 02\n"));
+        assert!(report.contains("    R1 = 0x00000005 (5)\n"));
+    }
+
+    #[test]
+    fn faults_are_located_with_debug_info() {
+        use crate::debug_info::DebugInfo;
+
+        let program = [
+            Instruction::CalLit(6),
+            // fail (6)
+            Instruction::Pop(Registers::R1),
+            Instruction::Pop(Registers::R1),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut info = DebugInfo::new();
+        info.lines.insert(0, "main.asm", 2);
+        info.lines.insert(6, "main.asm", 5);
+        info.lines.insert(9, "main.asm", 6);
+        info.subroutines.insert(0, "main".to_string());
+        info.subroutines.insert(6, "fail".to_string());
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+        vm.set_debug_info(Some(info));
+
+        let fault = vm.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::StackUnderflow);
+        assert_eq!(
+            fault.location.as_ref().map(|l| l.to_string()),
+            Some("fail+0x3 (main.asm:6)".to_string())
+        );
+
+        let mut report = Vec::new();
+        fault.write_report(&mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("  function:    fail\n"));
+        assert!(report.contains("  source:      main.asm:6\n"));
+    }
+
+    #[test]
+    fn access_violations_name_the_region() {
+        // The stack may only be written by the system.
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        let stack = vm.memory.stack_start();
+        let program = Instruction::MovRegMem(Registers::R1, stack).encode();
+        vm.load_program(&program, 0).unwrap();
+
+        let fault = vm.run().unwrap_err();
+        assert_eq!(
+            fault.kind,
+            FaultKind::AccessViolation {
+                address: stack,
+                region: "Stack".to_string()
+            }
+        );
+        assert_eq!(fault.security_context, SecurityContext::User);
+
+        // Undecodable instructions are not reported.
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&[0x34, 0x12], 0).unwrap();
+
+        let fault = vm.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::InvalidOpCode { opcode: 0x1234 });
+        assert_eq!(fault.instruction, None);
+    }
+
+    fn register(vm: &VirtualMachine, register: Registers) -> RegisterValue {
+        vm.cpu
+            .registers
+            .get_register_value(register, SecurityContext::System)
+            .unwrap()
+    }
+
+    #[test]
+    fn faults_are_delivered_to_the_handler() {
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        let stack = vm.memory.stack_start();
+        let program = [
+            Instruction::MovLitReg(23, Registers::FH),
+            Instruction::MovRegMem(Registers::R1, stack),
+            Instruction::MovLitReg(1, Registers::R2),
+            Instruction::HLT(),
+            // handler (23)
+            Instruction::Pop(Registers::R3),
+            Instruction::Pop(Registers::R4),
+            Instruction::Pop(Registers::R5),
+            // Resume after the faulting instruction.
+            Instruction::AddLitReg(7, Registers::R5),
+            Instruction::PshReg(Registers::AC),
+            Instruction::IRet(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+        vm.load_program(&bytes, 0).unwrap();
+        vm.enable_journal(100);
+
+        assert!(vm.run().is_ok());
+        assert_eq!(register(&vm, Registers::R2), RegisterValue::I32(1));
+        assert_eq!(register(&vm, Registers::R3), RegisterValue::I32(1));
+        assert_eq!(
+            register(&vm, Registers::R4),
+            RegisterValue::I32(stack as i32)
+        );
+        assert_eq!(register(&vm, Registers::R5), RegisterValue::I32(7));
+        assert_eq!(vm.cpu.get_flags().unwrap(), CpuFlags::empty());
+        assert_eq!(vm.cpu.get_stack_pointer().unwrap(), vm.memory.stack_end());
+
+        // Undoing the handler and the delivery of the fault
+        // returns to the faulting instruction.
+        for _ in 0..9 {
+            assert!(vm.step_back().unwrap());
+        }
+        assert_eq!(vm.cpu.get_instruction_pointer().unwrap(), 7);
+        assert_eq!(vm.cpu.get_flags().unwrap(), CpuFlags::empty());
+        assert_eq!(vm.cpu.get_stack_pointer().unwrap(), vm.memory.stack_end());
+    }
+
+    #[test]
+    fn stack_overflows_are_delivered_with_an_empty_stack() {
+        let program = [
+            Instruction::MovLitReg(19, Registers::FH),
+            Instruction::PshLit(1),
+            Instruction::PshLit(2),
+            // handler (19)
+            Instruction::Pop(Registers::R3),
+            Instruction::Pop(Registers::R4),
+            Instruction::Pop(Registers::R5),
+            Instruction::HLT(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 3, false);
+        vm.load_program(&bytes, 0).unwrap();
+
+        // Fill the stack, leaving room for a single value.
+        vm.cpu
+            .set_stack_pointer(vm.memory.stack_start() + 4)
+            .unwrap();
+
+        assert!(vm.run().is_ok());
+        assert_eq!(register(&vm, Registers::R3), RegisterValue::I32(5));
+        assert_eq!(register(&vm, Registers::R5), RegisterValue::I32(13));
+        assert_eq!(vm.cpu.get_flags().unwrap(), CpuFlags::FAULT);
+    }
+
+    #[test]
+    fn faults_within_the_handler_are_double_faults() {
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        let stack = vm.memory.stack_start();
+        let mut bytes: Vec<u8> = [
+            Instruction::MovLitReg(14, Registers::FH),
+            Instruction::MovRegMem(Registers::R1, stack),
+        ]
+        .iter()
+        .flat_map(|i| i.encode())
+        .collect();
+        // handler (14)
+        bytes.extend_from_slice(&[0x34, 0x12]);
+        vm.load_program(&bytes, 0).unwrap();
+
+        let fault = vm.run().unwrap_err();
+        assert_eq!(
+            fault.kind,
+            FaultKind::DoubleFault {
+                fault: Box::new(FaultKind::InvalidOpCode { opcode: 0x1234 })
+            }
+        );
+        assert_eq!(fault.address, 14);
+        assert!(vm.cpu.is_halted());
+    }
+}
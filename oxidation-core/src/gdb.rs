@@ -0,0 +1,849 @@
+//! A GDB remote serial protocol stub.
+//!
+//! The stub allows a virtual machine to be debugged by GDB, or any other client of the
+//! protocol, over a TCP or Unix socket. It supports reading and writing the registers and
+//! memory, software breakpoints, watchpoints, single-stepping and continuing. The registers
+//! are described to the client by a target description, with register numbers following
+//! the order of [`Registers`]. A running target may be interrupted by the client.
+
+use crate::cpu::CPU;
+use crate::execution::RunOutcome;
+use crate::memory::Memory;
+use crate::registers::{RegisterValue, Registers};
+use crate::security_context::SecurityContext;
+use crate::virtual_machine::VirtualMachine;
+use crate::watchpoints::{WatchAccess, WatchEvent, Watchpoint, WatchpointHit};
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// The target description sent to clients that request it.
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.oxidation.core">
+    <reg name="r1" bitsize="32" type="int32" regnum="0"/>
+    <reg name="r2" bitsize="32" type="int32"/>
+    <reg name="r3" bitsize="32" type="int32"/>
+    <reg name="r4" bitsize="32" type="int32"/>
+    <reg name="r5" bitsize="32" type="int32"/>
+    <reg name="r6" bitsize="32" type="int32"/>
+    <reg name="r7" bitsize="32" type="int32"/>
+    <reg name="r8" bitsize="32" type="int32"/>
+    <reg name="ac" bitsize="32" type="int32"/>
+    <reg name="fl" bitsize="32" type="int32"/>
+    <reg name="ip" bitsize="32" type="code_ptr"/>
+    <reg name="sp" bitsize="32" type="data_ptr"/>
+    <reg name="fh" bitsize="32" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// The signal reported when execution is interrupted by the client.
+const SIGINT: u8 = 2;
+
+/// The signal reported when execution stops normally.
+const SIGTRAP: u8 = 5;
+
+/// The signal reported when execution stops due to a fault.
+const SIGSEGV: u8 = 11;
+
+/// The number of cycles executed between checks for an interrupt from the client.
+const RESUME_SLICE: u64 = 10_000;
+
+/// The byte sent by the client to interrupt a running target.
+const INTERRUPT: u8 = 0x03;
+
+/// A connection to a client, which can be checked for an interrupt while the target runs.
+pub trait Connection: Read + Write {
+    /// Returns whether the client has sent an interrupt or disconnected, without blocking.
+    fn interrupted(&mut self) -> io::Result<bool>;
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        poll_interrupt(self, TcpStream::set_nonblocking)
+    }
+}
+
+#[cfg(unix)]
+impl Connection for std::os::unix::net::UnixStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        poll_interrupt(self, std::os::unix::net::UnixStream::set_nonblocking)
+    }
+}
+
+/// Whether the session should continue after a packet has been handled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Session {
+    Continue,
+    Close,
+}
+
+/// A GDB remote serial protocol stub attached to a virtual machine.
+pub struct GdbStub<'a> {
+    vm: &'a mut VirtualMachine,
+    breakpoints: BTreeSet<u32>,
+    /// The identifiers of the watchpoints set by the client, keyed by their kind, address and length.
+    watchpoints: HashMap<(u32, u32, u32), u32>,
+    no_ack: bool,
+}
+
+impl<'a> GdbStub<'a> {
+    /// Create a new stub.
+    ///
+    /// # Arguments
+    ///
+    /// * `vm` - the virtual machine, with a program loaded.
+    pub fn new(vm: &'a mut VirtualMachine) -> Self {
+        Self {
+            vm,
+            breakpoints: BTreeSet::new(),
+            watchpoints: HashMap::new(),
+            no_ack: false,
+        }
+    }
+
+    /// Accept a single client on a TCP socket and serve it until it detaches.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address on which to listen, such as `127.0.0.1:1234`.
+    pub fn serve_tcp<A: ToSocketAddrs>(&mut self, address: A) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        self.serve(stream)
+    }
+
+    /// Accept a single client on a Unix socket and serve it until it detaches.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path of the socket, which must not already exist.
+    #[cfg(unix)]
+    pub fn serve_unix<P: AsRef<std::path::Path>>(&mut self, path: P) -> io::Result<()> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        let (stream, _) = listener.accept()?;
+
+        self.serve(stream)
+    }
+
+    /// Serve a connected client until it detaches, kills the target or disconnects.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - the connection to the client.
+    pub fn serve<S: Connection>(&mut self, mut stream: S) -> io::Result<()> {
+        self.no_ack = false;
+
+        loop {
+            let packet = match read_packet(&mut stream)? {
+                Some(Ok(p)) => p,
+                Some(Err(())) => {
+                    // The checksum did not match, so the client should retransmit.
+                    stream.write_all(b"-")?;
+                    continue;
+                }
+                None => return Ok(()),
+            };
+
+            if !self.no_ack {
+                stream.write_all(b"+")?;
+            }
+
+            let (reply, session) = self.handle(&packet, &mut stream)?;
+            write_packet(&mut stream, &reply)?;
+            if !self.no_ack {
+                read_ack(&mut stream)?;
+            }
+
+            if session == Session::Close {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handle a single packet, returning the reply.
+    fn handle<S: Connection>(
+        &mut self,
+        packet: &str,
+        stream: &mut S,
+    ) -> io::Result<(String, Session)> {
+        let mut session = Session::Continue;
+
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => self.stop_reason(),
+            Some(b'g') => self.read_registers(),
+            Some(b'G') => self.write_registers(&packet[1..]),
+            Some(b'p') => self.read_register(&packet[1..]),
+            Some(b'P') => self.write_register(&packet[1..]),
+            Some(b'm') => self.read_memory(&packet[1..]),
+            Some(b'M') => self.write_memory(&packet[1..]),
+            Some(b'Z') => self.set_breakpoint(&packet[1..], true),
+            Some(b'z') => self.set_breakpoint(&packet[1..], false),
+            Some(b's') => self.step(),
+            Some(b'c') => self.resume(stream)?,
+            Some(b'H') => "OK".to_string(),
+            Some(b'D') => {
+                session = Session::Close;
+                "OK".to_string()
+            }
+            Some(b'k') => {
+                session = Session::Close;
+                String::new()
+            }
+            Some(b'q') | Some(b'Q') => self.query(packet),
+            _ => String::new(),
+        };
+
+        Ok((reply, session))
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+".to_string();
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return read_xfer(TARGET_XML, args);
+        }
+
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        register_ids()
+            .map(|r| match self.register_value(r) {
+                Some(value) => encode_hex(&value.to_le_bytes()),
+                None => "xxxxxxxx".to_string(),
+            })
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let bytes = match decode_hex(args) {
+            Some(b) => b,
+            None => return error(1),
+        };
+
+        for (register, chunk) in register_ids().zip(bytes.chunks_exact(4)) {
+            let value = i32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            if !self.set_register_value(register, value) {
+                return error(2);
+            }
+        }
+
+        "OK".to_string()
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        let value = parse_register(args).and_then(|r| self.register_value(r));
+
+        match value {
+            Some(value) => encode_hex(&value.to_le_bytes()),
+            None => error(1),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let (register, value) = match args.split_once('=') {
+            Some((r, v)) => (r, v),
+            None => return error(1),
+        };
+
+        let register = parse_register(register);
+        let value = decode_hex(value).filter(|b| b.len() == 4);
+
+        match (register, value) {
+            (Some(r), Some(b)) => {
+                let value = i32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                if self.set_register_value(r, value) {
+                    "OK".to_string()
+                } else {
+                    error(2)
+                }
+            }
+            _ => error(1),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let (address, len) = match parse_address_length(args) {
+            Some(v) => v,
+            None => return error(1),
+        };
+
+        match self
+            .vm
+            .memory
+            .read_bytes(address, len, SecurityContext::System)
+        {
+            Ok(bytes) => encode_hex(bytes),
+            Err(_) => error(14),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let (location, data) = match args.split_once(':') {
+            Some(v) => v,
+            None => return error(1),
+        };
+
+        let bytes = decode_hex(data);
+        let (address, len) = match (parse_address_length(location), bytes.as_ref()) {
+            (Some((a, l)), Some(b)) if l as usize == b.len() => (a, l),
+            _ => return error(1),
+        };
+
+        if len == 0 {
+            return "OK".to_string();
+        }
+
+        match self.vm.memory.write_bytes(
+            address,
+            bytes.as_deref().unwrap(),
+            SecurityContext::System,
+        ) {
+            Ok(_) => "OK".to_string(),
+            Err(_) => error(14),
+        }
+    }
+
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let mut parts = args.split(',').map(parse_hex);
+        let (kind, address, len) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(Some(k)), Some(Some(a)), Some(Some(l))) => (k, a, l),
+            _ => return error(1),
+        };
+
+        let access = match kind {
+            0 => {
+                if insert {
+                    self.breakpoints.insert(address);
+                } else {
+                    self.breakpoints.remove(&address);
+                }
+                return "OK".to_string();
+            }
+            2 => WatchAccess::Write,
+            3 => WatchAccess::Read,
+            4 => WatchAccess::ReadWrite,
+            // Hardware breakpoints are not supported.
+            _ => return String::new(),
+        };
+
+        let key = (kind, address, len.max(1));
+        if insert {
+            if !self.watchpoints.contains_key(&key) {
+                let id = self
+                    .vm
+                    .add_watchpoint(Watchpoint::memory(address, key.2, access));
+                self.watchpoints.insert(key, id);
+            }
+        } else if let Some(id) = self.watchpoints.remove(&key) {
+            self.vm.remove_watchpoint(id);
+        }
+
+        "OK".to_string()
+    }
+
+    /// Returns the stop reply describing why the target is stopped.
+    fn stop_reason(&self) -> String {
+        if self.vm.cpu.is_halted() {
+            "W00".to_string()
+        } else {
+            format!("S{:02x}", SIGTRAP)
+        }
+    }
+
+    fn step(&mut self) -> String {
+        if self.vm.cpu.is_halted() {
+            return "W00".to_string();
+        }
+
+        let outcome = self.vm.step();
+        if outcome.fault.is_some() {
+            format!("S{:02x}", SIGSEGV)
+        } else if let Some(hit) = &outcome.watchpoint {
+            self.watchpoint_reply(hit)
+        } else if outcome.halted {
+            "W00".to_string()
+        } else {
+            format!("S{:02x}", SIGTRAP)
+        }
+    }
+
+    fn resume<S: Connection>(&mut self, stream: &mut S) -> io::Result<String> {
+        if self.vm.cpu.is_halted() {
+            return Ok("W00".to_string());
+        }
+
+        // The target runs in slices, so that the client may interrupt it between them.
+        let breakpoints = &self.breakpoints;
+        let at_breakpoint = |cpu: &CPU, _: &Memory| match cpu.get_instruction_pointer() {
+            Ok(ip) => breakpoints.contains(&ip),
+            Err(_) => false,
+        };
+        let mut outcome = self.vm.run_for_until(RESUME_SLICE, at_breakpoint);
+        while matches!(outcome, RunOutcome::BudgetExhausted) {
+            if stream.interrupted()? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+
+            // The predicate is not evaluated before the first instruction of a slice.
+            outcome = if at_breakpoint(&self.vm.cpu, &self.vm.memory) {
+                RunOutcome::Breakpoint
+            } else {
+                self.vm.run_for_until(RESUME_SLICE, at_breakpoint)
+            };
+        }
+
+        Ok(match outcome {
+            RunOutcome::Breakpoint => format!("T{:02x}swbreak:;", SIGTRAP),
+            RunOutcome::Fault(_) => format!("S{:02x}", SIGSEGV),
+            RunOutcome::Watchpoint(hit) => self.watchpoint_reply(&hit),
+            _ => "W00".to_string(),
+        })
+    }
+
+    /// Returns the stop reply reporting a triggered watchpoint.
+    fn watchpoint_reply(&self, hit: &WatchpointHit) -> String {
+        // Watchpoints not set by the client, such as those on registers, are reported
+        // as a plain stop, as the protocol has no means to describe them.
+        let kind = self
+            .watchpoints
+            .iter()
+            .find(|(_, id)| **id == hit.id)
+            .map(|((kind, _, _), _)| *kind);
+        let address = match hit.event {
+            WatchEvent::Read { address, .. } | WatchEvent::Write { address, .. } => address,
+            WatchEvent::Register { .. } => return format!("S{:02x}", SIGTRAP),
+        };
+
+        match kind {
+            Some(2) => format!("T{:02x}watch:{:x};", SIGTRAP, address),
+            Some(3) => format!("T{:02x}rwatch:{:x};", SIGTRAP, address),
+            Some(4) => format!("T{:02x}awatch:{:x};", SIGTRAP, address),
+            _ => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    fn register_value(&self, register: Registers) -> Option<i32> {
+        match self
+            .vm
+            .cpu
+            .registers
+            .get_register_value(register, SecurityContext::System)
+        {
+            Ok(RegisterValue::I32(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn set_register_value(&mut self, register: Registers, value: i32) -> bool {
+        self.vm
+            .cpu
+            .registers
+            .set_register_value(register, RegisterValue::I32(value), SecurityContext::System)
+            .is_ok()
+    }
+}
+
+/// Returns an iterator over the registers, in the order of their register numbers.
+fn register_ids() -> impl Iterator<Item = Registers> {
+    (0..=u8::MAX).map_while(Registers::from_u8)
+}
+
+/// Read the next packet, skipping acknowledgements and interrupts.
+///
+/// Returns `None` if the client disconnected, or `Some(Err(()))` if the checksum was invalid.
+fn read_packet<S: Read>(stream: &mut S) -> io::Result<Option<Result<String, ()>>> {
+    // Discard everything prior to the start of the packet.
+    loop {
+        match read_byte(stream)? {
+            Some(b'$') => break,
+            Some(_) => continue,
+            None => return Ok(None),
+        }
+    }
+
+    let mut data = Vec::new();
+    let mut checksum: u8 = 0;
+    loop {
+        match read_byte(stream)? {
+            Some(b'#') => break,
+            Some(b) => {
+                checksum = checksum.wrapping_add(b);
+                data.push(b);
+            }
+            None => return Ok(None),
+        }
+    }
+
+    let mut expected = [0u8; 2];
+    stream.read_exact(&mut expected)?;
+    let expected = std::str::from_utf8(&expected)
+        .ok()
+        .and_then(|s| u8::from_str_radix(s, 16).ok());
+    if expected != Some(checksum) {
+        return Ok(Some(Err(())));
+    }
+
+    Ok(Some(Ok(unescape(&data))))
+}
+
+fn read_byte<S: Read>(stream: &mut S) -> io::Result<Option<u8>> {
+    let mut byte = [0u8; 1];
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// Drain the bytes received from the client without blocking, returning whether an
+/// interrupt was among them or the client disconnected.
+///
+/// # Arguments
+///
+/// * `stream` - the connection to the client.
+/// * `set_nonblocking` - the function switching the connection to and from non-blocking mode.
+fn poll_interrupt<S: Read>(
+    stream: &mut S,
+    set_nonblocking: fn(&S, bool) -> io::Result<()>,
+) -> io::Result<bool> {
+    set_nonblocking(stream, true)?;
+
+    let mut interrupted = false;
+    let mut byte = [0u8; 1];
+    let result = loop {
+        match stream.read(&mut byte) {
+            Ok(0) => break Ok(true),
+            Ok(_) => interrupted |= byte[0] == INTERRUPT,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(interrupted),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => break Err(e),
+        }
+    };
+
+    set_nonblocking(stream, false)?;
+    result
+}
+
+/// Read the acknowledgement of a reply. Retransmission requests are not supported.
+fn read_ack<S: Read>(stream: &mut S) -> io::Result<()> {
+    read_byte(stream).map(|_| ())
+}
+
+fn write_packet<S: Write>(stream: &mut S, data: &str) -> io::Result<()> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for b in data.bytes() {
+        if matches!(b, b'$' | b'#' | b'}' | b'*') {
+            escaped.push(b'}');
+            escaped.push(b ^ 0x20);
+        } else {
+            escaped.push(b);
+        }
+    }
+
+    let checksum = escaped.iter().fold(0u8, |c, b| c.wrapping_add(*b));
+
+    stream.write_all(b"$")?;
+    stream.write_all(&escaped)?;
+    write!(stream, "#{:02x}", checksum)?;
+    stream.flush()
+}
+
+fn unescape(data: &[u8]) -> String {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+    while let Some(b) = iter.next() {
+        match b {
+            b'}' => {
+                if let Some(next) = iter.next() {
+                    bytes.push(next ^ 0x20);
+                }
+            }
+            _ => bytes.push(*b),
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Reply to a request for part of a document, given as `offset,length`.
+fn read_xfer(document: &str, args: &str) -> String {
+    let (offset, len) = match parse_address_length(args) {
+        Some(v) => (v.0 as usize, v.1 as usize),
+        None => return error(1),
+    };
+
+    let bytes = document.as_bytes();
+    if offset >= bytes.len() {
+        return "l".to_string();
+    }
+
+    let end = (offset + len).min(bytes.len());
+    let marker = if end == bytes.len() { 'l' } else { 'm' };
+
+    format!("{}{}", marker, &document[offset..end])
+}
+
+fn parse_address_length(args: &str) -> Option<(u32, u32)> {
+    let (address, len) = args.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+/// Parse a register number, rejecting numbers that do not identify a register.
+fn parse_register(s: &str) -> Option<Registers> {
+    parse_hex(s)
+        .and_then(|n| u8::try_from(n).ok())
+        .and_then(Registers::from_u8)
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s, 16).ok()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn error(code: u8) -> String {
+    format!("E{:02x}", code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::enums::Instruction;
+    use std::net::TcpStream;
+    use std::thread;
+
+    /// A minimal scripted client of the remote serial protocol.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        fn request(&mut self, data: &str) -> String {
+            write_packet(&mut self.stream, data).unwrap();
+            assert_eq!(read_byte(&mut self.stream).unwrap(), Some(b'+'));
+
+            let reply = read_packet(&mut self.stream).unwrap().unwrap().unwrap();
+            self.stream.write_all(b"+").unwrap();
+
+            reply
+        }
+    }
+
+    /// Run a client script against a stub serving a small program.
+    fn session<F>(script: F) -> (VirtualMachine, Vec<String>)
+    where
+        F: FnOnce(&mut Client) -> Vec<String> + Send + 'static,
+    {
+        let program = [
+            Instruction::MovLitReg(5, Registers::R1),
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::MovRegMem(Registers::AC, 500),
+            Instruction::HLT(),
+        ];
+
+        session_with(&program, script)
+    }
+
+    /// Run a client script against a stub serving the given program.
+    fn session_with<F>(program: &[Instruction], script: F) -> (VirtualMachine, Vec<String>)
+    where
+        F: FnOnce(&mut Client) -> Vec<String> + Send + 'static,
+    {
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let stream = TcpStream::connect(address).unwrap();
+            stream.set_nodelay(true).unwrap();
+
+            let mut client = Client { stream };
+            let replies = script(&mut client);
+            client.request("D");
+            replies
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        GdbStub::new(&mut vm).serve(stream).unwrap();
+
+        (vm, client.join().unwrap())
+    }
+
+    #[test]
+    fn gdb_describes_target() {
+        let (_, replies) = session(|c| {
+            vec![
+                c.request("qSupported:multiprocess+;swbreak+"),
+                c.request("qXfer:features:read:target.xml:0,ffff"),
+                c.request("qXfer:features:read:target.xml:0,10"),
+                c.request("?"),
+                c.request("vMustReplyEmpty"),
+            ]
+        });
+
+        assert!(replies[0].contains("qXfer:features:read+"));
+        assert_eq!(replies[1], format!("l{}", TARGET_XML));
+        assert_eq!(replies[2], format!("m{}", &TARGET_XML[..16]));
+        assert_eq!(replies[3], "S05");
+        assert_eq!(replies[4], "");
+    }
+
+    #[test]
+    fn gdb_registers_and_memory() {
+        let (vm, replies) = session(|c| {
+            vec![
+                c.request("g"),
+                c.request("P1=78563412"),
+                c.request("p1"),
+                c.request("M1f4,3:0a0b0c"),
+                c.request("m1f4,4"),
+                c.request("m100000,4"),
+                c.request("pff"),
+                c.request("p100"),
+            ]
+        });
+
+        // Every register is 32-bit: R1 to R8, AC, FL, IP, SP and FH.
+        assert_eq!(replies[0].len(), 13 * 8);
+        assert!(replies[0][..12 * 8].ends_with(&encode_hex(&vm.memory.stack_end().to_le_bytes())));
+        assert_eq!(replies[1], "OK");
+        assert_eq!(replies[2], "78563412");
+        assert_eq!(replies[3], "OK");
+        assert_eq!(replies[4], "0a0b0c00");
+        assert_eq!(replies[5], "E0e");
+        assert_eq!(replies[6], "E01");
+        assert_eq!(replies[7], "E01");
+
+        assert_eq!(
+            vm.cpu
+                .registers
+                .get_register_value(Registers::R2, SecurityContext::System)
+                .unwrap(),
+            RegisterValue::I32(0x12345678)
+        );
+    }
+
+    #[test]
+    fn gdb_breakpoints_step_and_continue() {
+        let (vm, replies) = session(|c| {
+            vec![
+                c.request("s"),
+                c.request("pa"),
+                c.request("Z0,e,2"),
+                c.request("c"),
+                c.request("pa"),
+                c.request("z0,e,2"),
+                c.request("c"),
+                c.request("c"),
+                c.request("?"),
+            ]
+        });
+
+        assert_eq!(replies[0], "S05");
+        assert_eq!(replies[1], "07000000");
+        assert_eq!(replies[2], "OK");
+        assert_eq!(replies[3], "T05swbreak:;");
+        assert_eq!(replies[4], "0e000000");
+        assert_eq!(replies[5], "OK");
+        assert_eq!(replies[6], "W00");
+        assert_eq!(replies[7], "W00");
+        assert_eq!(replies[8], "W00");
+
+        assert_eq!(vm.memory.read_i32(500, SecurityContext::User).unwrap(), 6);
+    }
+
+    #[test]
+    fn gdb_watchpoints() {
+        let (_, replies) = session(|c| {
+            vec![
+                c.request("Z2,1f4,4"),
+                c.request("c"),
+                c.request("pa"),
+                c.request("z2,1f4,4"),
+                c.request("Z1,0,2"),
+                c.request("c"),
+            ]
+        });
+
+        assert_eq!(replies[0], "OK");
+        assert_eq!(replies[1], "T05watch:1f4;");
+        assert_eq!(replies[2], "15000000");
+        assert_eq!(replies[3], "OK");
+        assert_eq!(replies[4], "");
+        assert_eq!(replies[5], "W00");
+    }
+
+    #[test]
+    fn gdb_interrupts_running_target() {
+        // A loop which never halts, as the accumulator is never one.
+        let program = [Instruction::JmpNotEq(1, 0)];
+        let (vm, replies) = session_with(&program, |c| {
+            write_packet(&mut c.stream, "c").unwrap();
+            assert_eq!(read_byte(&mut c.stream).unwrap(), Some(b'+'));
+            c.stream.write_all(&[INTERRUPT]).unwrap();
+
+            let reply = read_packet(&mut c.stream).unwrap().unwrap().unwrap();
+            c.stream.write_all(b"+").unwrap();
+
+            vec![reply, c.request("pa")]
+        });
+
+        assert_eq!(replies[0], "S02");
+        assert_eq!(replies[1], "00000000");
+        assert!(!vm.cpu.is_halted());
+        assert!(vm.cpu.instructions_executed() > 0);
+    }
+
+    #[test]
+    fn gdb_packets_are_framed() {
+        let mut buffer = Vec::new();
+        write_packet(&mut buffer, "a#b").unwrap();
+        assert_eq!(buffer, b"$a}\x03b#43");
+
+        let mut input = &b"+$a}\x03b#43"[..];
+        assert_eq!(
+            read_packet(&mut input).unwrap(),
+            Some(Ok("a#b".to_string()))
+        );
+
+        let mut input = &b"$g#00"[..];
+        assert_eq!(read_packet(&mut input).unwrap(), Some(Err(())));
+
+        let mut input = &b""[..];
+        assert_eq!(read_packet(&mut input).unwrap(), None);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::cpu::*;
+use crate::instructions::enums::{Instruction, OpCode};
+use crate::memory::Memory;
+use crate::registers::Registers;
+use crate::security_context::SecurityContext;
+use snafu::{ensure, OptionExt, ResultExt};
+
+type Result<T, E = CpuError> = std::result::Result<T, E>;
+
+/// The size, in bytes, of an encoded opcode.
+pub const OPCODE_SIZE: u32 = 2;
+
+/// The size, in bytes, of the largest encoded instruction.
+pub const MAX_INSTRUCTION_SIZE: u32 = 10;
+
+impl Instruction {
+    /// Returns the size, in bytes, of the encoded instruction.
+    pub fn size(&self) -> u32 {
+        let operands = operands(self.opcode()).unwrap_or_default();
+
+        OPCODE_SIZE + operands.iter().map(|o| o.size()).sum::<u32>()
+    }
+
+    /// Returns the encoded (little-endian) form of the instruction.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size() as usize);
+        bytes.extend_from_slice(&(self.opcode() as i16).to_le_bytes());
+
+        match *self {
+            Instruction::NOP() => {}
+            Instruction::MovLitReg(lit, reg) | Instruction::AddLitReg(lit, reg) => {
+                bytes.extend_from_slice(&lit.to_le_bytes());
+                bytes.push(reg as u8);
+            }
+            Instruction::MovRegReg(src, dst) => {
+                bytes.push(src as u8);
+                bytes.push(dst as u8);
+            }
+            Instruction::Out(reg, port) => {
+                bytes.push(reg as u8);
+                bytes.push(port);
+            }
+            Instruction::In(port, reg) => {
+                bytes.push(port);
+                bytes.push(reg as u8);
+            }
+            Instruction::MovRegMem(reg, addr) => {
+                bytes.push(reg as u8);
+                bytes.extend_from_slice(&addr.to_le_bytes());
+            }
+            Instruction::MovMemReg(addr, reg) => {
+                bytes.extend_from_slice(&addr.to_le_bytes());
+                bytes.push(reg as u8);
+            }
+            Instruction::PshLit(lit) => bytes.extend_from_slice(&lit.to_le_bytes()),
+            Instruction::PshReg(reg) | Instruction::Pop(reg) | Instruction::CalReg(reg) => {
+                bytes.push(reg as u8);
+            }
+            Instruction::CalLit(addr) => bytes.extend_from_slice(&addr.to_le_bytes()),
+            Instruction::JmpNotEq(lit, addr) | Instruction::JeqLit(lit, addr) => {
+                bytes.extend_from_slice(&lit.to_le_bytes());
+                bytes.extend_from_slice(&addr.to_le_bytes());
+            }
+            Instruction::JneReg(reg, addr) | Instruction::JeqReg(reg, addr) => {
+                bytes.push(reg as u8);
+                bytes.extend_from_slice(&addr.to_le_bytes());
+            }
+            Instruction::Ret() | Instruction::IRet() | Instruction::HLT() => {}
+        }
+
+        bytes
+    }
+
+    /// Decode the instruction found at the start of the byte slice.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - the bytes from which the instruction should be decoded.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = ArgumentReader::new(bytes);
+
+        let value = reader.read_i16()?;
+        let opcode = OpCode::from_i16(value).context(InvalidOpCode { opcode: value })?;
+
+        let operands = operands(opcode).context(UnimplementedOpCode { opcode })?;
+
+        // Every operand is read as laid out by the operand table, and then interpreted.
+        let mut values = [0u32; 2];
+        for (value, operand) in values.iter_mut().zip(operands) {
+            *value = match operand {
+                Operand::Register => reader.read_register()? as u32,
+                Operand::Byte => reader.read_u8()? as u32,
+                Operand::Literal | Operand::Address | Operand::Target => reader.read_u32()?,
+            };
+        }
+        let [a, b] = values;
+        let reg = |value: u32| Registers::from_u8(value as u8).context(InvalidRegisterId);
+
+        let ins = match opcode {
+            OpCode::NOP => Instruction::NOP(),
+            OpCode::MovLitReg => Instruction::MovLitReg(a as i32, reg(b)?),
+            OpCode::MovRegReg => Instruction::MovRegReg(reg(a)?, reg(b)?),
+            OpCode::MovRegMem => Instruction::MovRegMem(reg(a)?, b),
+            OpCode::MovMemReg => Instruction::MovMemReg(a, reg(b)?),
+            OpCode::AddLitReg => Instruction::AddLitReg(a as i32, reg(b)?),
+            OpCode::Out => Instruction::Out(reg(a)?, b as u8),
+            OpCode::In => Instruction::In(a as u8, reg(b)?),
+            OpCode::PshLit => Instruction::PshLit(a as i32),
+            OpCode::PshReg => Instruction::PshReg(reg(a)?),
+            OpCode::Pop => Instruction::Pop(reg(a)?),
+            OpCode::CalLit => Instruction::CalLit(a),
+            OpCode::CalReg => Instruction::CalReg(reg(a)?),
+            OpCode::Ret => Instruction::Ret(),
+            OpCode::IRet => Instruction::IRet(),
+            OpCode::JmpNotEq => Instruction::JmpNotEq(a as i32, b),
+            OpCode::JneReg => Instruction::JneReg(reg(a)?, b),
+            OpCode::JeqLit => Instruction::JeqLit(a as i32, b),
+            OpCode::JeqReg => Instruction::JeqReg(reg(a)?, b),
+            OpCode::Hlt => Instruction::HLT(),
+            _ => return UnimplementedOpCode { opcode }.fail(),
+        };
+
+        Ok(ins)
+    }
+
+    /// Decode the instruction found at an address in memory, without regard for
+    /// the access flags of the memory regions.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory from which the instruction should be decoded.
+    /// * `address` - the address of the instruction.
+    pub fn decode_at(mem: &Memory, address: u32) -> Result<Self> {
+        let available = (mem.len() as u32).saturating_sub(address);
+        let bytes = mem
+            .read_bytes(
+                address,
+                available.min(MAX_INSTRUCTION_SIZE),
+                SecurityContext::System,
+            )
+            .context(MemoryFault)?;
+
+        Self::decode(bytes)
+    }
+}
+
+/// The kinds of operand that follow an opcode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Operand {
+    /// A register ID.
+    Register,
+    /// An 8-bit literal, such as a port.
+    Byte,
+    /// A 32-bit literal.
+    Literal,
+    /// The 32-bit address of data in memory.
+    Address,
+    /// The 32-bit address of an instruction to which control may be transferred.
+    Target,
+}
+
+impl Operand {
+    /// Returns the size, in bytes, of the encoded operand.
+    pub(crate) fn size(self) -> u32 {
+        match self {
+            Operand::Register | Operand::Byte => 1,
+            Operand::Literal | Operand::Address | Operand::Target => 4,
+        }
+    }
+}
+
+/// Returns the operands that follow an opcode, in the order they are encoded, or
+/// `None` if the opcode is not supported by the CPU.
+///
+/// # Arguments
+///
+/// * `opcode` - the opcode.
+pub(crate) fn operands(opcode: OpCode) -> Option<&'static [Operand]> {
+    use Operand::*;
+
+    let operands: &[Operand] = match opcode {
+        OpCode::NOP | OpCode::Ret | OpCode::IRet | OpCode::Hlt => &[],
+        OpCode::MovLitReg | OpCode::AddLitReg => &[Literal, Register],
+        OpCode::MovRegReg => &[Register, Register],
+        OpCode::MovRegMem => &[Register, Address],
+        OpCode::MovMemReg => &[Address, Register],
+        OpCode::Out => &[Register, Byte],
+        OpCode::In => &[Byte, Register],
+        OpCode::PshLit => &[Literal],
+        OpCode::PshReg | OpCode::Pop | OpCode::CalReg => &[Register],
+        OpCode::CalLit => &[Target],
+        OpCode::JmpNotEq | OpCode::JeqLit => &[Literal, Target],
+        OpCode::JneReg | OpCode::JeqReg => &[Register, Target],
+        _ => return None,
+    };
+
+    Some(operands)
+}
+
+/// A cursor over the bytes of an encoded instruction.
+struct ArgumentReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ArgumentReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        ensure!(self.position + N <= self.bytes.len(), TruncatedInstruction);
+
+        let mut buffer = [0u8; N];
+        buffer.copy_from_slice(&self.bytes[self.position..self.position + N]);
+        self.position += N;
+
+        Ok(buffer)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.take::<2>()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take::<4>()?))
+    }
+
+    fn read_register(&mut self) -> Result<Registers> {
+        let [value] = self.take::<1>()?;
+        Registers::from_u8(value).context(InvalidRegisterId)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let instructions = vec![
+            Instruction::NOP(),
+            Instruction::MovLitReg(0x7FFF_0000, Registers::R1),
+            Instruction::MovRegReg(Registers::R1, Registers::R8),
+            Instruction::MovRegMem(Registers::AC, 0xDEAD_BEEF),
+            Instruction::MovMemReg(12, Registers::R2),
+            Instruction::AddLitReg(-123, Registers::R3),
+            Instruction::Out(Registers::R5, 0xFE),
+            Instruction::In(0x01, Registers::R6),
+            Instruction::PshLit(-1),
+            Instruction::PshReg(Registers::R4),
+            Instruction::Pop(Registers::R7),
+            Instruction::CalLit(0x100),
+            Instruction::CalReg(Registers::R1),
+            Instruction::Ret(),
+            Instruction::IRet(),
+            Instruction::JmpNotEq(-5, 0x200),
+            Instruction::JneReg(Registers::R2, 0x300),
+            Instruction::JeqLit(i32::MIN, u32::MAX),
+            Instruction::JeqReg(Registers::AC, 0),
+            Instruction::HLT(),
+        ];
+
+        for ins in instructions {
+            let bytes = ins.encode();
+            assert_eq!(bytes.len() as u32, ins.size());
+            assert_eq!(Instruction::decode(&bytes).unwrap(), ins);
+        }
+    }
+
+    #[test]
+    fn decode_rejects_bad_input() {
+        assert!(matches!(
+            Instruction::decode(&[0x34, 0x12]),
+            Err(CpuError::InvalidOpCode { opcode: 0x1234 })
+        ));
+        assert!(matches!(
+            Instruction::decode(&[0x0B, 0x00, 0x7B]),
+            Err(CpuError::TruncatedInstruction)
+        ));
+        assert!(matches!(
+            Instruction::decode(&[0x0B, 0x00, 0x7B, 0x00, 0x00, 0x00, 0xFF]),
+            Err(CpuError::InvalidRegisterId)
+        ));
+    }
+}
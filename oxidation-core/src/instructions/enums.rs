@@ -65,15 +65,33 @@ pub enum InstructionSizeHint {
     DWord,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     //Subroutine(i32, String),
     NOP(),
+    MovLitReg(i32, Registers),
+    MovRegReg(Registers, Registers),
+    MovRegMem(Registers, u32),
+    MovMemReg(u32, Registers),
     AddLitReg(i32, Registers),
+    Out(Registers, u8),
+    In(u8, Registers),
+    PshLit(i32),
+    PshReg(Registers),
+    Pop(Registers),
+    CalLit(u32),
+    CalReg(Registers),
+    Ret(),
+    IRet(),
+    JmpNotEq(i32, u32),
+    JneReg(Registers, u32),
+    JeqLit(i32, u32),
+    JeqReg(Registers, u32),
     HLT(),
 }
 
 #[repr(i16)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum OpCode {
     /// <summary>
     /// Subroutine - a pseudo-opcode used to identify a
@@ -280,10 +298,6 @@ pub enum OpCode {
     /// to the literal A then jump to an address specified by the
     /// literal B.
     /// </summary>
-    /// <remarks>
-    /// This address is treated as being relative to the base address
-    /// of the executable memory region in which the program resides.
-    /// </remarks>
     JmpNotEq,
     /// <summary>
     /// Jump If Not Equal Register - if the accumulator is not equal
@@ -381,7 +395,21 @@ pub enum OpCode {
     Ret,
 
     Pushl,
+    /// <summary>
+    /// Output Register to Port - write the value of the register
+    /// to the device attached to the I/O port.
+    /// </summary>
     Out,
+    /// <summary>
+    /// Input Port to Register - read a value from the device
+    /// attached to the I/O port into the register.
+    /// </summary>
+    In,
+    /// <summary>
+    /// Return from Fault Handler - return from a fault handler to the
+    /// address on the stack, ending the handling of the fault.
+    /// </summary>
+    IRet,
 
     /// <summary>
     /// Halt - halt the execution of the virtual machine.
@@ -389,11 +417,168 @@ pub enum OpCode {
     Hlt = 32767,
 }
 
+impl OpCode {
+    /// Returns the opcode that corresponds to the specified encoded value, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - the encoded opcode value.
+    pub fn from_i16(value: i16) -> Option<Self> {
+        let opcode = match value {
+            -2 => OpCode::Subroutine,
+            -1 => OpCode::Label,
+            0 => OpCode::NOP,
+            1 => OpCode::MovLitReg,
+            2 => OpCode::MovRegReg,
+            3 => OpCode::MovRegMem,
+            4 => OpCode::MovMemReg,
+            5 => OpCode::MovLitMem,
+            6 => OpCode::MovRegPtrReg,
+            7 => OpCode::MovHRegPtrReg,
+            8 => OpCode::MovLitOffReg,
+            9 => OpCode::Swap,
+            10 => OpCode::AddRegReg,
+            11 => OpCode::AddLitReg,
+            12 => OpCode::SubLitReg,
+            13 => OpCode::SubRegLit,
+            14 => OpCode::SubRegReg,
+            15 => OpCode::IncReg,
+            16 => OpCode::DegReg,
+            17 => OpCode::MulLitReg,
+            18 => OpCode::MulRegReg,
+            19 => OpCode::ModLitReg,
+            20 => OpCode::MocRegLit,
+            21 => OpCode::MocRegReg,
+            22 => OpCode::Bit,
+            23 => OpCode::LsfRegLit,
+            24 => OpCode::LsfRegReg,
+            25 => OpCode::RsfRegLit,
+            26 => OpCode::RsfRegReg,
+            27 => OpCode::AndRegLit,
+            28 => OpCode::AndRegReg,
+            29 => OpCode::OrRegLit,
+            30 => OpCode::OrRegReg,
+            31 => OpCode::XorRegLit,
+            32 => OpCode::XorRegReg,
+            33 => OpCode::Not,
+            34 => OpCode::JmpNotEq,
+            35 => OpCode::JneReg,
+            36 => OpCode::JeqReg,
+            37 => OpCode::JeqLit,
+            38 => OpCode::JltReg,
+            39 => OpCode::JltLit,
+            40 => OpCode::JgtReg,
+            41 => OpCode::JgtLit,
+            42 => OpCode::JleReg,
+            43 => OpCode::JleLit,
+            44 => OpCode::JgeReg,
+            45 => OpCode::JgeLit,
+            46 => OpCode::PshLit,
+            47 => OpCode::PshReg,
+            48 => OpCode::Pop,
+            49 => OpCode::CalLit,
+            50 => OpCode::CalReg,
+            51 => OpCode::Ret,
+            52 => OpCode::Pushl,
+            53 => OpCode::Out,
+            54 => OpCode::In,
+            55 => OpCode::IRet,
+            32767 => OpCode::Hlt,
+            _ => return None,
+        };
+
+        Some(opcode)
+    }
+}
+
+impl Instruction {
+    /// Returns the opcode used to encode the instruction.
+    pub fn opcode(&self) -> OpCode {
+        match *self {
+            Instruction::NOP() => OpCode::NOP,
+            Instruction::MovLitReg(_, _) => OpCode::MovLitReg,
+            Instruction::MovRegReg(_, _) => OpCode::MovRegReg,
+            Instruction::MovRegMem(_, _) => OpCode::MovRegMem,
+            Instruction::MovMemReg(_, _) => OpCode::MovMemReg,
+            Instruction::AddLitReg(_, _) => OpCode::AddLitReg,
+            Instruction::Out(_, _) => OpCode::Out,
+            Instruction::In(_, _) => OpCode::In,
+            Instruction::PshLit(_) => OpCode::PshLit,
+            Instruction::PshReg(_) => OpCode::PshReg,
+            Instruction::Pop(_) => OpCode::Pop,
+            Instruction::CalLit(_) => OpCode::CalLit,
+            Instruction::CalReg(_) => OpCode::CalReg,
+            Instruction::Ret() => OpCode::Ret,
+            Instruction::IRet() => OpCode::IRet,
+            Instruction::JmpNotEq(_, _) => OpCode::JmpNotEq,
+            Instruction::JneReg(_, _) => OpCode::JneReg,
+            Instruction::JeqLit(_, _) => OpCode::JeqLit,
+            Instruction::JeqReg(_, _) => OpCode::JeqReg,
+            Instruction::HLT() => OpCode::Hlt,
+        }
+    }
+
+    /// Returns true if the instruction may either jump or fall through to the next instruction.
+    pub fn is_conditional_branch(&self) -> bool {
+        matches!(
+            self,
+            Instruction::JmpNotEq(_, _)
+                | Instruction::JneReg(_, _)
+                | Instruction::JeqLit(_, _)
+                | Instruction::JeqReg(_, _)
+        )
+    }
+}
+
+/// Format a literal operand in hexadecimal, prefixed by `0x` where it would otherwise
+/// read as the name of a register.
+///
+/// # Arguments
+///
+/// * `value` - the value of the literal.
+fn literal_text<T: fmt::UpperHex>(value: T) -> String {
+    let text = format!("{:02X}", value);
+    let is_register = (0..Registers::COUNT as u8)
+        .filter_map(Registers::from_u8)
+        .any(|r| r.to_string() == text);
+
+    if is_register {
+        format!("0x{}", text)
+    } else {
+        text
+    }
+}
+
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let printable = match *self {
             Instruction::NOP() => String::from("nop"),
-            Instruction::AddLitReg(literal, reg) => format!("add {:02X}, {}", literal, reg),
+            Instruction::MovLitReg(literal, reg) => {
+                format!("mov {}, {}", literal_text(literal), reg)
+            }
+            Instruction::MovRegReg(src, dst) => format!("mov {}, {}", src, dst),
+            Instruction::MovRegMem(reg, addr) => format!("mov {}, [{:02X}]", reg, addr),
+            Instruction::MovMemReg(addr, reg) => format!("mov [{:02X}], {}", addr, reg),
+            Instruction::AddLitReg(literal, reg) => {
+                format!("add {}, {}", literal_text(literal), reg)
+            }
+            Instruction::Out(reg, port) => format!("out {}, {}", reg, literal_text(port)),
+            Instruction::In(port, reg) => format!("in {}, {}", literal_text(port), reg),
+            Instruction::PshLit(literal) => format!("push {}", literal_text(literal)),
+            Instruction::PshReg(reg) => format!("push {}", reg),
+            Instruction::Pop(reg) => format!("pop {}", reg),
+            Instruction::CalLit(addr) => format!("call [{:02X}]", addr),
+            Instruction::CalReg(reg) => format!("call {}", reg),
+            Instruction::Ret() => String::from("ret"),
+            Instruction::IRet() => String::from("iret"),
+            Instruction::JmpNotEq(literal, addr) => {
+                format!("jne {}, [{:02X}]", literal_text(literal), addr)
+            }
+            Instruction::JneReg(reg, addr) => format!("jne {}, [{:02X}]", reg, addr),
+            Instruction::JeqLit(literal, addr) => {
+                format!("jeq {}, [{:02X}]", literal_text(literal), addr)
+            }
+            Instruction::JeqReg(reg, addr) => format!("jeq {}, [{:02X}]", reg, addr),
             Instruction::HLT() => String::from("hlt"),
         };
         write!(f, "{}", printable)
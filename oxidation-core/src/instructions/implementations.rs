@@ -1,32 +1,182 @@
 use crate::cpu::*;
+use crate::memory::Memory;
 use crate::registers::*;
 use crate::security_context::SecurityContext;
+use snafu::ResultExt;
 
 type Result<T, E = CpuError> = std::result::Result<T, E>;
 
+pub fn mov_lit_reg(cpu: &mut CPU, imm: i32, reg: Registers) -> Result<bool> {
+    cpu.registers
+        .set_register_value(reg, RegisterValue::I32(imm), SecurityContext::User)?;
+
+    Ok(false)
+}
+
+pub fn mov_reg_reg(cpu: &mut CPU, src: Registers, dst: Registers) -> Result<bool> {
+    let val = cpu
+        .registers
+        .get_register_value(src, SecurityContext::User)?;
+    cpu.registers
+        .set_register_value(dst, val, SecurityContext::User)?;
+
+    Ok(false)
+}
+
+pub fn mov_reg_mem(cpu: &mut CPU, mem: &mut Memory, reg: Registers, addr: u32) -> Result<bool> {
+    match cpu
+        .registers
+        .get_register_value(reg, SecurityContext::User)?
+    {
+        RegisterValue::I32(int) => {
+            mem.write_i32(addr, int, SecurityContext::User)
+                .context(MemoryFault)?;
+
+            Ok(false)
+        }
+        _ => Err(CpuError::InvalidRegisterValueType),
+    }
+}
+
+pub fn mov_mem_reg(cpu: &mut CPU, mem: &mut Memory, addr: u32, reg: Registers) -> Result<bool> {
+    let int = mem
+        .read_i32(addr, SecurityContext::User)
+        .context(MemoryFault)?;
+    cpu.registers
+        .set_register_value(reg, RegisterValue::I32(int), SecurityContext::User)?;
+
+    Ok(false)
+}
+
 pub fn add_lit_reg(cpu: &mut CPU, imm: i32, reg: Registers) -> Result<bool> {
     let reg = cpu.registers.get_register_value(reg, SecurityContext::User);
     match reg {
-        Err(_) => {
-            return Err(CpuError::RegisterAccessViolation);
+        Err(_) => Err(CpuError::RegisterAccessViolation),
+        Ok(val) => match val {
+            RegisterValue::I32(int) => {
+                cpu.registers.set_register_value(
+                    Registers::AC,
+                    RegisterValue::I32(imm.wrapping_add(int)),
+                    SecurityContext::User,
+                )?;
+
+                Ok(false)
+            }
+            _ => Err(CpuError::InvalidRegisterValueType),
+        },
+    }
+}
+
+pub fn out_reg_port(cpu: &mut CPU, reg: Registers, port: u8) -> Result<bool> {
+    match cpu
+        .registers
+        .get_register_value(reg, SecurityContext::User)?
+    {
+        RegisterValue::I32(int) => {
+            cpu.io.write(port, int)?;
+
+            Ok(false)
+        }
+        _ => Err(CpuError::InvalidRegisterValueType),
+    }
+}
+
+pub fn in_port_reg(cpu: &mut CPU, address: u32, port: u8, reg: Registers) -> Result<bool> {
+    let instruction = cpu.instructions_executed();
+    let int = cpu.io.read(port, instruction, address)?;
+    cpu.registers
+        .set_register_value(reg, RegisterValue::I32(int), SecurityContext::User)?;
+
+    Ok(false)
+}
+
+pub fn psh_lit(cpu: &mut CPU, mem: &mut Memory, imm: i32) -> Result<bool> {
+    cpu.push_stack(mem, imm)?;
+
+    Ok(false)
+}
+
+pub fn psh_reg(cpu: &mut CPU, mem: &mut Memory, reg: Registers) -> Result<bool> {
+    match cpu
+        .registers
+        .get_register_value(reg, SecurityContext::User)?
+    {
+        RegisterValue::I32(int) => {
+            cpu.push_stack(mem, int)?;
+
+            Ok(false)
         }
-        Ok(val) => {
-            match val {
-                RegisterValue::I32(int) => {
-                    let res = cpu.registers.set_register_value(
-                        Registers::AC,
-                        RegisterValue::I32(imm + int),
-                        SecurityContext::User,
-                    );
-
-                    if let Err(e) = res {
-                        return Err(e);
-                    } else {
-                        return Ok(false);
-                    }
-                }
-                _ => return Err(CpuError::InvalidRegisterValueType),
-            };
+        _ => Err(CpuError::InvalidRegisterValueType),
+    }
+}
+
+pub fn pop_reg(cpu: &mut CPU, mem: &mut Memory, reg: Registers) -> Result<bool> {
+    let int = cpu.pop_stack(mem)?;
+    cpu.registers
+        .set_register_value(reg, RegisterValue::I32(int), SecurityContext::User)?;
+
+    Ok(false)
+}
+
+pub fn cal_lit(cpu: &mut CPU, mem: &mut Memory, addr: u32) -> Result<bool> {
+    // The instruction pointer has already been advanced to the return address.
+    let ret = cpu.get_instruction_pointer()?;
+    cpu.push_stack(mem, ret as i32)?;
+    cpu.set_instruction_pointer(addr)?;
+
+    Ok(false)
+}
+
+pub fn cal_reg(cpu: &mut CPU, mem: &mut Memory, reg: Registers) -> Result<bool> {
+    match cpu
+        .registers
+        .get_register_value(reg, SecurityContext::User)?
+    {
+        RegisterValue::I32(int) => cal_lit(cpu, mem, int as u32),
+        _ => Err(CpuError::InvalidRegisterValueType),
+    }
+}
+
+pub fn ret(cpu: &mut CPU, mem: &mut Memory) -> Result<bool> {
+    let addr = cpu.pop_stack(mem)?;
+    cpu.set_instruction_pointer(addr as u32)?;
+
+    Ok(false)
+}
+
+pub fn iret(cpu: &mut CPU, mem: &mut Memory) -> Result<bool> {
+    ret(cpu, mem)?;
+    cpu.set_flags(cpu.get_flags()? - CpuFlags::FAULT)?;
+
+    Ok(false)
+}
+
+/// Jump to the address if the comparison of the accumulator with the literal
+/// matches the expected equality.
+pub fn jmp_lit(cpu: &mut CPU, imm: i32, addr: u32, equal: bool) -> Result<bool> {
+    match cpu
+        .registers
+        .get_register_value(Registers::AC, SecurityContext::User)?
+    {
+        RegisterValue::I32(ac) => {
+            if (ac == imm) == equal {
+                cpu.set_instruction_pointer(addr)?;
+            }
+
+            Ok(false)
         }
+        _ => Err(CpuError::InvalidRegisterValueType),
+    }
+}
+
+/// Jump to the address if the comparison of the accumulator with the register
+/// matches the expected equality.
+pub fn jmp_reg(cpu: &mut CPU, reg: Registers, addr: u32, equal: bool) -> Result<bool> {
+    match cpu
+        .registers
+        .get_register_value(reg, SecurityContext::User)?
+    {
+        RegisterValue::I32(int) => jmp_lit(cpu, int, addr, equal),
+        _ => Err(CpuError::InvalidRegisterValueType),
     }
 }
@@ -0,0 +1,862 @@
+//! A just-in-time compiler that translates hot blocks of instructions into native
+//! x86-64 code.
+//!
+//! A block is a straight-line sequence of instructions, ending with the first jump or
+//! before the first instruction that the compiler does not support, which is left to
+//! the interpreter. Compiled code operates upon a copy of the registers that is written
+//! back once the block exits, and performs every memory access by calling back into
+//! [`Memory`] so that the access flags of the memory regions are honoured.
+//!
+//! If a memory access fails, the block exits before the faulting instruction so that
+//! the interpreter may execute it again and raise the fault.
+//!
+//! The code of every block is allocated from a single arena of executable memory,
+//! which is discarded along with the blocks.
+
+use crate::cpu::RegisterCollection;
+use crate::decode_cache::DecodeCache;
+use crate::execution::CostTable;
+use crate::instructions::enums::Instruction;
+use crate::memory::Memory;
+use crate::registers::{RegisterAccess, RegisterValue, Registers};
+use crate::security_context::SecurityContext;
+use std::collections::HashMap;
+use std::ptr;
+
+/// The number of times a block must be reached before it is compiled.
+const DEFAULT_THRESHOLD: u32 = 16;
+
+/// The maximum number of instructions within a single block.
+const MAX_BLOCK_INSTRUCTIONS: usize = 64;
+
+/// The minimum size of each chunk of executable memory mapped by the code arena.
+const ARENA_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The alignment of the code of each block within the code arena.
+const BLOCK_ALIGNMENT: usize = 16;
+
+/// The state shared between compiled code and the runtime.
+#[repr(C)]
+struct Context {
+    /// The value of each register, at the index given by its register ID.
+    registers: [i32; Registers::COUNT],
+    memory: *mut Memory,
+}
+
+/// The entry point of a compiled block, returning the number of instructions executed.
+type BlockFn = unsafe extern "C" fn(*mut Context) -> u32;
+
+/// A description of the work done by a compiled block.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockExit {
+    /// The number of cycles consumed.
+    pub cycles: u64,
+    /// The number of instructions executed.
+    pub instructions: u64,
+}
+
+pub struct Jit {
+    enabled: bool,
+    threshold: u32,
+    /// The generation of the decode cache from which the blocks were compiled.
+    generation: u64,
+    /// The number of times each address has been reached, until it is compiled.
+    counts: HashMap<u32, u32>,
+    /// The block compiled at each address, or `None` if no block could be compiled.
+    blocks: HashMap<u32, Option<Block>>,
+    /// The memory holding the code of every compiled block.
+    arena: CodeArena,
+    /// The identifier of the cost table from which the costs of the blocks were taken.
+    cost_table: Option<u64>,
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            threshold: DEFAULT_THRESHOLD,
+            generation: 0,
+            counts: HashMap::new(),
+            blocks: HashMap::new(),
+            arena: CodeArena::default(),
+            cost_table: None,
+        }
+    }
+
+    /// Returns true if hot blocks are compiled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enable or disable compilation, discarding any compiled blocks.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - true if hot blocks should be compiled.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.clear();
+    }
+
+    /// Sets the number of times a block must be reached before it is compiled.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - the number of times, which is treated as one if zero.
+    pub fn set_threshold(&mut self, threshold: u32) {
+        self.threshold = threshold.max(1);
+    }
+
+    /// Discard every compiled block.
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.blocks.clear();
+        self.arena.clear();
+    }
+
+    /// Returns the number of blocks that have been compiled.
+    pub fn compiled_blocks(&self) -> usize {
+        self.blocks.values().filter(|b| b.is_some()).count()
+    }
+
+    /// Execute the compiled block at the instruction pointer, compiling it first if it
+    /// has become hot. Returns `None` if the next instruction must be interpreted.
+    ///
+    /// Blocks are compiled from the decode cache and are discarded whenever it is,
+    /// so nothing is compiled while the decode cache is disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - the decode cache of the CPU.
+    /// * `registers` - the registers of the CPU.
+    /// * `costs` - the cost table of the CPU.
+    /// * `mem` - the memory from which the program is being executed.
+    /// * `seq_id` - the sequence ID of the executable region.
+    /// * `remaining` - the number of cycles that may be consumed, if limited.
+    pub(crate) fn run_block(
+        &mut self,
+        cache: &mut DecodeCache,
+        registers: &mut RegisterCollection,
+        costs: &CostTable,
+        mem: &mut Memory,
+        seq_id: u32,
+        remaining: Option<u64>,
+    ) -> Option<BlockExit> {
+        if !self.enabled || !cache.is_enabled() {
+            return None;
+        }
+
+        let region = cache.sync(mem, seq_id).ok()?;
+        if cache.generation() != self.generation {
+            self.clear();
+            self.generation = cache.generation();
+        }
+
+        let ip = match registers.get_register_value(Registers::IP, SecurityContext::System) {
+            Ok(RegisterValue::I32(ip)) => ip as u32,
+            _ => return None,
+        };
+        if ip < region.start || ip > region.end {
+            return None;
+        }
+
+        if self.cost_table != Some(costs.id()) {
+            for block in self.blocks.values_mut().flatten() {
+                block.set_costs(costs);
+            }
+            self.cost_table = Some(costs.id());
+        }
+
+        if !self.blocks.contains_key(&ip) {
+            let count = self.counts.entry(ip).or_insert(0);
+            *count += 1;
+            if *count < self.threshold {
+                return None;
+            }
+
+            self.counts.remove(&ip);
+            let block = Block::compile(&mut self.arena, cache, costs, mem, seq_id, region.end, ip);
+            self.blocks.insert(ip, block);
+        }
+
+        self.blocks
+            .get(&ip)?
+            .as_ref()?
+            .run(registers, mem, remaining)
+    }
+}
+
+/// A compiled block of instructions.
+struct Block {
+    instructions: Vec<Instruction>,
+    /// The registers whose values are read by the block.
+    reads: Vec<Registers>,
+    /// The register written by each instruction, if any.
+    writes: Vec<Option<Registers>>,
+    /// The number of cycles consumed by each instruction.
+    costs: Vec<u64>,
+    /// The number of cycles consumed by the whole block.
+    cycles: u64,
+    entry: BlockFn,
+}
+
+impl Block {
+    /// Compile the block of instructions starting at an address, returning `None`
+    /// if the first instruction is not supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `arena` - the memory into which the code is placed.
+    /// * `cache` - the decode cache from which the instructions are fetched.
+    /// * `costs` - the cost table of the CPU.
+    /// * `mem` - the memory holding the executable region.
+    /// * `seq_id` - the sequence ID of the executable region.
+    /// * `end` - the address of the last byte of the executable region.
+    /// * `start` - the address of the first instruction.
+    fn compile(
+        arena: &mut CodeArena,
+        cache: &mut DecodeCache,
+        costs: &CostTable,
+        mem: &mut Memory,
+        seq_id: u32,
+        end: u32,
+        start: u32,
+    ) -> Option<Self> {
+        let mut instructions = Vec::new();
+        let mut address = start;
+        while address <= end && instructions.len() < MAX_BLOCK_INSTRUCTIONS {
+            let ins = match cache.fetch(mem, seq_id, address) {
+                Ok(ins) if is_supported(&ins) => ins,
+                _ => break,
+            };
+
+            let is_jump = is_jump(&ins);
+            address = address.checked_add(ins.size())?;
+            instructions.push(ins);
+            if is_jump {
+                break;
+            }
+        }
+
+        if instructions.is_empty() {
+            return None;
+        }
+
+        let (reads, writes): (Vec<_>, Vec<_>) = instructions.iter().map(register_use).unzip();
+        let mut reads: Vec<Registers> = reads.into_iter().flatten().collect();
+        reads.sort_by_key(|r| *r as u8);
+        reads.dedup();
+
+        let entry = arena.insert(&generate(start, &instructions))?;
+
+        let mut block = Self {
+            instructions,
+            reads,
+            writes,
+            costs: Vec::new(),
+            cycles: 0,
+            entry,
+        };
+        block.set_costs(costs);
+
+        Some(block)
+    }
+
+    /// Take the cost of each instruction of the block from a cost table.
+    ///
+    /// # Arguments
+    ///
+    /// * `costs` - the cost table of the CPU.
+    fn set_costs(&mut self, costs: &CostTable) {
+        self.costs = self
+            .instructions
+            .iter()
+            .map(|i| costs.get_cost(i.opcode()))
+            .collect();
+        self.cycles = self.costs.iter().sum();
+    }
+
+    /// Execute the block, returning `None` if it must instead be interpreted.
+    ///
+    /// # Arguments
+    ///
+    /// * `registers` - the registers of the CPU.
+    /// * `mem` - the memory from which the program is being executed.
+    /// * `remaining` - the number of cycles that may be consumed, if limited.
+    fn run(
+        &self,
+        registers: &mut RegisterCollection,
+        mem: &mut Memory,
+        remaining: Option<u64>,
+    ) -> Option<BlockExit> {
+        if remaining.is_some_and(|r| self.cycles > r) {
+            return None;
+        }
+
+        // Compiled code can only operate upon integer values, and must not access a
+        // register that the interpreter would refuse to access.
+        let mut context = Context {
+            registers: [0; Registers::COUNT],
+            memory: mem as *mut Memory,
+        };
+        for &reg in &self.reads {
+            match registers.get_register_value(reg, SecurityContext::User) {
+                Ok(RegisterValue::I32(value)) => context.registers[reg as usize] = value,
+                _ => return None,
+            }
+        }
+        for &reg in self.writes.iter().flatten() {
+            let access = registers.get_register_ref(reg).get_access_flags();
+            if !access.contains(RegisterAccess::W) {
+                return None;
+            }
+        }
+
+        let executed = unsafe { (self.entry)(&mut context) } as usize;
+        if executed == 0 {
+            // The first instruction faulted, which the interpreter must report.
+            return None;
+        }
+
+        for &reg in self.writes[..executed].iter().flatten() {
+            let value = RegisterValue::I32(context.registers[reg as usize]);
+            registers
+                .set_register_value(reg, value, SecurityContext::User)
+                .ok()?;
+        }
+        let ip = context.registers[Registers::IP as usize];
+        registers
+            .set_register_value(
+                Registers::IP,
+                RegisterValue::I32(ip),
+                SecurityContext::System,
+            )
+            .ok()?;
+
+        Some(BlockExit {
+            cycles: self.costs[..executed].iter().sum(),
+            instructions: executed as u64,
+        })
+    }
+}
+
+/// Returns true if the instruction can be compiled.
+///
+/// # Arguments
+///
+/// * `ins` - the instruction.
+fn is_supported(ins: &Instruction) -> bool {
+    matches!(
+        ins,
+        Instruction::NOP()
+            | Instruction::MovLitReg(..)
+            | Instruction::MovRegReg(..)
+            | Instruction::MovRegMem(..)
+            | Instruction::MovMemReg(..)
+            | Instruction::AddLitReg(..)
+            | Instruction::JmpNotEq(..)
+            | Instruction::JneReg(..)
+            | Instruction::JeqLit(..)
+            | Instruction::JeqReg(..)
+    )
+}
+
+/// Returns true if the instruction may change the instruction pointer, ending a block.
+///
+/// # Arguments
+///
+/// * `ins` - the instruction.
+fn is_jump(ins: &Instruction) -> bool {
+    matches!(
+        ins,
+        Instruction::JmpNotEq(..)
+            | Instruction::JneReg(..)
+            | Instruction::JeqLit(..)
+            | Instruction::JeqReg(..)
+    )
+}
+
+/// Returns the registers read by a supported instruction, along with the register
+/// it writes, if any. Writes to the instruction pointer made by jumps are excluded.
+///
+/// # Arguments
+///
+/// * `ins` - the instruction.
+fn register_use(ins: &Instruction) -> (Vec<Registers>, Option<Registers>) {
+    match *ins {
+        Instruction::MovLitReg(_, reg) | Instruction::MovMemReg(_, reg) => (vec![], Some(reg)),
+        Instruction::MovRegReg(src, dst) => (vec![src], Some(dst)),
+        Instruction::MovRegMem(reg, _) => (vec![reg], None),
+        Instruction::AddLitReg(_, reg) => (vec![reg], Some(Registers::AC)),
+        Instruction::JmpNotEq(..) | Instruction::JeqLit(..) => (vec![Registers::AC], None),
+        Instruction::JneReg(reg, _) | Instruction::JeqReg(reg, _) => {
+            (vec![reg, Registers::AC], None)
+        }
+        _ => (vec![], None),
+    }
+}
+
+/// Generate the native code for a block of supported instructions.
+///
+/// The context is held in `rbx` throughout, with the registers addressed relative to
+/// it. Upon exit, the instruction pointer within the context holds the address of the
+/// next instruction to be executed.
+///
+/// # Arguments
+///
+/// * `start` - the address of the first instruction.
+/// * `instructions` - the instructions.
+fn generate(start: u32, instructions: &[Instruction]) -> Vec<u8> {
+    let mut asm = Assembler::default();
+    // push rbx; mov rbx, rdi
+    asm.emit(&[0x53, 0x48, 0x89, 0xFB]);
+
+    // The offsets of the branches to be taken if an instruction faults.
+    let mut faults = Vec::new();
+
+    let mut address = start;
+    for (i, ins) in instructions.iter().enumerate() {
+        let next = address + ins.size();
+
+        // The interpreter advances the instruction pointer before execution.
+        if register_use(ins).0.contains(&Registers::IP) {
+            asm.store_literal(Registers::IP, next as i32);
+        }
+
+        match *ins {
+            Instruction::NOP() => {}
+            Instruction::MovLitReg(lit, reg) => asm.store_literal(reg, lit),
+            Instruction::MovRegReg(src, dst) => {
+                asm.load_eax(src);
+                asm.store_eax(dst);
+            }
+            Instruction::AddLitReg(lit, reg) => {
+                asm.load_eax(reg);
+                // add eax, imm32
+                asm.emit(&[0x05]);
+                asm.emit(&lit.to_le_bytes());
+                asm.store_eax(Registers::AC);
+            }
+            Instruction::MovRegMem(reg, addr) => {
+                asm.prepare_call(addr);
+                // mov edx, [rbx + reg]
+                asm.emit(&[0x8B, 0x53, offset(reg)]);
+                faults.push((asm.call(write_i32 as *const () as usize), i, address));
+            }
+            Instruction::MovMemReg(addr, reg) => {
+                asm.prepare_call(addr);
+                // lea rdx, [rbx + reg]
+                asm.emit(&[0x48, 0x8D, 0x53, offset(reg)]);
+                faults.push((asm.call(read_i32 as *const () as usize), i, address));
+            }
+            Instruction::JmpNotEq(lit, target) | Instruction::JeqLit(lit, target) => {
+                asm.load_eax(Registers::AC);
+                // cmp eax, imm32
+                asm.emit(&[0x3D]);
+                asm.emit(&lit.to_le_bytes());
+                asm.branch(matches!(ins, Instruction::JeqLit(..)), target, next);
+            }
+            Instruction::JneReg(reg, target) | Instruction::JeqReg(reg, target) => {
+                asm.load_eax(Registers::AC);
+                // cmp eax, [rbx + reg]
+                asm.emit(&[0x3B, 0x43, offset(reg)]);
+                asm.branch(matches!(ins, Instruction::JeqReg(..)), target, next);
+            }
+            _ => unreachable!("unsupported instruction {}", ins),
+        }
+
+        address = next;
+    }
+
+    if !instructions.last().is_some_and(is_jump) {
+        asm.store_literal(Registers::IP, address as i32);
+    }
+    asm.exit(instructions.len() as u32);
+
+    // A faulting instruction exits with the instruction pointer identifying it.
+    for (patch, executed, address) in faults {
+        let rel = (asm.code.len() - (patch + 4)) as u32;
+        asm.code[patch..patch + 4].copy_from_slice(&rel.to_le_bytes());
+
+        asm.store_literal(Registers::IP, address as i32);
+        asm.exit(executed as u32);
+    }
+
+    asm.code
+}
+
+/// Returns the offset of a register from the start of the context.
+///
+/// # Arguments
+///
+/// * `reg` - the register.
+fn offset(reg: Registers) -> u8 {
+    (reg as usize * 4) as u8
+}
+
+/// A minimal x86-64 assembler for the instruction forms used by compiled blocks.
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// mov eax, [rbx + reg]
+    fn load_eax(&mut self, reg: Registers) {
+        self.emit(&[0x8B, 0x43, offset(reg)]);
+    }
+
+    /// mov [rbx + reg], eax
+    fn store_eax(&mut self, reg: Registers) {
+        self.emit(&[0x89, 0x43, offset(reg)]);
+    }
+
+    /// mov dword [rbx + reg], imm32
+    fn store_literal(&mut self, reg: Registers, value: i32) {
+        self.emit(&[0xC7, 0x43, offset(reg)]);
+        self.emit(&value.to_le_bytes());
+    }
+
+    /// Load the context and a memory address as the first two arguments of a call.
+    fn prepare_call(&mut self, address: u32) {
+        // mov rdi, rbx; mov esi, imm32
+        self.emit(&[0x48, 0x89, 0xDF, 0xBE]);
+        self.emit(&address.to_le_bytes());
+    }
+
+    /// Call a function that returns zero upon success, branching if it fails.
+    /// Returns the offset of the branch displacement, which must be patched.
+    fn call(&mut self, function: usize) -> usize {
+        // mov rax, imm64; call rax; test eax, eax; jnz rel32
+        self.emit(&[0x48, 0xB8]);
+        self.emit(&(function as u64).to_le_bytes());
+        self.emit(&[0xFF, 0xD0, 0x85, 0xC0, 0x0F, 0x85]);
+        self.emit(&[0; 4]);
+
+        self.code.len() - 4
+    }
+
+    /// Exit the block, following the result of the preceding comparison.
+    fn branch(&mut self, equal: bool, target: u32, next: u32) {
+        self.store_literal(Registers::IP, next as i32);
+        // Skip over the store of the target if the jump is not taken.
+        let skip = if equal { 0x75 } else { 0x74 };
+        self.emit(&[skip, 0x07]);
+        self.store_literal(Registers::IP, target as i32);
+    }
+
+    /// Return the number of instructions executed.
+    fn exit(&mut self, executed: u32) {
+        // mov eax, imm32; pop rbx; ret
+        self.emit(&[0xB8]);
+        self.emit(&executed.to_le_bytes());
+        self.emit(&[0x5B, 0xC3]);
+    }
+}
+
+/// Read an integer from memory on behalf of compiled code, returning zero upon success.
+unsafe extern "C" fn read_i32(context: *mut Context, address: u32, value: *mut i32) -> u32 {
+    match (*(*context).memory).read_i32(address, SecurityContext::User) {
+        Ok(v) => {
+            *value = v;
+            0
+        }
+        Err(_) => 1,
+    }
+}
+
+/// Write an integer to memory on behalf of compiled code, returning zero upon success.
+unsafe extern "C" fn write_i32(context: *mut Context, address: u32, value: i32) -> u32 {
+    match (*(*context).memory).write_i32(address, value, SecurityContext::User) {
+        Ok(_) => 0,
+        Err(_) => 1,
+    }
+}
+
+/// Executable memory holding the code of compiled blocks, which is mapped in chunks
+/// and is never writable while it may be executed.
+#[derive(Default)]
+struct CodeArena {
+    chunks: Vec<Chunk>,
+}
+
+impl CodeArena {
+    /// Copy code into the arena, returning its entry point, or `None` if it cannot be mapped.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - the machine code.
+    fn insert(&mut self, code: &[u8]) -> Option<BlockFn> {
+        let fits = self
+            .chunks
+            .last()
+            .is_some_and(|c| c.len - c.used >= code.len());
+        if !fits {
+            self.chunks
+                .push(Chunk::map(code.len().max(ARENA_CHUNK_SIZE))?);
+        }
+
+        self.chunks.last_mut()?.write(code)
+    }
+
+    /// Unmap every chunk. The entry points returned so far must no longer be called.
+    fn clear(&mut self) {
+        self.chunks.clear();
+    }
+}
+
+/// A mapping of executable memory, filled from its start.
+struct Chunk {
+    ptr: *mut libc::c_void,
+    len: usize,
+    used: usize,
+}
+
+// The mapping is owned exclusively, and is only modified through a mutable reference.
+unsafe impl Send for Chunk {}
+
+impl Chunk {
+    /// Map an empty chunk, returning `None` if it cannot be mapped.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - the minimum size of the chunk.
+    fn map(size: usize) -> Option<Self> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+        let len = size.div_ceil(page) * page;
+
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return None;
+        }
+
+        Some(Self { ptr, len, used: 0 })
+    }
+
+    /// Copy code into the unused part of the chunk, which must have room for it,
+    /// returning its entry point.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - the machine code.
+    fn write(&mut self, code: &[u8]) -> Option<BlockFn> {
+        unsafe {
+            if libc::mprotect(self.ptr, self.len, libc::PROT_READ | libc::PROT_WRITE) != 0 {
+                return None;
+            }
+
+            let start = (self.ptr as *mut u8).add(self.used);
+            ptr::copy_nonoverlapping(code.as_ptr(), start, code.len());
+            if libc::mprotect(self.ptr, self.len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return None;
+            }
+
+            self.used = (self.used + code.len())
+                .next_multiple_of(BLOCK_ALIGNMENT)
+                .min(self.len);
+            Some(std::mem::transmute::<*mut u8, BlockFn>(start))
+        }
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CpuFlags;
+    use crate::execution::RunOutcome;
+    use crate::instructions::enums::OpCode;
+    use crate::registers::Register;
+    use crate::virtual_machine::VirtualMachine;
+
+    /// The observable state of a virtual machine after a run.
+    #[derive(Debug, PartialEq)]
+    struct State {
+        outcome: String,
+        registers: Vec<Register>,
+        memory: Vec<u8>,
+        cycles: u64,
+        instructions: u64,
+    }
+
+    /// Run a program to completion in slices of the budget, recording the state
+    /// after each slice.
+    fn run(program: &[Instruction], budget: u64, jit: bool) -> (Vec<State>, usize) {
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.cpu.jit.set_enabled(jit);
+        vm.cpu.jit.set_threshold(1);
+        vm.load_program(&bytes, 0).unwrap();
+
+        let mut states = Vec::new();
+        loop {
+            let outcome = vm.run_for(budget);
+            let finished = !matches!(outcome, RunOutcome::BudgetExhausted);
+            states.push(State {
+                outcome: format!("{:?}", outcome),
+                registers: vm.cpu.registers.registers.to_vec(),
+                memory: vm
+                    .memory
+                    .read_bytes(0, vm.memory.len() as u32, SecurityContext::System)
+                    .unwrap()
+                    .to_vec(),
+                cycles: vm.cpu.cycles(),
+                instructions: vm.cpu.instructions_executed(),
+            });
+            if finished {
+                return (states, vm.cpu.jit.compiled_blocks());
+            }
+        }
+    }
+
+    /// Assert that a program behaves identically whether or not it is compiled.
+    fn assert_equivalent(program: &[Instruction]) -> State {
+        for &budget in &[u64::MAX, 7, 100] {
+            let (interpreted, _) = run(program, budget, false);
+            let (compiled, blocks) = run(program, budget, true);
+            assert!(blocks > 0);
+            assert_eq!(interpreted, compiled, "budget {}", budget);
+        }
+
+        run(program, u64::MAX, true).0.pop().unwrap()
+    }
+
+    fn value(state: &State, reg: Registers) -> RegisterValue {
+        state.registers[reg as usize]
+            .get_value(SecurityContext::System)
+            .unwrap()
+    }
+
+    #[test]
+    fn loops_match_the_interpreter() {
+        let state = assert_equivalent(&[
+            Instruction::MovLitReg(0, Registers::R1),
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::MovRegReg(Registers::AC, Registers::R1),
+            Instruction::MovRegMem(Registers::R1, 600),
+            Instruction::MovMemReg(600, Registers::R2),
+            Instruction::MovRegReg(Registers::IP, Registers::R3),
+            Instruction::JmpNotEq(200, 7),
+            Instruction::HLT(),
+        ]);
+        assert_eq!(state.outcome, "Halted");
+        assert_eq!(value(&state, Registers::R2), RegisterValue::I32(200));
+        assert_eq!(value(&state, Registers::R3), RegisterValue::I32(36));
+    }
+
+    #[test]
+    fn branches_match_the_interpreter() {
+        let state = assert_equivalent(&[
+            Instruction::MovLitReg(50, Registers::R4),
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::MovRegReg(Registers::AC, Registers::R1),
+            Instruction::JeqLit(25, 44),
+            Instruction::JneReg(Registers::R4, 7),
+            Instruction::JeqReg(Registers::R4, 58),
+            Instruction::NOP(),
+            Instruction::MovLitReg(7, Registers::R5),
+            Instruction::JneReg(Registers::R4, 7),
+            Instruction::HLT(),
+        ]);
+        assert_eq!(state.outcome, "Halted");
+        assert_eq!(value(&state, Registers::R1), RegisterValue::I32(50));
+        assert_eq!(value(&state, Registers::R5), RegisterValue::I32(7));
+    }
+
+    #[test]
+    fn faults_match_the_interpreter() {
+        // A user may not write to the stack, or to the stack pointer.
+        let state = assert_equivalent(&[
+            Instruction::MovLitReg(5, Registers::R1),
+            Instruction::MovRegMem(Registers::R1, 500),
+            Instruction::MovRegMem(Registers::R1, 1_000),
+            Instruction::HLT(),
+        ]);
+        assert!(state.outcome.starts_with("Fault(MemoryFault"));
+        assert_eq!(value(&state, Registers::IP), RegisterValue::I32(14));
+
+        let state = assert_equivalent(&[
+            Instruction::MovLitReg(5, Registers::R1),
+            Instruction::MovLitReg(0, Registers::SP),
+            Instruction::HLT(),
+        ]);
+        assert_eq!(state.outcome, "Fault(RegisterAccessViolation)");
+
+        // A fault raised within a block is delivered to the fault handler.
+        let state = assert_equivalent(&[
+            Instruction::MovLitReg(23, Registers::FH),
+            Instruction::MovLitReg(5, Registers::R1),
+            Instruction::MovRegMem(Registers::R1, 1_000),
+            Instruction::HLT(),
+            Instruction::MovLitReg(9, Registers::R6),
+            Instruction::HLT(),
+        ]);
+        assert_eq!(state.outcome, "Halted");
+        assert_eq!(value(&state, Registers::R6), RegisterValue::I32(9));
+        assert_eq!(
+            value(&state, Registers::FL),
+            RegisterValue::I32(CpuFlags::FAULT.bits() as i32)
+        );
+    }
+
+    #[test]
+    fn blocks_share_the_arena_and_follow_the_costs() {
+        let bytes: Vec<u8> = [
+            Instruction::MovLitReg(0, Registers::R1),
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::MovRegReg(Registers::AC, Registers::R1),
+            Instruction::JmpNotEq(100, 7),
+            Instruction::NOP(),
+            Instruction::JmpNotEq(0, 40),
+            Instruction::HLT(),
+        ]
+        .iter()
+        .flat_map(|i| i.encode())
+        .collect();
+
+        let run = |jit: bool| {
+            let mut vm = VirtualMachine::new(1_000, 10, false);
+            vm.cpu.jit.set_enabled(jit);
+            vm.cpu.jit.set_threshold(1);
+            vm.load_program(&bytes, 0).unwrap();
+
+            assert!(matches!(vm.run_for(50), RunOutcome::BudgetExhausted));
+            vm.cpu.cost_table.set_cost(OpCode::AddLitReg, 5);
+            assert!(matches!(vm.run_for(u64::MAX), RunOutcome::Halted));
+            vm
+        };
+
+        let interpreted = run(false);
+        let compiled = run(true);
+        assert_eq!(compiled.cpu.cycles(), interpreted.cpu.cycles());
+        assert_eq!(compiled.cpu.jit.compiled_blocks(), 3);
+        assert_eq!(compiled.cpu.jit.arena.chunks.len(), 1);
+    }
+}
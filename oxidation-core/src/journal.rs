@@ -0,0 +1,127 @@
+use crate::cpu::*;
+use crate::execution::StepOutcome;
+use crate::memory::{Memory, MemoryWrite};
+use crate::registers::RegisterWrite;
+use snafu::ResultExt;
+use std::collections::VecDeque;
+
+type Result<T, E = CpuError> = std::result::Result<T, E>;
+
+/// The reason for which a reverse execution of the CPU stopped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReverseOutcome {
+    /// The stop predicate was satisfied after an instruction was undone.
+    Breakpoint,
+    /// There are no further journal entries that can be undone.
+    JournalExhausted,
+}
+
+/// The state required to undo the execution of a single instruction.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    /// The value of the instruction pointer before the instruction was executed.
+    pub address: u32,
+    /// The registers written by the instruction, holding their previous values.
+    pub register_writes: Vec<RegisterWrite>,
+    /// The memory written by the instruction, holding its previous contents.
+    pub memory_writes: Vec<MemoryWrite>,
+    /// The total number of cycles consumed before the instruction was executed.
+    pub cycles: u64,
+    /// The total number of instructions executed before the instruction was executed.
+    pub instructions_executed: u64,
+}
+
+/// A bounded journal of the state changes made by each executed instruction.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    entries: VecDeque<JournalEntry>,
+    limit: usize,
+}
+
+impl Journal {
+    /// Create a new journal.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - the maximum number of entries to retain. The oldest entries are discarded first.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(limit.min(1024)),
+            limit,
+        }
+    }
+
+    /// Returns the number of entries held within the journal.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the journal holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the maximum number of entries retained by the journal.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Record the changes made by a step.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - the CPU, in the state following the step.
+    /// * `outcome` - the outcome of the step.
+    pub fn record(&mut self, cpu: &CPU, outcome: &StepOutcome) {
+        // Steps taken while the CPU was already halted change nothing.
+        if outcome.instruction.is_none()
+            && outcome.fault.is_none()
+            && outcome.handled_fault.is_none()
+        {
+            return;
+        }
+
+        if self.limit == 0 {
+            return;
+        }
+        if self.entries.len() == self.limit {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(JournalEntry {
+            address: outcome.address,
+            register_writes: outcome.register_writes.clone(),
+            memory_writes: outcome.memory_writes.clone(),
+            cycles: cpu.cycles() - outcome.cycles,
+            instructions_executed: cpu.instructions_executed() - outcome.executed() as u64,
+        });
+    }
+
+    /// Undo the most recently recorded step, returning true if a step was undone.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - the CPU.
+    /// * `mem` - the memory.
+    pub fn step_back(&mut self, cpu: &mut CPU, mem: &mut Memory) -> Result<bool> {
+        let entry = match self.entries.pop_back() {
+            Some(e) => e,
+            None => return Ok(false),
+        };
+
+        // Changes are undone in the reverse of the order in which they were made.
+        for write in entry.memory_writes.iter().rev() {
+            mem.restore_bytes(write.address, &write.old_bytes)
+                .context(MemoryFault)?;
+        }
+        for write in entry.register_writes.iter().rev() {
+            cpu.registers
+                .restore_register_value(write.register_id, write.old_value);
+        }
+
+        cpu.set_instruction_pointer(entry.address)?;
+        cpu.restore_state(false, entry.cycles, entry.instructions_executed);
+
+        Ok(true)
+    }
+}
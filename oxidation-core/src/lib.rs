@@ -3,12 +3,31 @@
 #[macro_use]
 extern crate bitflags;
 
+pub mod coverage;
 pub mod cpu;
-pub mod memory;
+pub mod debug_info;
+pub mod decode_cache;
+pub mod devices;
+pub mod executable;
+pub mod execution;
+pub mod fault;
+pub mod gdb;
 pub mod instructions;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+pub mod journal;
+pub mod memory;
+pub mod profiler;
 pub mod registers;
+pub mod replay;
 pub mod security_context;
+pub mod snapshot;
+pub mod source_map;
+pub mod symbols;
+pub mod tracer;
+pub mod verifier;
 pub mod virtual_machine;
+pub mod watchpoints;
 
 #[cfg(test)]
 mod tests {
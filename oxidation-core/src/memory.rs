@@ -1,3 +1,31 @@
+use crate::security_context::SecurityContext;
+use crate::snapshot::{self, SnapshotReader, SnapshotWriter};
+use snafu::{ensure, OptionExt, Snafu};
+use std::cell::RefCell;
+
+type Result<T, E = MemoryError> = std::result::Result<T, E>;
+
+#[derive(Debug, Snafu)]
+pub enum MemoryError {
+    #[snafu(display(
+        "an attempt was made to access an invalid memory address {:#010X}",
+        address
+    ))]
+    InvalidMemoryAddress { address: u32 },
+    #[snafu(display(
+        "an attempt was made to access memory address {:#010X} in region '{}' with invalid permissions",
+        address,
+        region
+    ))]
+    MemoryAccessViolation {
+        address: u32,
+        region: String,
+        security_context: SecurityContext,
+    },
+    #[snafu(display("no memory region with the sequence ID {} exists", seq_id))]
+    InvalidMemorySequenceId { seq_id: u32 },
+}
+
 bitflags! {
     #[derive(Default)]
     pub struct MemoryAccess: u8 {
@@ -5,25 +33,46 @@ bitflags! {
         const R = 1 << 1;
         const W = 1 << 2;
         const PR = 1 << 3;
-        const PW = 1 << 3;
+        const PW = 1 << 4;
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum AccessType {
     Read,
     Write,
 }
 
+/// A record of a single write to memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryWrite {
+    pub address: u32,
+    pub old_bytes: Vec<u8>,
+    pub new_bytes: Vec<u8>,
+}
+
+/// A record of a single read from memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryRead {
+    pub address: u32,
+    pub bytes: Vec<u8>,
+}
+
 pub struct Memory {
     base_size: u32,
     stack_start: u32,
     stack_end: u32,
     stack_pointer: u32,
-    data: Vec<i8>,
+    data: Vec<u8>,
     memory_regions: Vec<MemoryRegion>,
     memory_seq_id: u32,
+    write_log: Option<Vec<MemoryWrite>>,
+    read_log: RefCell<Option<Vec<MemoryRead>>>,
+    code_range: Option<(u32, u32)>,
+    code_writes: Vec<(u32, u32)>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct MemoryRegion {
     pub start: u32,
     pub end: u32,
@@ -51,9 +100,13 @@ impl Memory {
             stack_start,
             stack_end,
             stack_pointer: stack_end,
-            data: Vec::with_capacity(memory_capacity as usize),
+            data: vec![0; memory_capacity as usize],
             memory_regions: Vec::with_capacity(100),
             memory_seq_id: 0,
+            write_log: None,
+            read_log: RefCell::new(None),
+            code_range: None,
+            code_writes: Vec::new(),
         };
 
         // The stack memory region should be marked
@@ -68,7 +121,7 @@ impl Memory {
         );
         mem.add_memory_region(
             stack_start,
-            stack_end,
+            stack_end - 1,
             MemoryAccess::R | MemoryAccess::PW,
             "Stack".to_string(),
         );
@@ -77,15 +130,362 @@ impl Memory {
     }
 
     pub fn len(&self) -> usize {
-        self.data.capacity()
+        self.base_size as usize
     }
 
-    fn add_memory_region(&mut self, start: u32, end: u32, access: MemoryAccess, name: String) {
-        let region = MemoryRegion::new(start, end, access, self.memory_seq_id, name);
+    pub fn is_empty(&self) -> bool {
+        self.base_size == 0
+    }
+
+    /// Returns the address of the first byte of the stack memory region.
+    pub fn stack_start(&self) -> u32 {
+        self.stack_start
+    }
+
+    /// Returns the address one past the final byte of the stack memory region.
+    pub fn stack_end(&self) -> u32 {
+        self.stack_end
+    }
+
+    /// Returns the current value of the stack pointer.
+    pub fn stack_pointer(&self) -> u32 {
+        self.stack_pointer
+    }
+
+    /// Add a new memory region, returning the sequence ID assigned to it.
+    ///
+    /// Regions that are added later take precedence over any earlier
+    /// regions that they overlap.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte of the region.
+    /// * `end` - the address of the final byte of the region.
+    /// * `access` - the access flags of the region.
+    /// * `name` - the name of the region.
+    pub fn add_memory_region(
+        &mut self,
+        start: u32,
+        end: u32,
+        access: MemoryAccess,
+        name: String,
+    ) -> u32 {
+        let seq_id = self.memory_seq_id;
+        let region = MemoryRegion::new(start, end, access, seq_id, name);
         self.memory_regions.push(region);
 
         // Ensure that the sequence ID is never reused.
         self.memory_seq_id += 1;
+
+        seq_id
+    }
+
+    /// Returns a reference to the memory region with the specified sequence ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `seq_id` - the sequence ID of the region.
+    pub fn get_region_by_seq_id(&self, seq_id: u32) -> Result<&MemoryRegion> {
+        self.memory_regions
+            .iter()
+            .find(|r| r.seq_id == seq_id)
+            .context(InvalidMemorySequenceId { seq_id })
+    }
+
+    /// Returns a reference to the memory region that governs access to the specified address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the memory address.
+    pub fn get_region_for_address(&self, address: u32) -> Option<&MemoryRegion> {
+        self.memory_regions
+            .iter()
+            .rev()
+            .find(|r| r.contains(address))
+    }
+
+    /// Returns the memory regions, in the order in which they were added.
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.memory_regions
+    }
+
+    /// Returns a slice of the memory, validating that every byte may be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte to be read.
+    /// * `len` - the number of bytes to be read.
+    /// * `security_context` - the security context to be used when fulfilling this request.
+    pub fn read_bytes(
+        &self,
+        start: u32,
+        len: u32,
+        security_context: SecurityContext,
+    ) -> Result<&[u8]> {
+        self.validate_range(start, len, AccessType::Read, security_context)?;
+
+        let bytes = &self.data[start as usize..start as usize + len as usize];
+        if let Some(log) = self.read_log.borrow_mut().as_mut() {
+            log.push(MemoryRead {
+                address: start,
+                bytes: bytes.to_vec(),
+            });
+        }
+
+        Ok(bytes)
+    }
+
+    /// Writes a sequence of bytes into memory, validating that every byte may be written.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte to be written.
+    /// * `bytes` - the bytes to be written.
+    /// * `security_context` - the security context to be used when fulfilling this request.
+    pub fn write_bytes(
+        &mut self,
+        start: u32,
+        bytes: &[u8],
+        security_context: SecurityContext,
+    ) -> Result<()> {
+        self.validate_range(
+            start,
+            bytes.len() as u32,
+            AccessType::Write,
+            security_context,
+        )?;
+
+        let range = start as usize..start as usize + bytes.len();
+        if let Some(log) = &mut self.write_log {
+            log.push(MemoryWrite {
+                address: start,
+                old_bytes: self.data[range.clone()].to_vec(),
+                new_bytes: bytes.to_vec(),
+            });
+        }
+
+        self.data[range].copy_from_slice(bytes);
+        self.record_code_write(start, bytes.len());
+
+        Ok(())
+    }
+
+    /// Writes a sequence of bytes into memory without validating access or recording the write.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte to be written.
+    /// * `bytes` - the bytes to be written.
+    pub(crate) fn restore_bytes(&mut self, start: u32, bytes: &[u8]) -> Result<()> {
+        let end = start as u64 + bytes.len() as u64;
+        ensure!(
+            end <= self.data.len() as u64,
+            InvalidMemoryAddress {
+                address: start.max(self.data.len() as u32)
+            }
+        );
+
+        self.data[start as usize..start as usize + bytes.len()].copy_from_slice(bytes);
+        self.record_code_write(start, bytes.len());
+
+        Ok(())
+    }
+
+    /// Begin recording the writes made to a range of memory holding code, replacing
+    /// any range previously watched and discarding any previous records.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte of code.
+    /// * `end` - the address of the last byte of code.
+    pub(crate) fn watch_code(&mut self, start: u32, end: u32) {
+        self.code_range = Some((start, end));
+        self.code_writes.clear();
+    }
+
+    /// Returns the inclusive ranges of addresses written within the watched code
+    /// since they were last taken.
+    pub(crate) fn take_code_writes(&mut self) -> Vec<(u32, u32)> {
+        std::mem::take(&mut self.code_writes)
+    }
+
+    /// Record a write if it overlaps the watched code.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte written.
+    /// * `len` - the number of bytes written.
+    fn record_code_write(&mut self, start: u32, len: usize) {
+        if let Some((code_start, code_end)) = self.code_range {
+            let end = start.saturating_add((len as u32).max(1) - 1);
+            if len > 0 && start <= code_end && end >= code_start {
+                self.code_writes.push((start, end));
+            }
+        }
+    }
+
+    /// Begin recording every write made to memory, discarding any previous records.
+    pub fn start_write_log(&mut self) {
+        self.write_log = Some(Vec::new());
+    }
+
+    /// Stop recording writes made to memory, returning those that were recorded.
+    pub fn take_write_log(&mut self) -> Vec<MemoryWrite> {
+        self.write_log.take().unwrap_or_default()
+    }
+
+    /// Begin recording every read made from memory, discarding any previous records.
+    pub fn start_read_log(&mut self) {
+        self.read_log = RefCell::new(Some(Vec::new()));
+    }
+
+    /// Stop recording reads made from memory, returning those that were recorded.
+    pub fn take_read_log(&mut self) -> Vec<MemoryRead> {
+        self.read_log.get_mut().take().unwrap_or_default()
+    }
+
+    /// Reads a little-endian 32-bit integer from memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the first byte of the integer.
+    /// * `security_context` - the security context to be used when fulfilling this request.
+    pub fn read_i32(&self, address: u32, security_context: SecurityContext) -> Result<i32> {
+        let bytes = self.read_bytes(address, 4, security_context)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Writes a little-endian 32-bit integer into memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the first byte of the integer.
+    /// * `value` - the value to be written.
+    /// * `security_context` - the security context to be used when fulfilling this request.
+    pub fn write_i32(
+        &mut self,
+        address: u32,
+        value: i32,
+        security_context: SecurityContext,
+    ) -> Result<()> {
+        self.write_bytes(address, &value.to_le_bytes(), security_context)
+    }
+
+    /// Write the complete state of the memory into a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the snapshot writer.
+    pub(crate) fn write_snapshot(&self, writer: &mut SnapshotWriter) -> snapshot::Result<()> {
+        writer.write_u32(self.base_size)?;
+        writer.write_u32(self.stack_start)?;
+        writer.write_u32(self.stack_end)?;
+        writer.write_u32(self.stack_pointer)?;
+        writer.write_u32(self.memory_seq_id)?;
+
+        writer.write_u32(self.memory_regions.len() as u32)?;
+        for region in &self.memory_regions {
+            writer.write_u32(region.start)?;
+            writer.write_u32(region.end)?;
+            writer.write_u8(region.access.bits())?;
+            writer.write_u32(region.seq_id)?;
+            writer.write_string(&region.name)?;
+        }
+
+        writer.write_bytes(&self.data)
+    }
+
+    /// Create a memory instance from the state held within a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - the snapshot reader.
+    pub(crate) fn read_snapshot(reader: &mut SnapshotReader) -> snapshot::Result<Self> {
+        let base_size = reader.read_u32()?;
+        let stack_start = reader.read_u32()?;
+        let stack_end = reader.read_u32()?;
+        let stack_pointer = reader.read_u32()?;
+        let memory_seq_id = reader.read_u32()?;
+
+        // The end of the stack is exclusive, and lies at the end of the memory.
+        if stack_start > stack_end || stack_end > base_size {
+            return snapshot::invalid("the stack does not lie within memory".to_string());
+        }
+        if stack_pointer < stack_start || stack_pointer > stack_end {
+            return snapshot::invalid(
+                "the stack pointer does not lie within the stack".to_string(),
+            );
+        }
+
+        let region_count = reader.read_u32()?;
+        let mut memory_regions = Vec::new();
+        for _ in 0..region_count {
+            let start = reader.read_u32()?;
+            let end = reader.read_u32()?;
+            let access = match MemoryAccess::from_bits(reader.read_u8()?) {
+                Some(a) => a,
+                None => return snapshot::invalid("invalid memory access flags".to_string()),
+            };
+            let seq_id = reader.read_u32()?;
+            let name = reader.read_string()?;
+            if start > end || end >= base_size {
+                return snapshot::invalid(format!(
+                    "the region '{}' does not lie within memory",
+                    name
+                ));
+            }
+
+            memory_regions.push(MemoryRegion::new(start, end, access, seq_id, name));
+        }
+
+        let data = reader.read_bytes(base_size as usize)?;
+
+        Ok(Self {
+            base_size,
+            stack_start,
+            stack_end,
+            stack_pointer,
+            data,
+            memory_regions,
+            memory_seq_id,
+            write_log: None,
+            read_log: RefCell::new(None),
+            code_range: None,
+            code_writes: Vec::new(),
+        })
+    }
+
+    fn validate_range(
+        &self,
+        start: u32,
+        len: u32,
+        access_type: AccessType,
+        security_context: SecurityContext,
+    ) -> Result<()> {
+        let end = start as u64 + len as u64;
+        ensure!(
+            end <= self.data.len() as u64,
+            InvalidMemoryAddress {
+                address: start.max(self.data.len() as u32)
+            }
+        );
+
+        for address in start..(end as u32) {
+            let region = self
+                .get_region_for_address(address)
+                .context(InvalidMemoryAddress { address })?;
+
+            ensure!(
+                region.validate_access(security_context, access_type),
+                MemoryAccessViolation {
+                    address,
+                    region: region.name.clone(),
+                    security_context
+                }
+            );
+        }
+
+        Ok(())
     }
 }
 
@@ -99,4 +499,27 @@ impl MemoryRegion {
             name,
         }
     }
+
+    /// Returns true if the specified address lies within this region.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the memory address.
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.start && address <= self.end
+    }
+
+    fn validate_access(&self, security_context: SecurityContext, access_type: AccessType) -> bool {
+        // The system context may also make use of the private flags,
+        // the user context may only make use of the public ones.
+        let (public, private) = match access_type {
+            AccessType::Read => (MemoryAccess::R, MemoryAccess::PR),
+            AccessType::Write => (MemoryAccess::W, MemoryAccess::PW),
+        };
+
+        match security_context {
+            SecurityContext::User => self.access.contains(public),
+            SecurityContext::System => self.access.intersects(public | private),
+        }
+    }
 }
@@ -0,0 +1,443 @@
+//! Execution profiling of guest programs.
+//!
+//! The profiler counts the executions and cycles of every instruction address and,
+//! by following the call and return instructions, of every subroutine. The results
+//! may be written as a hot-spot report or as folded stacks, in the form consumed by
+//! flame graph tools such as `flamegraph.pl` and `inferno`.
+
+use crate::cpu::CPU;
+use crate::execution::StepOutcome;
+use crate::instructions::enums::Instruction;
+use crate::symbols::SymbolTable;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// The execution statistics of a single instruction address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AddressProfile {
+    /// The address of the instruction.
+    pub address: u32,
+    /// The number of times the instruction was executed.
+    pub executions: u64,
+    /// The number of cycles consumed by the instruction.
+    pub cycles: u64,
+}
+
+/// The execution statistics of a single subroutine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubroutineProfile {
+    /// The entry address of the subroutine.
+    pub address: u32,
+    /// The name of the subroutine.
+    pub name: String,
+    /// The number of times the subroutine was entered.
+    pub calls: u64,
+    /// The number of instructions executed within the subroutine itself.
+    pub executions: u64,
+    /// The number of cycles consumed within the subroutine itself.
+    pub self_cycles: u64,
+    /// The number of cycles consumed within the subroutine and everything it called.
+    pub total_cycles: u64,
+}
+
+/// A distinct call stack, identified by its innermost subroutine and its caller.
+#[derive(Debug, Clone)]
+struct StackNode {
+    address: u32,
+    parent: Option<usize>,
+    children: HashMap<u32, usize>,
+    cycles: u64,
+}
+
+/// A subroutine on the call stack.
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    node: usize,
+    /// The total number of cycles profiled when the subroutine was entered.
+    entered: u64,
+    /// Whether no other frame of the same subroutine is beneath this one.
+    outermost: bool,
+}
+
+/// Collects execution statistics from every instruction executed by a virtual machine.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    symbols: SymbolTable,
+    addresses: HashMap<u32, AddressProfile>,
+    subroutines: HashMap<u32, SubroutineProfile>,
+    nodes: Vec<StackNode>,
+    stack: Vec<Frame>,
+    active: HashMap<u32, u32>,
+    cycles: u64,
+}
+
+impl Profiler {
+    /// Create a new profiler.
+    ///
+    /// # Arguments
+    ///
+    /// * `symbols` - the symbols used to name subroutines and addresses.
+    pub fn new(symbols: SymbolTable) -> Self {
+        Self {
+            symbols,
+            ..Self::default()
+        }
+    }
+
+    /// Returns a reference to the symbols used to name subroutines and addresses.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// Record a step.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - the CPU, in the state following the step.
+    /// * `outcome` - the outcome of the step.
+    pub fn record(&mut self, cpu: &CPU, outcome: &StepOutcome) {
+        if !outcome.executed() {
+            return;
+        }
+
+        // The subroutine in which execution began is treated as the root of every stack.
+        if self.stack.is_empty() {
+            self.enter(outcome.address);
+        }
+
+        let entry = self
+            .addresses
+            .entry(outcome.address)
+            .or_insert(AddressProfile {
+                address: outcome.address,
+                executions: 0,
+                cycles: 0,
+            });
+        entry.executions += 1;
+        entry.cycles += outcome.cycles;
+
+        self.cycles += outcome.cycles;
+
+        // The total cycles of a subroutine are credited when its outermost frame returns.
+        let node = &mut self.nodes[self.stack.last().unwrap().node];
+        node.cycles += outcome.cycles;
+        let sub = self.subroutines.get_mut(&node.address).unwrap();
+        sub.executions += 1;
+        sub.self_cycles += outcome.cycles;
+
+        // The cost of a call is borne by the caller and that of a return by the callee.
+        match outcome.instruction {
+            Some(Instruction::CalLit(_)) | Some(Instruction::CalReg(_)) => {
+                if let Ok(target) = cpu.get_instruction_pointer() {
+                    self.enter(target);
+                }
+            }
+            Some(Instruction::Ret()) if self.stack.len() > 1 => {
+                self.leave();
+            }
+            _ => {}
+        }
+    }
+
+    /// Discard every statistic collected so far.
+    pub fn reset(&mut self) {
+        self.addresses.clear();
+        self.subroutines.clear();
+        self.nodes.clear();
+        self.stack.clear();
+        self.active.clear();
+        self.cycles = 0;
+    }
+
+    /// Returns the statistics of every executed address, most expensive first.
+    pub fn hot_spots(&self) -> Vec<AddressProfile> {
+        let mut spots: Vec<AddressProfile> = self.addresses.values().copied().collect();
+        spots.sort_by(|a, b| b.cycles.cmp(&a.cycles).then(a.address.cmp(&b.address)));
+        spots
+    }
+
+    /// Returns the statistics of every entered subroutine, most expensive first.
+    pub fn subroutines(&self) -> Vec<SubroutineProfile> {
+        let mut subs = self.subroutines.clone();
+        // Subroutines that have not yet returned are credited up to the present.
+        for frame in self.stack.iter().filter(|f| f.outermost) {
+            let address = self.nodes[frame.node].address;
+            subs.get_mut(&address).unwrap().total_cycles += self.cycles - frame.entered;
+        }
+
+        let mut subs: Vec<SubroutineProfile> = subs.into_values().collect();
+        subs.sort_by(|a, b| {
+            b.total_cycles
+                .cmp(&a.total_cycles)
+                .then(a.address.cmp(&b.address))
+        });
+        subs
+    }
+
+    /// Write a human-readable report of the hot spots and subroutines.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the destination of the report.
+    /// * `limit` - the maximum number of entries in each section of the report.
+    pub fn write_report(&self, writer: &mut dyn Write, limit: usize) -> io::Result<()> {
+        writeln!(writer, "Hot spots")?;
+        writeln!(
+            writer,
+            "{:>10}  {:>12}  {:>12}  location",
+            "address", "executions", "cycles"
+        )?;
+        for spot in self.hot_spots().iter().take(limit) {
+            writeln!(
+                writer,
+                "{:#010X}  {:>12}  {:>12}  {}",
+                spot.address,
+                spot.executions,
+                spot.cycles,
+                self.symbols.describe(spot.address)
+            )?;
+        }
+
+        writeln!(writer)?;
+        writeln!(writer, "Subroutines")?;
+        writeln!(
+            writer,
+            "{:>10}  {:>8}  {:>12}  {:>12}  {:>12}  name",
+            "address", "calls", "executions", "self", "total"
+        )?;
+        for sub in self.subroutines().iter().take(limit) {
+            writeln!(
+                writer,
+                "{:#010X}  {:>8}  {:>12}  {:>12}  {:>12}  {}",
+                sub.address, sub.calls, sub.executions, sub.self_cycles, sub.total_cycles, sub.name
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the cycles consumed by every distinct call stack as folded stacks,
+    /// one `outer;inner cycles` line per stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the destination of the folded stacks.
+    pub fn write_folded(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let mut lines: Vec<String> = self
+            .nodes
+            .iter()
+            .filter(|node| node.cycles > 0)
+            .map(|node| {
+                let mut names = vec![self.subroutines[&node.address].name.as_str()];
+                let mut parent = node.parent;
+                while let Some(index) = parent {
+                    let caller = &self.nodes[index];
+                    names.push(self.subroutines[&caller.address].name.as_str());
+                    parent = caller.parent;
+                }
+                names.reverse();
+                format!("{} {}", names.join(";"), node.cycles)
+            })
+            .collect();
+        lines.sort();
+
+        for line in lines {
+            writeln!(writer, "{}", line)?;
+        }
+
+        Ok(())
+    }
+
+    fn enter(&mut self, address: u32) {
+        let parent = self.stack.last().map(|f| f.node);
+        let node = match parent.and_then(|p| self.nodes[p].children.get(&address)) {
+            Some(node) => *node,
+            None => {
+                self.nodes.push(StackNode {
+                    address,
+                    parent,
+                    children: HashMap::new(),
+                    cycles: 0,
+                });
+                let node = self.nodes.len() - 1;
+                if let Some(parent) = parent {
+                    self.nodes[parent].children.insert(address, node);
+                }
+                node
+            }
+        };
+
+        let active = self.active.entry(address).or_insert(0);
+        *active += 1;
+        self.stack.push(Frame {
+            node,
+            entered: self.cycles,
+            outermost: *active == 1,
+        });
+
+        let symbols = &self.symbols;
+        let sub = self
+            .subroutines
+            .entry(address)
+            .or_insert_with(|| SubroutineProfile {
+                address,
+                name: symbols.describe(address),
+                calls: 0,
+                executions: 0,
+                self_cycles: 0,
+                total_cycles: 0,
+            });
+        sub.calls += 1;
+    }
+
+    fn leave(&mut self) {
+        let frame = self.stack.pop().unwrap();
+        let address = self.nodes[frame.node].address;
+        *self.active.get_mut(&address).unwrap() -= 1;
+        if frame.outermost {
+            self.subroutines.get_mut(&address).unwrap().total_cycles += self.cycles - frame.entered;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registers::Registers;
+    use crate::virtual_machine::VirtualMachine;
+
+    /// A program in which `main` calls `inner` twice, once directly and once via `outer`.
+    fn profile_program() -> Profiler {
+        let program = vec![
+            // main (0)
+            Instruction::CalLit(20),
+            Instruction::CalLit(28),
+            Instruction::NOP(),
+            Instruction::HLT(),
+            // inner (16)
+            Instruction::Ret(),
+            Instruction::NOP(),
+            // outer (20)
+            Instruction::CalLit(16),
+            Instruction::Ret(),
+            // leaf (28)
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::Ret(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, "main".to_string());
+        symbols.insert(16, "inner".to_string());
+        symbols.insert(20, "outer".to_string());
+        symbols.insert(28, "leaf".to_string());
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+        vm.attach_profiler(Profiler::new(symbols));
+        vm.run_for(1_000);
+
+        vm.detach_profiler().unwrap()
+    }
+
+    #[test]
+    fn profiler_counts_subroutines() {
+        let profiler = profile_program();
+
+        let subs = profiler.subroutines();
+        let names: Vec<&str> = subs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["main", "outer", "leaf", "inner"]);
+
+        // main: two calls, a nop and a halt.
+        assert_eq!(subs[0].calls, 1);
+        assert_eq!(subs[0].executions, 4);
+        assert_eq!(subs[0].self_cycles, 3 + 3 + 1 + 1);
+        assert_eq!(
+            subs[0].total_cycles,
+            profiler.hot_spots().iter().map(|s| s.cycles).sum::<u64>()
+        );
+
+        // outer: a call and a return, plus the return of inner.
+        assert_eq!(subs[1].self_cycles, 6);
+        assert_eq!(subs[1].total_cycles, 9);
+
+        // leaf: an addition and a return.
+        assert_eq!(subs[2].total_cycles, 5);
+        assert_eq!(subs[3].calls, 1);
+        assert_eq!(subs[3].executions, 1);
+    }
+
+    #[test]
+    fn profiler_reports_hot_spots() {
+        let profiler = profile_program();
+
+        let spots = profiler.hot_spots();
+        assert_eq!(spots.len(), 9);
+        assert_eq!(spots[0].address, 0);
+        assert_eq!(spots[0].cycles, 3);
+        assert!(spots.windows(2).all(|w| w[0].cycles >= w[1].cycles));
+        assert!(spots.iter().all(|s| s.executions == 1));
+        // The nop following the return within inner is never executed.
+        assert!(spots.iter().all(|s| s.address != 18));
+
+        let mut report = Vec::new();
+        profiler.write_report(&mut report, 10).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("leaf+0x7"));
+        assert!(report.contains("main"));
+    }
+
+    #[test]
+    fn profiler_writes_folded_stacks() {
+        let profiler = profile_program();
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!(
+            String::from_utf8(folded).unwrap(),
+            "main 8\nmain;leaf 5\nmain;outer 6\nmain;outer;inner 3\n"
+        );
+    }
+
+    #[test]
+    fn profiler_counts_recursive_frames_once() {
+        let program = [
+            // main (0)
+            Instruction::CalLit(8),
+            Instruction::HLT(),
+            // rec (8), recursing until R1 reaches three.
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::MovRegReg(Registers::AC, Registers::R1),
+            Instruction::JeqLit(3, 35),
+            Instruction::CalLit(8),
+            Instruction::Ret(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0, "main".to_string());
+        symbols.insert(8, "rec".to_string());
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+        vm.attach_profiler(Profiler::new(symbols));
+        vm.run_for(1_000);
+        let profiler = vm.detach_profiler().unwrap();
+
+        let subs = profiler.subroutines();
+        assert_eq!(subs[0].name, "main");
+        assert_eq!(subs[1].name, "rec");
+        assert_eq!(subs[1].calls, 3);
+        assert_eq!(subs[1].self_cycles, subs[1].total_cycles);
+        assert_eq!(
+            subs[0].total_cycles,
+            subs[0].self_cycles + subs[1].total_cycles
+        );
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded).unwrap();
+        let folded = String::from_utf8(folded).unwrap();
+        assert_eq!(folded.lines().count(), 4);
+        assert!(folded.contains("main;rec;rec;rec "));
+    }
+}
@@ -17,7 +17,7 @@ bitflags! {
         const R = 1 << 1;
         const W = 1 << 2;
         const PR = 1 << 3;
-        const PW = 1 << 3;
+        const PW = 1 << 4;
     }
 }
 
@@ -41,6 +41,14 @@ pub struct Register {
     value: RegisterValue,
 }
 
+/// A record of a single write to a register.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RegisterWrite {
+    pub register_id: Registers,
+    pub old_value: RegisterValue,
+    pub new_value: RegisterValue,
+}
+
 impl Register {
     pub fn new(access: RegisterAccess, reg: Registers, reg_type: RegisterValue) -> Self {
         Self {
@@ -70,13 +78,14 @@ impl Register {
         Ok(&self.value)
     }
 
+    /// Sets the value field of the Register struct.
     pub fn set_value(
         &mut self,
         value: RegisterValue,
         security_context: SecurityContext,
     ) -> Result<()> {
         ensure!(
-            self.validate_access(security_context, AccessType::Read),
+            self.validate_access(security_context, AccessType::Write),
             RegisterInvalidAccess
         );
 
@@ -85,9 +94,33 @@ impl Register {
         Ok(())
     }
 
+    /// Returns the value field of the Register struct without validating access.
+    pub(crate) fn get_value_unchecked(&self) -> RegisterValue {
+        self.value
+    }
+
+    /// Sets the value field of the Register struct without validating access.
+    pub(crate) fn set_value_unchecked(&mut self, value: RegisterValue) {
+        self.value = value;
+    }
+
+    /// Returns the access flags of the Register struct.
+    pub fn get_access_flags(&self) -> RegisterAccess {
+        self.access_flags
+    }
+
     fn validate_access(&self, security_context: SecurityContext, access_type: AccessType) -> bool {
-        // TODO - make this actually do something
-        true
+        // The system context may also make use of the private flags,
+        // the user context may only make use of the public ones.
+        let (public, private) = match access_type {
+            AccessType::Read => (RegisterAccess::R, RegisterAccess::PR),
+            AccessType::Write => (RegisterAccess::W, RegisterAccess::PW),
+        };
+
+        match security_context {
+            SecurityContext::User => self.access_flags.contains(public),
+            SecurityContext::System => self.access_flags.intersects(public | private),
+        }
     }
 }
 
@@ -103,6 +136,51 @@ pub enum Registers {
     R8,
     AC,
     FL,
+    IP,
+    SP,
+    FH,
+}
+
+impl Registers {
+    /// The number of registers.
+    pub const COUNT: usize = 13;
+
+    /// Returns the register that corresponds to the specified encoded register ID, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - the encoded register ID.
+    pub fn from_u8(value: u8) -> Option<Self> {
+        let reg = match value {
+            0 => Registers::R1,
+            1 => Registers::R2,
+            2 => Registers::R3,
+            3 => Registers::R4,
+            4 => Registers::R5,
+            5 => Registers::R6,
+            6 => Registers::R7,
+            7 => Registers::R8,
+            8 => Registers::AC,
+            9 => Registers::FL,
+            10 => Registers::IP,
+            11 => Registers::SP,
+            12 => Registers::FH,
+            _ => return None,
+        };
+
+        Some(reg)
+    }
+
+    /// Returns the register with the specified name, ignoring case, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the register, as displayed.
+    pub fn from_name(name: &str) -> Option<Self> {
+        (0..=u8::MAX)
+            .map_while(Registers::from_u8)
+            .find(|r| r.to_string().eq_ignore_ascii_case(name))
+    }
 }
 
 impl fmt::Display for Registers {
@@ -118,6 +196,9 @@ impl fmt::Display for Registers {
             Registers::R8 => "R8",
             Registers::AC => "AC",
             Registers::FL => "FL",
+            Registers::IP => "IP",
+            Registers::SP => "SP",
+            Registers::FH => "FH",
         };
         write!(f, "{}", printable)
     }
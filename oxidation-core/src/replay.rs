@@ -0,0 +1,163 @@
+//! Logs of the non-deterministic inputs supplied to a virtual machine.
+//!
+//! A log file consists of the magic number, the format version, the number of
+//! instructions executed when recording began and a count-prefixed list of events.
+//! All values are little-endian.
+
+use snafu::{ensure, ResultExt, Snafu};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+type Result<T, E = ReplayError> = std::result::Result<T, E>;
+
+/// The magic number found at the start of every input log file.
+pub const INPUT_LOG_MAGIC: [u8; 4] = *b"OXRL";
+
+/// The version of the input log format written by this library.
+pub const INPUT_LOG_VERSION: u16 = 1;
+
+#[derive(Debug, Snafu)]
+pub enum ReplayError {
+    #[snafu(display("an I/O error occurred while processing an input log: {}", source))]
+    InputLogIo { source: std::io::Error },
+    #[snafu(display("the file is not an input log"))]
+    InvalidInputLogMagic,
+    #[snafu(display("the input log version {} is not supported", version))]
+    UnsupportedInputLogVersion { version: u16 },
+    #[snafu(display("the input log contains an unknown input kind ({})", kind))]
+    InvalidInputKind { kind: u8 },
+}
+
+/// The kind of a non-deterministic input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputKind {
+    /// A value read from the device attached to an I/O port.
+    DeviceRead { port: u8 },
+}
+
+/// A single non-deterministic input supplied to the virtual machine.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InputEvent {
+    /// The number of instructions executed before the input was supplied.
+    pub instruction: u64,
+    /// The address of the instruction that consumed the input.
+    pub address: u32,
+    /// The kind of the input.
+    pub kind: InputKind,
+    /// The value of the input.
+    pub value: i32,
+}
+
+/// A recording of every non-deterministic input supplied to the virtual machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputLog {
+    /// The number of instructions executed when recording began.
+    pub start_instruction: u64,
+    /// The recorded inputs, in the order in which they were supplied.
+    pub events: Vec<InputEvent>,
+}
+
+impl InputLog {
+    pub fn new(start_instruction: u64) -> Self {
+        Self {
+            start_instruction,
+            events: Vec::new(),
+        }
+    }
+
+    /// Save the log to a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to the log file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = File::create(path).context(InputLogIo)?;
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer)?;
+        writer.flush().context(InputLogIo)
+    }
+
+    /// Load a log from a file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to the log file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path).context(InputLogIo)?;
+        Self::read(&mut BufReader::new(file))
+    }
+
+    /// Write the log in its binary form.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the destination of the log.
+    pub fn write(&self, writer: &mut dyn Write) -> Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&INPUT_LOG_MAGIC);
+        bytes.extend_from_slice(&INPUT_LOG_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.start_instruction.to_le_bytes());
+        bytes.extend_from_slice(&(self.events.len() as u32).to_le_bytes());
+
+        for event in &self.events {
+            bytes.extend_from_slice(&event.instruction.to_le_bytes());
+            bytes.extend_from_slice(&event.address.to_le_bytes());
+            match event.kind {
+                InputKind::DeviceRead { port } => {
+                    bytes.push(0);
+                    bytes.push(port);
+                }
+            }
+            bytes.extend_from_slice(&event.value.to_le_bytes());
+        }
+
+        writer.write_all(&bytes).context(InputLogIo)
+    }
+
+    /// Read a log from its binary form.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - the source of the log.
+    pub fn read(reader: &mut dyn Read) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic).context(InputLogIo)?;
+        ensure!(magic == INPUT_LOG_MAGIC, InvalidInputLogMagic);
+
+        let version = u16::from_le_bytes(read_array(reader)?);
+        ensure!(
+            version == INPUT_LOG_VERSION,
+            UnsupportedInputLogVersion { version }
+        );
+
+        let mut log = InputLog::new(u64::from_le_bytes(read_array(reader)?));
+        let count = u32::from_le_bytes(read_array(reader)?);
+
+        for _ in 0..count {
+            let instruction = u64::from_le_bytes(read_array(reader)?);
+            let address = u32::from_le_bytes(read_array(reader)?);
+            let [kind, port] = read_array(reader)?;
+            let kind = match kind {
+                0 => InputKind::DeviceRead { port },
+                _ => return InvalidInputKind { kind }.fail(),
+            };
+            let value = i32::from_le_bytes(read_array(reader)?);
+
+            log.events.push(InputEvent {
+                instruction,
+                address,
+                kind,
+                value,
+            });
+        }
+
+        Ok(log)
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut dyn Read) -> Result<[u8; N]> {
+    let mut buffer = [0u8; N];
+    reader.read_exact(&mut buffer).context(InputLogIo)?;
+    Ok(buffer)
+}
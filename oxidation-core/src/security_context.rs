@@ -1,4 +1,5 @@
 /// The security context to be used for data access requests.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SecurityContext {
     User,
     System,
@@ -0,0 +1,292 @@
+//! Versioned binary snapshots of the complete state of a virtual machine.
+//!
+//! All values are little-endian. A snapshot consists of the magic number
+//! and version, followed by the state of the CPU (executable region, halt
+//! state, counters and every register with its access flags) and then the
+//! state of the memory (sizes, stack pointer, region table and contents).
+
+use crate::registers::RegisterValue;
+use snafu::{ensure, ResultExt, Snafu};
+use std::io::{Read, Write};
+
+pub type Result<T, E = SnapshotError> = std::result::Result<T, E>;
+
+/// The magic number found at the start of every snapshot file.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"OXSS";
+
+/// The version of the snapshot format written by this library.
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility = "pub(crate)")]
+pub enum SnapshotError {
+    #[snafu(display("an I/O error occurred while processing a snapshot: {}", source))]
+    SnapshotIo { source: std::io::Error },
+    #[snafu(display("the file is not a virtual machine snapshot"))]
+    InvalidSnapshotMagic,
+    #[snafu(display("the snapshot version {} is not supported", version))]
+    UnsupportedSnapshotVersion { version: u16 },
+    #[snafu(display("the snapshot contains invalid data: {}", reason))]
+    InvalidSnapshotData { reason: String },
+}
+
+/// Writes the primitive values of a snapshot in little-endian form.
+pub(crate) struct SnapshotWriter<'a> {
+    writer: &'a mut dyn Write,
+}
+
+/// Reads the primitive values of a snapshot in little-endian form.
+pub(crate) struct SnapshotReader<'a> {
+    reader: &'a mut dyn Read,
+}
+
+impl<'a> SnapshotWriter<'a> {
+    pub fn new(writer: &'a mut dyn Write) -> Self {
+        Self { writer }
+    }
+
+    /// Write the snapshot header.
+    pub fn write_header(&mut self) -> Result<()> {
+        self.write_bytes(&SNAPSHOT_MAGIC)?;
+        self.write_u16(SNAPSHOT_VERSION)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer.write_all(bytes).context(SnapshotIo)
+    }
+
+    pub fn write_bool(&mut self, value: bool) -> Result<()> {
+        self.write_u8(value as u8)
+    }
+
+    pub fn write_u8(&mut self, value: u8) -> Result<()> {
+        self.write_bytes(&[value])
+    }
+
+    pub fn write_u16(&mut self, value: u16) -> Result<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    pub fn write_i16(&mut self, value: i16) -> Result<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    pub fn write_u32(&mut self, value: u32) -> Result<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    pub fn write_u64(&mut self, value: u64) -> Result<()> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    /// Write a length-prefixed UTF-8 string.
+    pub fn write_string(&mut self, value: &str) -> Result<()> {
+        self.write_u32(value.len() as u32)?;
+        self.write_bytes(value.as_bytes())
+    }
+
+    /// Write a register value, prefixed by a tag identifying its type.
+    pub fn write_register_value(&mut self, value: RegisterValue) -> Result<()> {
+        match value {
+            RegisterValue::I16(v) => {
+                self.write_u8(0)?;
+                self.write_bytes(&v.to_le_bytes())
+            }
+            RegisterValue::I32(v) => {
+                self.write_u8(1)?;
+                self.write_bytes(&v.to_le_bytes())
+            }
+            RegisterValue::I64(v) => {
+                self.write_u8(2)?;
+                self.write_bytes(&v.to_le_bytes())
+            }
+            RegisterValue::F32(v) => {
+                self.write_u8(3)?;
+                self.write_bytes(&v.to_le_bytes())
+            }
+        }
+    }
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn new(reader: &'a mut dyn Read) -> Self {
+        Self { reader }
+    }
+
+    /// Read and validate the snapshot header.
+    pub fn read_header(&mut self) -> Result<()> {
+        ensure!(
+            self.read_array::<4>()? == SNAPSHOT_MAGIC,
+            InvalidSnapshotMagic
+        );
+
+        let version = self.read_u16()?;
+        ensure!(
+            version == SNAPSHOT_VERSION,
+            UnsupportedSnapshotVersion { version }
+        );
+
+        Ok(())
+    }
+
+    /// Read a number of bytes. The length is not trusted, so the buffer grows only
+    /// as the bytes are read, rather than being allocated up front.
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - the number of bytes to read.
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        (&mut *self.reader)
+            .take(len as u64)
+            .read_to_end(&mut buffer)
+            .context(SnapshotIo)?;
+        if buffer.len() != len {
+            let eof = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+            return Err(eof).context(SnapshotIo);
+        }
+
+        Ok(buffer)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buffer = [0u8; N];
+        self.reader.read_exact(&mut buffer).context(SnapshotIo)?;
+        Ok(buffer)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            v => invalid(format!("{} is not a valid boolean", v)),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    /// Read a length-prefixed UTF-8 string.
+    pub fn read_string(&mut self) -> Result<String> {
+        let len = self.read_u32()? as usize;
+        match String::from_utf8(self.read_bytes(len)?) {
+            Ok(s) => Ok(s),
+            Err(_) => invalid("a string is not valid UTF-8".to_string()),
+        }
+    }
+
+    /// Read a register value that is prefixed by a tag identifying its type.
+    pub fn read_register_value(&mut self) -> Result<RegisterValue> {
+        let value = match self.read_u8()? {
+            0 => RegisterValue::I16(i16::from_le_bytes(self.read_array()?)),
+            1 => RegisterValue::I32(i32::from_le_bytes(self.read_array()?)),
+            2 => RegisterValue::I64(i64::from_le_bytes(self.read_array()?)),
+            3 => RegisterValue::F32(f32::from_le_bytes(self.read_array()?)),
+            tag => return invalid(format!("{} is not a valid register value type", tag)),
+        };
+
+        Ok(value)
+    }
+}
+
+/// Returns an error indicating that the snapshot contains invalid data.
+///
+/// # Arguments
+///
+/// * `reason` - a description of the problem.
+pub(crate) fn invalid<T>(reason: String) -> Result<T> {
+    InvalidSnapshotData { reason }.fail()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{RegisterCollection, CPU};
+    use crate::memory::Memory;
+    use crate::registers::{RegisterAccess, Registers};
+
+    #[test]
+    fn lengths_are_not_trusted() {
+        let mut bytes: &[u8] = &[0xFF, 0xFF, 0xFF, 0xFF, b'a', b'b'];
+        let mut reader = SnapshotReader::new(&mut bytes);
+        assert!(matches!(
+            reader.read_string(),
+            Err(SnapshotError::SnapshotIo { .. })
+        ));
+    }
+
+    #[test]
+    fn inconsistent_states_are_rejected() {
+        // The base size, stack bounds and stack pointer precede the end of the root region.
+        let mut bytes = Vec::new();
+        Memory::new(16, 2)
+            .write_snapshot(&mut SnapshotWriter::new(&mut bytes))
+            .unwrap();
+        let read_memory = |patch: (usize, u32)| {
+            let mut bytes = bytes.clone();
+            bytes[patch.0..patch.0 + 4].copy_from_slice(&patch.1.to_le_bytes());
+            Memory::read_snapshot(&mut SnapshotReader::new(&mut bytes.as_slice()))
+        };
+        assert!(read_memory((4, 16)).is_ok());
+        for patch in [
+            (4, 25),
+            (8, 25),
+            (8, 12),
+            (12, 8),
+            (28, 24),
+            (4, u32::MAX - 1),
+        ] {
+            assert!(matches!(
+                read_memory(patch),
+                Err(SnapshotError::InvalidSnapshotData { .. })
+            ));
+        }
+
+        // The instruction pointer may not be made writable by user code.
+        let mut bytes = Vec::new();
+        let mut writer = SnapshotWriter::new(&mut bytes);
+        writer.write_u32(Registers::COUNT as u32).unwrap();
+        for id in 0..Registers::COUNT as u8 {
+            let register = Registers::from_u8(id).unwrap();
+            let access = match register {
+                Registers::IP => RegisterAccess::R | RegisterAccess::W,
+                _ => CPU::new()
+                    .registers
+                    .get_register_ref(register)
+                    .get_access_flags(),
+            };
+            writer.write_u8(id).unwrap();
+            writer.write_u8(access.bits()).unwrap();
+            writer.write_register_value(RegisterValue::I32(0)).unwrap();
+        }
+        assert!(matches!(
+            RegisterCollection::read_snapshot(&mut SnapshotReader::new(&mut bytes.as_slice())),
+            Err(SnapshotError::InvalidSnapshotData { .. })
+        ));
+
+        // A stack pointer at the top of the address space cannot overflow the stack guards.
+        let mut cpu = CPU::new();
+        let mut mem = Memory::new(16, 2);
+        cpu.set_stack_pointer(u32::MAX - 1).unwrap();
+        assert!(cpu.pop_stack(&mem).is_err());
+        cpu.set_stack_pointer(2).unwrap();
+        assert!(cpu.push_stack(&mut mem, 1).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+/// A line within an assembly source file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceLocation {
+    /// The path of the source file.
+    pub file: String,
+    /// The one-based line number.
+    pub line: u32,
+}
+
+/// A mapping between the addresses of instructions and the source lines from which they were assembled.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    locations: BTreeMap<u32, SourceLocation>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self {
+            locations: BTreeMap::new(),
+        }
+    }
+
+    /// Map the instruction at an address to a source line, replacing any existing mapping.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the instruction.
+    /// * `file` - the path of the source file.
+    /// * `line` - the one-based line number.
+    pub fn insert(&mut self, address: u32, file: &str, line: u32) {
+        self.locations.insert(
+            address,
+            SourceLocation {
+                file: file.to_string(),
+                line,
+            },
+        );
+    }
+
+    /// Returns the number of mapped instructions.
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Returns true if no instructions are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Returns the source line from which the instruction at an address was assembled, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the instruction.
+    pub fn get(&self, address: u32) -> Option<&SourceLocation> {
+        self.locations.get(&address)
+    }
+
+    /// Returns the address and source line of the closest mapped instruction at or
+    /// before an address, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address to be resolved.
+    pub fn lookup(&self, address: u32) -> Option<(u32, &SourceLocation)> {
+        self.locations
+            .range(..=address)
+            .next_back()
+            .map(|(a, l)| (*a, l))
+    }
+
+    /// Returns an iterator over the mapped instructions, in ascending order of address.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &SourceLocation)> {
+        self.locations.iter().map(|(a, l)| (*a, l))
+    }
+}
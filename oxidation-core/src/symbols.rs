@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+
+/// A mapping between addresses and the names of the symbols found at them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: BTreeMap<u32, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    /// Add a symbol, replacing any symbol previously found at the same address.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the symbol.
+    /// * `name` - the name of the symbol.
+    pub fn insert(&mut self, address: u32, name: String) {
+        self.symbols.insert(address, name);
+    }
+
+    /// Returns the number of symbols held within the table.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Returns true if the table holds no symbols.
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns the name of the symbol found at exactly the specified address, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address of the symbol.
+    pub fn get(&self, address: u32) -> Option<&str> {
+        self.symbols.get(&address).map(|s| s.as_str())
+    }
+
+    /// Returns the address and name of the closest symbol at or before the specified address, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address to be resolved.
+    pub fn lookup(&self, address: u32) -> Option<(u32, &str)> {
+        self.symbols
+            .range(..=address)
+            .next_back()
+            .map(|(a, s)| (*a, s.as_str()))
+    }
+
+    /// Returns the address of the symbol with the specified name, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - the name of the symbol.
+    pub fn address_of(&self, name: &str) -> Option<u32> {
+        self.symbols
+            .iter()
+            .find(|(_, s)| s.as_str() == name)
+            .map(|(a, _)| *a)
+    }
+
+    /// Returns a description of the specified address in the form `name+offset`,
+    /// falling back to the hexadecimal address if no symbol precedes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - the address to be described.
+    pub fn describe(&self, address: u32) -> String {
+        match self.lookup(address) {
+            Some((start, name)) if start == address => name.to_string(),
+            Some((start, name)) => format!("{}+{:#X}", name, address - start),
+            None => format!("{:#010X}", address),
+        }
+    }
+
+    /// Returns an iterator over the symbols, in ascending order of address.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.symbols.iter().map(|(a, s)| (*a, s.as_str()))
+    }
+}
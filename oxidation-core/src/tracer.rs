@@ -0,0 +1,458 @@
+//! Structured per-instruction execution traces.
+//!
+//! Traces may be written as JSON Lines, with one object per executed instruction,
+//! or in a compact binary form. The binary form consists of the magic number and
+//! format version followed by one record per instruction, all values being
+//! little-endian:
+//!
+//! * `u64` instruction index, `u32` address, `u8` length and the encoded instruction.
+//!   The length is zero for a step that faulted before an instruction was decoded.
+//! * `u8` register write count, each being a `u8` register ID and the old and new
+//!   values (a `u8` type tag followed by the value).
+//! * `u16` memory write count, each being a `u32` address, a `u16` length and the
+//!   old and new bytes.
+//! * the flags register value, as a `u8` type tag followed by the value.
+//! * `u8` fault indicator.
+
+use crate::cpu::{CpuError, CPU};
+use crate::execution::StepOutcome;
+use crate::instructions::enums::OpCode;
+use crate::registers::{RegisterValue, Registers};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+/// The magic number found at the start of every binary trace.
+pub const TRACE_MAGIC: [u8; 4] = *b"OXTR";
+
+/// The version of the binary trace format written by this library.
+pub const TRACE_VERSION: u16 = 1;
+
+/// The format in which trace records are written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One JSON object per line.
+    JsonLines,
+    /// The compact binary form described in the module documentation.
+    Binary,
+}
+
+/// Restricts the instructions for which trace records are written.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// If set, only instructions within the address range are traced.
+    pub address_range: Option<RangeInclusive<u32>>,
+    /// If set, only instructions with one of the opcodes are traced. Steps that
+    /// faulted before an instruction was decoded are then never traced.
+    pub opcodes: Option<HashSet<OpCode>>,
+}
+
+impl TraceFilter {
+    /// Returns true if a record should be written for the step.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - the outcome of the step.
+    pub fn matches(&self, outcome: &StepOutcome) -> bool {
+        if outcome.instruction.is_none() && fault(outcome).is_none() {
+            return false;
+        }
+
+        if let Some(range) = &self.address_range {
+            if !range.contains(&outcome.address) {
+                return false;
+            }
+        }
+
+        match (&self.opcodes, &outcome.instruction) {
+            (Some(opcodes), Some(ins)) => opcodes.contains(&ins.opcode()),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+}
+
+/// Writes a structured record for every instruction executed by a virtual machine.
+pub struct Tracer {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    started: bool,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    /// Create a new tracer.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the destination of the trace records.
+    /// * `format` - the format in which records are written.
+    /// * `filter` - the filter restricting the instructions to be traced.
+    pub fn new(writer: Box<dyn Write>, format: TraceFormat, filter: TraceFilter) -> Self {
+        Self {
+            writer,
+            format,
+            filter,
+            started: false,
+            error: None,
+        }
+    }
+
+    /// Write the record for a step, if it passes the filter.
+    ///
+    /// Writing stops after the first error, which is reported by [`Tracer::finish`].
+    ///
+    /// # Arguments
+    ///
+    /// * `cpu` - the CPU, in the state following the step.
+    /// * `outcome` - the outcome of the step.
+    pub fn trace(&mut self, cpu: &CPU, outcome: &StepOutcome) {
+        if self.error.is_some() || !self.filter.matches(outcome) {
+            return;
+        }
+
+        let result = match self.format {
+            TraceFormat::JsonLines => self.write_json(cpu, outcome),
+            TraceFormat::Binary => self.write_binary(cpu, outcome),
+        };
+
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flush the trace, returning the first error that occurred while writing it.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+
+        if self.format == TraceFormat::Binary && !self.started {
+            self.write_header()?;
+        }
+
+        self.writer.flush()
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.started = true;
+        self.writer.write_all(&TRACE_MAGIC)?;
+        self.writer.write_all(&TRACE_VERSION.to_le_bytes())
+    }
+
+    fn write_json(&mut self, cpu: &CPU, outcome: &StepOutcome) -> io::Result<()> {
+        let ins = outcome.instruction.as_ref();
+
+        let registers: Vec<Value> = outcome
+            .register_writes
+            .iter()
+            .map(|w| {
+                json!({
+                    "register": w.register_id.to_string(),
+                    "old": json_value(w.old_value),
+                    "new": json_value(w.new_value),
+                })
+            })
+            .collect();
+
+        let memory: Vec<Value> = outcome
+            .memory_writes
+            .iter()
+            .map(|w| {
+                json!({
+                    "address": w.address,
+                    "old": hex(&w.old_bytes),
+                    "new": hex(&w.new_bytes),
+                })
+            })
+            .collect();
+
+        let record = json!({
+            "instruction": instruction_index(cpu, outcome),
+            "address": outcome.address,
+            "bytes": ins.map(|i| hex(&i.encode())),
+            "disassembly": ins.map(|i| i.to_string()),
+            "opcode": ins.map(|i| format!("{:?}", i.opcode())),
+            "registers": registers,
+            "memory": memory,
+            "flags": json_value(flags(cpu)),
+            "fault": fault(outcome).map(|e| e.to_string()),
+        });
+
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn write_binary(&mut self, cpu: &CPU, outcome: &StepOutcome) -> io::Result<()> {
+        if !self.started {
+            self.write_header()?;
+        }
+
+        let encoded = outcome
+            .instruction
+            .as_ref()
+            .map(|i| i.encode())
+            .unwrap_or_default();
+
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(&instruction_index(cpu, outcome).to_le_bytes());
+        bytes.extend_from_slice(&outcome.address.to_le_bytes());
+        bytes.push(encoded.len() as u8);
+        bytes.extend_from_slice(&encoded);
+
+        bytes.push(outcome.register_writes.len() as u8);
+        for w in &outcome.register_writes {
+            bytes.push(w.register_id as u8);
+            binary_value(&mut bytes, w.old_value);
+            binary_value(&mut bytes, w.new_value);
+        }
+
+        bytes.extend_from_slice(&(outcome.memory_writes.len() as u16).to_le_bytes());
+        for w in &outcome.memory_writes {
+            bytes.extend_from_slice(&w.address.to_le_bytes());
+            bytes.extend_from_slice(&(w.new_bytes.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(&w.old_bytes);
+            bytes.extend_from_slice(&w.new_bytes);
+        }
+
+        binary_value(&mut bytes, flags(cpu));
+        bytes.push(fault(outcome).is_some() as u8);
+
+        self.writer.write_all(&bytes)
+    }
+}
+
+/// Returns the error that occurred during a step, whether or not it was delivered
+/// to the fault handler.
+fn fault(outcome: &StepOutcome) -> Option<&CpuError> {
+    outcome.fault.as_ref().or(outcome.handled_fault.as_ref())
+}
+
+/// Returns the zero-based index of the instruction described by the step.
+fn instruction_index(cpu: &CPU, outcome: &StepOutcome) -> u64 {
+    cpu.instructions_executed() - outcome.executed() as u64
+}
+
+fn flags(cpu: &CPU) -> RegisterValue {
+    cpu.registers
+        .get_register_ref(Registers::FL)
+        .get_value_unchecked()
+}
+
+fn json_value(value: RegisterValue) -> Value {
+    match value {
+        RegisterValue::I16(v) => json!(v),
+        RegisterValue::I32(v) => json!(v),
+        RegisterValue::I64(v) => json!(v),
+        RegisterValue::F32(v) => json!(v),
+    }
+}
+
+fn binary_value(bytes: &mut Vec<u8>, value: RegisterValue) {
+    match value {
+        RegisterValue::I16(v) => {
+            bytes.push(0);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        RegisterValue::I32(v) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        RegisterValue::I64(v) => {
+            bytes.push(2);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        RegisterValue::F32(v) => {
+            bytes.push(3);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::enums::Instruction;
+    use crate::virtual_machine::VirtualMachine;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// A writer whose contents remain accessible once the tracer has taken ownership of it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace_program(format: TraceFormat, filter: TraceFilter) -> Vec<u8> {
+        let program: Vec<u8> = [
+            Instruction::MovLitReg(0x1234, Registers::R2),
+            Instruction::MovRegMem(Registers::R2, 500),
+            Instruction::AddLitReg(1, Registers::R2),
+            Instruction::HLT(),
+        ]
+        .iter()
+        .flat_map(|i| i.encode())
+        .collect();
+
+        trace_bytes(&program, format, filter)
+    }
+
+    fn trace_bytes(program: &[u8], format: TraceFormat, filter: TraceFilter) -> Vec<u8> {
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(program, 0).unwrap();
+
+        let buffer = SharedBuffer::default();
+        vm.attach_tracer(Tracer::new(Box::new(buffer.clone()), format, filter));
+        vm.run_for(100);
+        vm.detach_tracer().unwrap().finish().unwrap();
+
+        let bytes = buffer.0.borrow().clone();
+        bytes
+    }
+
+    fn json_records(filter: TraceFilter) -> Vec<Value> {
+        let bytes = trace_program(TraceFormat::JsonLines, filter);
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn json_trace_describes_each_instruction() {
+        let records = json_records(TraceFilter::default());
+        assert_eq!(records.len(), 4);
+
+        assert_eq!(records[0]["instruction"], 0);
+        assert_eq!(records[0]["address"], 0);
+        assert_eq!(records[0]["disassembly"], "mov 1234, R2");
+        assert_eq!(records[0]["opcode"], "MovLitReg");
+        assert_eq!(records[0]["registers"][0]["register"], "R2");
+        assert_eq!(records[0]["registers"][0]["old"], 0);
+        assert_eq!(records[0]["registers"][0]["new"], 0x1234);
+        assert_eq!(records[0]["flags"], 0);
+        assert!(records[0]["fault"].is_null());
+
+        assert_eq!(records[1]["instruction"], 1);
+        assert_eq!(records[1]["address"], 7);
+        assert_eq!(
+            records[1]["bytes"],
+            hex(&Instruction::MovRegMem(Registers::R2, 500).encode())
+        );
+        assert_eq!(records[1]["memory"][0]["address"], 500);
+        assert_eq!(records[1]["memory"][0]["old"], "00000000");
+        assert_eq!(records[1]["memory"][0]["new"], "34120000");
+
+        assert_eq!(records[3]["disassembly"], "hlt");
+    }
+
+    #[test]
+    fn trace_filters_restrict_records() {
+        let records = json_records(TraceFilter {
+            address_range: Some(7..=20),
+            opcodes: None,
+        });
+        let addresses: Vec<u64> = records
+            .iter()
+            .map(|r| r["address"].as_u64().unwrap())
+            .collect();
+        assert_eq!(addresses, vec![7, 14]);
+
+        let mut opcodes = HashSet::new();
+        opcodes.insert(OpCode::AddLitReg);
+        let records = json_records(TraceFilter {
+            address_range: None,
+            opcodes: Some(opcodes),
+        });
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["disassembly"], "add 01, R2");
+    }
+
+    #[test]
+    fn undecoded_steps_are_traced_with_their_fault() {
+        let mut program = Instruction::NOP().encode();
+        program.extend_from_slice(&[0xFF, 0xFF]);
+        let lines = |filter| {
+            let bytes = trace_bytes(&program, TraceFormat::JsonLines, filter);
+            String::from_utf8(bytes)
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect::<Vec<Value>>()
+        };
+
+        let records = lines(TraceFilter::default());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["address"], 2);
+        assert!(records[1]["disassembly"].is_null());
+        assert!(records[1]["fault"].is_string());
+
+        // Only the address filter applies to a step without an instruction.
+        let records = lines(TraceFilter {
+            address_range: Some(2..=2),
+            opcodes: None,
+        });
+        assert_eq!(records.len(), 1);
+        let mut opcodes = HashSet::new();
+        opcodes.insert(OpCode::NOP);
+        let records = lines(TraceFilter {
+            address_range: None,
+            opcodes: Some(opcodes),
+        });
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["address"], 0);
+
+        // The binary record holds no encoded instruction, and indicates the fault.
+        let bytes = trace_bytes(
+            &program,
+            TraceFormat::Binary,
+            TraceFilter {
+                address_range: Some(2..=2),
+                opcodes: None,
+            },
+        );
+        assert_eq!(bytes[14..18], 2u32.to_le_bytes());
+        assert_eq!(bytes[18], 0);
+        assert_eq!(bytes[bytes.len() - 1], 1);
+    }
+
+    #[test]
+    fn binary_trace_has_header_and_records() {
+        let bytes = trace_program(TraceFormat::Binary, TraceFilter::default());
+        assert_eq!(bytes[0..4], TRACE_MAGIC);
+        assert_eq!(bytes[4..6], TRACE_VERSION.to_le_bytes());
+
+        // The first record: instruction 0 at address 0.
+        assert_eq!(bytes[6..14], 0u64.to_le_bytes());
+        assert_eq!(bytes[14..18], 0u32.to_le_bytes());
+        let encoded = Instruction::MovLitReg(0x1234, Registers::R2).encode();
+        assert_eq!(bytes[18] as usize, encoded.len());
+        assert_eq!(bytes[19..19 + encoded.len()], encoded[..]);
+
+        // An empty trace still has a header.
+        let bytes = trace_program(
+            TraceFormat::Binary,
+            TraceFilter {
+                address_range: Some(900..=999),
+                opcodes: None,
+            },
+        );
+        assert_eq!(bytes.len(), 6);
+    }
+}
@@ -0,0 +1,262 @@
+//! A static verifier for program images.
+//!
+//! The image is swept linearly from its start, checking that each instruction has a
+//! known opcode, valid register operands and that it lies entirely within the image.
+//! The targets of every jump and call must then land upon the boundary of one of the
+//! instructions found by the sweep.
+
+use crate::instructions::encoding::{operands, Operand, OPCODE_SIZE};
+use crate::instructions::enums::OpCode;
+use crate::registers::Registers;
+use snafu::Snafu;
+
+#[derive(Debug, Snafu, PartialEq, Eq)]
+pub enum VerifyError {
+    #[snafu(display("an invalid opcode ({:#06X}) was found at {:#010X}", opcode, address))]
+    InvalidOpCode { address: u32, opcode: i16 },
+    #[snafu(display(
+        "the opcode {:?} at {:#010X} is not supported by the CPU",
+        opcode,
+        address
+    ))]
+    UnsupportedOpCode { address: u32, opcode: OpCode },
+    #[snafu(display(
+        "the instruction at {:#010X} has an invalid register ID ({})",
+        address,
+        register
+    ))]
+    InvalidRegister { address: u32, register: u8 },
+    #[snafu(display(
+        "the instruction at {:#010X} extends beyond the end of the executable region",
+        address
+    ))]
+    TruncatedInstruction { address: u32 },
+    #[snafu(display(
+        "the instruction at {:#010X} targets {:#010X}, which is not the start of an instruction",
+        address,
+        target
+    ))]
+    InvalidTarget { address: u32, target: u32 },
+    #[snafu(display(
+        "the instruction at offset {:#X} lies beyond the end of the address space",
+        offset
+    ))]
+    OutOfRange { offset: u32 },
+}
+
+impl VerifyError {
+    /// Returns the address of the instruction at which the problem was found, or the
+    /// last address if the instruction lies beyond the end of the address space.
+    pub fn address(&self) -> u32 {
+        match *self {
+            VerifyError::InvalidOpCode { address, .. }
+            | VerifyError::UnsupportedOpCode { address, .. }
+            | VerifyError::InvalidRegister { address, .. }
+            | VerifyError::TruncatedInstruction { address }
+            | VerifyError::InvalidTarget { address, .. } => address,
+            VerifyError::OutOfRange { .. } => u32::MAX,
+        }
+    }
+}
+
+/// Statically verify a program image, returning every problem found, ordered by
+/// the address at which it was found.
+///
+/// Once an instruction is found whose size cannot be determined, the remainder of
+/// the image cannot be decoded, so the sweep stops and targets beyond that point
+/// are not checked.
+///
+/// # Arguments
+///
+/// * `program` - the bytes of the program.
+/// * `base` - the address at which the program is to be loaded.
+pub fn verify(program: &[u8], base: u32) -> Result<(), Vec<VerifyError>> {
+    let len = program.len() as u32;
+    let mut problems = Vec::new();
+    let mut boundaries = vec![false; program.len()];
+    let mut targets = Vec::new();
+
+    let mut offset = 0;
+    while offset < len {
+        let address = match base.checked_add(offset) {
+            Some(address) => address,
+            None => {
+                problems.push(VerifyError::OutOfRange { offset });
+                break;
+            }
+        };
+        if len - offset < OPCODE_SIZE {
+            problems.push(VerifyError::TruncatedInstruction { address });
+            break;
+        }
+
+        let value = i16::from_le_bytes([program[offset as usize], program[offset as usize + 1]]);
+        let opcode = match OpCode::from_i16(value) {
+            Some(opcode) => opcode,
+            None => {
+                problems.push(VerifyError::InvalidOpCode {
+                    address,
+                    opcode: value,
+                });
+                break;
+            }
+        };
+        let operands = match operands(opcode) {
+            Some(operands) => operands,
+            None => {
+                problems.push(VerifyError::UnsupportedOpCode { address, opcode });
+                break;
+            }
+        };
+
+        let size = OPCODE_SIZE + operands.iter().map(|o| o.size()).sum::<u32>();
+        if len - offset < size {
+            problems.push(VerifyError::TruncatedInstruction { address });
+            break;
+        }
+
+        let mut position = (offset + OPCODE_SIZE) as usize;
+        for operand in operands {
+            match operand {
+                Operand::Register => {
+                    let register = program[position];
+                    if Registers::from_u8(register).is_none() {
+                        problems.push(VerifyError::InvalidRegister { address, register });
+                    }
+                }
+                Operand::Target => {
+                    let mut bytes = [0u8; 4];
+                    bytes.copy_from_slice(&program[position..position + 4]);
+                    targets.push((address, u32::from_le_bytes(bytes)));
+                }
+                _ => {}
+            }
+            position += operand.size() as usize;
+        }
+
+        boundaries[offset as usize] = true;
+        offset += size;
+    }
+
+    for (address, target) in targets {
+        let valid = match target.checked_sub(base) {
+            Some(t) if t < len => {
+                // Nothing is known about the instructions beyond the end of the sweep.
+                t >= offset || boundaries[t as usize]
+            }
+            _ => false,
+        };
+
+        if !valid {
+            problems.push(VerifyError::InvalidTarget { address, target });
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    problems.sort_by_key(|p| p.address());
+    Err(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::enums::Instruction;
+
+    fn assemble(instructions: &[Instruction]) -> Vec<u8> {
+        instructions.iter().flat_map(|i| i.encode()).collect()
+    }
+
+    #[test]
+    fn valid_programs_pass() {
+        let program = assemble(&[
+            Instruction::MovLitReg(0, Registers::R1),
+            Instruction::AddLitReg(1, Registers::R1),
+            Instruction::MovRegReg(Registers::AC, Registers::R1),
+            Instruction::CalLit(0x1024),
+            Instruction::JmpNotEq(10, 0x1007),
+            Instruction::HLT(),
+            Instruction::Out(Registers::R1, 1),
+            Instruction::Ret(),
+        ]);
+
+        assert_eq!(verify(&program, 0x1000), Ok(()));
+        assert_eq!(verify(&[], 0), Ok(()));
+    }
+
+    #[test]
+    fn every_problem_is_reported() {
+        let mut program = assemble(&[
+            Instruction::JeqLit(0, 1),
+            Instruction::MovRegReg(Registers::R1, Registers::R2),
+            Instruction::CalLit(100),
+            Instruction::Pop(Registers::R1),
+            Instruction::JneReg(Registers::R1, 10),
+        ]);
+        // Corrupt the destination register of the move.
+        program[13] = 0xFF;
+        // Leave the final jump without the last byte of its target.
+        program.pop();
+
+        assert_eq!(
+            verify(&program, 0),
+            Err(vec![
+                VerifyError::InvalidTarget {
+                    address: 0,
+                    target: 1
+                },
+                VerifyError::InvalidRegister {
+                    address: 10,
+                    register: 0xFF
+                },
+                VerifyError::InvalidTarget {
+                    address: 14,
+                    target: 100
+                },
+                VerifyError::TruncatedInstruction { address: 23 },
+            ])
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_end_the_sweep() {
+        let mut program = assemble(&[
+            Instruction::JeqLit(0, 14),
+            Instruction::NOP(),
+            Instruction::HLT(),
+            Instruction::NOP(),
+        ]);
+        program.splice(12..12, (-1i16).to_le_bytes().iter().cloned());
+
+        assert_eq!(
+            verify(&program, 0),
+            Err(vec![VerifyError::UnsupportedOpCode {
+                address: 12,
+                opcode: OpCode::Label
+            }])
+        );
+
+        program[12] = 0x34;
+        program[13] = 0x12;
+        assert_eq!(
+            verify(&program, 0),
+            Err(vec![VerifyError::InvalidOpCode {
+                address: 12,
+                opcode: 0x1234
+            }])
+        );
+    }
+
+    #[test]
+    fn programs_beyond_the_address_space_are_rejected() {
+        let program = assemble(&[Instruction::NOP(), Instruction::HLT()]);
+
+        assert_eq!(verify(&program, u32::MAX - 3), Ok(()));
+        assert_eq!(
+            verify(&program, u32::MAX - 1),
+            Err(vec![VerifyError::OutOfRange { offset: 2 }])
+        );
+    }
+}
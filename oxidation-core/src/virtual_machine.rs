@@ -1,17 +1,48 @@
+use crate::coverage::Coverage;
 use crate::cpu::*;
+use crate::debug_info::DebugInfo;
+use crate::devices::Device;
+use crate::executable::{self, Executable, LoadExecutable, SectionKind};
+use crate::execution::{RunOutcome, StepOutcome};
+use crate::fault::VmFault;
+use crate::journal::{Journal, ReverseOutcome};
 use crate::memory::*;
+use crate::profiler::Profiler;
+use crate::replay::InputLog;
+use crate::security_context::SecurityContext;
+use crate::snapshot::{self, SnapshotIo, SnapshotReader, SnapshotWriter};
+use crate::tracer::Tracer;
+use crate::watchpoints::{Watchpoint, Watchpoints};
 use log::trace;
+use snafu::ResultExt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+type Result<T, E = CpuError> = std::result::Result<T, E>;
 
 pub struct VirtualMachine {
     pub cpu: CPU,
     pub memory: Memory,
+    journal: Option<Journal>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    watchpoints: Watchpoints,
+    debug_info: Option<DebugInfo>,
 }
 
 impl VirtualMachine {
-    pub fn new(memory_size: u32, stack_capacity: u32, cpu_can_swap_regions: bool) -> Self {
+    pub fn new(memory_size: u32, stack_capacity: u32, _cpu_can_swap_regions: bool) -> Self {
         let mut v = Self {
             cpu: CPU::new(),
             memory: Memory::new(memory_size, stack_capacity),
+            journal: None,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            watchpoints: Watchpoints::new(),
+            debug_info: None,
         };
         v.initialize();
         v
@@ -23,25 +54,1013 @@ impl VirtualMachine {
         //trace!("{:?}", std::any::type_name::<crate::registers::Registers>());
     }
 
-    pub fn run(&mut self) {
+    /// Load a program into memory and mark it as the executable region.
+    /// Returns the sequence ID of the executable memory region.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - the bytes of the program.
+    /// * `address` - the address at which the program should be loaded.
+    pub fn load_program(&mut self, program: &[u8], address: u32) -> Result<u32> {
+        self.memory
+            .write_bytes(address, program, SecurityContext::System)
+            .context(MemoryFault)?;
+
+        let end = address + (program.len() as u32).max(1) - 1;
+        let seq_id = self.memory.add_memory_region(
+            address,
+            end,
+            MemoryAccess::R | MemoryAccess::PW,
+            "Executable".to_string(),
+        );
+
+        self.cpu.set_executable_region(&self.memory, seq_id)?;
+
+        Ok(seq_id)
+    }
+
+    /// Load the sections of an executable into memory, each within a region of its own,
+    /// and begin execution from its entry point.
+    /// Returns the sequence ID of the executable memory region.
+    ///
+    /// # Arguments
+    ///
+    /// * `executable` - the executable to be loaded.
+    pub fn load_executable(&mut self, executable: &Executable) -> executable::Result<u32> {
+        executable.validate()?;
+
+        let mut code_seq_id = None;
+        for section in &executable.sections {
+            let zeros;
+            let bytes = match section.kind {
+                SectionKind::Zero => {
+                    zeros = vec![0u8; section.size as usize];
+                    &zeros
+                }
+                _ => &section.bytes,
+            };
+            self.memory
+                .write_bytes(section.address, bytes, SecurityContext::System)
+                .context(MemoryFault)
+                .context(LoadExecutable)?;
+
+            let seq_id = self.memory.add_memory_region(
+                section.address,
+                section.end(),
+                section.access,
+                section.region_name().to_string(),
+            );
+            if section.kind == SectionKind::Code {
+                code_seq_id = Some(seq_id);
+            }
+        }
+
+        self.debug_info = executable.debug_info.clone();
+
+        // A valid executable always holds exactly one code section.
+        let seq_id = code_seq_id.unwrap();
+        self.cpu
+            .set_executable_region(&self.memory, seq_id)
+            .context(LoadExecutable)?;
+        self.cpu
+            .set_instruction_pointer(executable.entry_point)
+            .context(LoadExecutable)?;
+
+        Ok(seq_id)
+    }
+
+    /// Set the debug information of the loaded program, which is used to describe
+    /// the locations of faults.
+    ///
+    /// # Arguments
+    ///
+    /// * `debug_info` - the debug information, or `None` to discard it.
+    pub fn set_debug_info(&mut self, debug_info: Option<DebugInfo>) {
+        self.debug_info = debug_info;
+    }
+
+    /// Returns the debug information of the loaded program, if any.
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Capture a report of a fault, describing its location in the source of the
+    /// program if debug information is available.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - the error that caused the fault.
+    pub fn capture_fault(&self, error: &CpuError) -> VmFault {
+        let mut fault = VmFault::capture(&self.cpu, &self.memory, error);
+        let code = self
+            .cpu
+            .get_executable_region_id()
+            .and_then(|id| self.memory.get_region_by_seq_id(id).ok())
+            .map(|r| r.start..=r.end);
+        fault.location = match (&self.debug_info, code) {
+            (Some(info), Some(code)) => Some(Box::new(info.locate(fault.address, &code))),
+            _ => None,
+        };
+        fault
+    }
+
+    /// Attach a device to an I/O port, returning any device previously attached to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - the port to which the device should be attached.
+    /// * `device` - the device.
+    pub fn attach_device(&mut self, port: u8, device: Box<dyn Device>) -> Option<Box<dyn Device>> {
+        self.cpu.io.attach(port, device)
+    }
+
+    /// Begin recording every non-deterministic input supplied to the virtual machine.
+    pub fn start_recording(&mut self) {
+        let start = self.cpu.instructions_executed();
+        self.cpu.io.start_recording(start);
+    }
+
+    /// Stop recording, returning the recorded log of inputs.
+    pub fn stop_recording(&mut self) -> Option<InputLog> {
+        self.cpu.io.stop()
+    }
+
+    /// Begin supplying the non-deterministic inputs from a previously recorded log.
+    /// The virtual machine must be in the same state as it was when the recording began.
+    ///
+    /// # Arguments
+    ///
+    /// * `log` - the recorded log.
+    pub fn start_replay(&mut self, log: InputLog) -> Result<()> {
+        let instruction = self.cpu.instructions_executed();
+        if log.start_instruction != instruction {
+            return Err(CpuError::ReplayDivergence {
+                instruction,
+                address: self.cpu.get_instruction_pointer()?,
+                reason: format!(
+                    "the recording began at instruction {}",
+                    log.start_instruction
+                ),
+            });
+        }
+
+        self.cpu.io.start_replay(log);
+        Ok(())
+    }
+
+    /// Stop replaying, verifying that every recorded input was consumed.
+    pub fn finish_replay(&mut self) -> Result<()> {
+        let pending = self.cpu.io.pending_replay_event().copied();
+        self.cpu.io.stop();
+
+        match pending {
+            Some(event) => Err(CpuError::ReplayDivergence {
+                instruction: event.instruction,
+                address: event.address,
+                reason: format!("the recorded input {:?} was never consumed", event.kind),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Save the complete state of the virtual machine to a snapshot file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to the snapshot file.
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> snapshot::Result<()> {
+        let file = File::create(path).context(SnapshotIo)?;
+        let mut writer = BufWriter::new(file);
+        self.write_snapshot(&mut writer)?;
+        writer.flush().context(SnapshotIo)
+    }
+
+    /// Create a virtual machine from the state held within a snapshot file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - the path to the snapshot file.
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> snapshot::Result<Self> {
+        let file = File::open(path).context(SnapshotIo)?;
+        Self::read_snapshot(&mut BufReader::new(file))
+    }
+
+    /// Write the complete state of the virtual machine as a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - the destination of the snapshot.
+    pub fn write_snapshot(&self, writer: &mut dyn Write) -> snapshot::Result<()> {
+        let mut writer = SnapshotWriter::new(writer);
+        writer.write_header()?;
+        self.cpu.write_snapshot(&mut writer)?;
+        self.memory.write_snapshot(&mut writer)
+    }
+
+    /// Create a virtual machine from the state held within a snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - the source of the snapshot.
+    pub fn read_snapshot(reader: &mut dyn Read) -> snapshot::Result<Self> {
+        let mut reader = SnapshotReader::new(reader);
+        reader.read_header()?;
+
+        let mut vm = Self {
+            cpu: CPU::read_snapshot(&mut reader)?,
+            memory: Memory::read_snapshot(&mut reader)?,
+            journal: None,
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            watchpoints: Watchpoints::new(),
+            debug_info: None,
+        };
+        vm.initialize();
+
+        Ok(vm)
+    }
+
+    /// Run the virtual machine until the program is complete or a watchpoint is triggered,
+    /// returning the reason it stopped, or a report of the fault if the program could not
+    /// be run to completion.
+    pub fn run(&mut self) -> Result<RunOutcome, VmFault> {
         trace!("Currently in VirtualMachine::run");
 
-        //println!("{:#?}", self.cpu.registers);
+        match self.run_unobserved(None) {
+            RunOutcome::Fault(e) => Err(self.capture_fault(&e)),
+            outcome => Ok(outcome),
+        }
+    }
 
-        // TODO - handle errors a bit better here.
-        if let Err(e) = self.cpu.run() {
-            println!("{}", e);
-        } else {
-            println!("successfully ran the CPU to completion.");
+    /// Run the virtual machine until the program is complete or the cycle budget is exhausted.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - the maximum number of cycles that may be consumed.
+    pub fn run_for(&mut self, budget: u64) -> RunOutcome {
+        trace!("Currently in VirtualMachine::run_for");
+
+        self.run_unobserved(Some(budget))
+    }
+
+    /// Run the virtual machine until the program is complete or the predicate is satisfied.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - the stop predicate, see [`CPU::run_until`].
+    pub fn run_until<F>(&mut self, predicate: F) -> RunOutcome
+    where
+        F: FnMut(&CPU, &Memory) -> bool,
+    {
+        trace!("Currently in VirtualMachine::run_until");
+
+        self.run_observed(None, predicate)
+    }
+
+    /// Run the virtual machine until the program is complete, the cycle budget is exhausted
+    /// or the predicate is satisfied.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - the maximum number of cycles that may be consumed.
+    /// * `predicate` - the stop predicate, see [`CPU::run_until`].
+    pub fn run_for_until<F>(&mut self, budget: u64, predicate: F) -> RunOutcome
+    where
+        F: FnMut(&CPU, &Memory) -> bool,
+    {
+        trace!("Currently in VirtualMachine::run_for_until");
+
+        self.run_observed(Some(budget), predicate)
+    }
+
+    /// Execute exactly one instruction, returning a description of its effects,
+    /// including any watchpoint it triggered.
+    pub fn step(&mut self) -> StepOutcome {
+        trace!("Currently in VirtualMachine::step");
+
+        let mut outcome = self.cpu.step(&mut self.memory);
+        outcome.watchpoint = self.watchpoints.check(&outcome);
+        if let Some(journal) = &mut self.journal {
+            journal.record(&self.cpu, &outcome);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace(&self.cpu, &outcome);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(&self.cpu, &outcome);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(&self.cpu, &outcome);
+        }
+
+        outcome
+    }
+
+    /// Begin journaling the state changes made by each executed instruction,
+    /// allowing execution to be reversed. Any existing journal is discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - the maximum number of instructions that may be reversed.
+    pub fn enable_journal(&mut self, limit: usize) {
+        self.journal = Some(Journal::new(limit));
+    }
+
+    /// Stop journaling and discard the journal.
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Returns a reference to the journal, if journaling is enabled.
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Attach a tracer that records every subsequently executed instruction,
+    /// returning any tracer that was previously attached.
+    ///
+    /// # Arguments
+    ///
+    /// * `tracer` - the tracer.
+    pub fn attach_tracer(&mut self, tracer: Tracer) -> Option<Tracer> {
+        self.tracer.replace(tracer)
+    }
+
+    /// Detach the tracer, if any. The tracer should be finished to flush its output.
+    pub fn detach_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Attach a profiler that records every subsequently executed instruction,
+    /// returning any profiler that was previously attached.
+    ///
+    /// # Arguments
+    ///
+    /// * `profiler` - the profiler.
+    pub fn attach_profiler(&mut self, profiler: Profiler) -> Option<Profiler> {
+        self.profiler.replace(profiler)
+    }
+
+    /// Detach the profiler, if any.
+    pub fn detach_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Returns a reference to the profiler, if one is attached.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Begin recording the coverage of every subsequently executed instruction,
+    /// returning any coverage that was previously being recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `coverage` - the coverage.
+    pub fn attach_coverage(&mut self, coverage: Coverage) -> Option<Coverage> {
+        self.coverage.replace(coverage)
+    }
+
+    /// Stop recording coverage, returning the coverage recorded so far, if any.
+    pub fn detach_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Returns a reference to the coverage being recorded, if any.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Add a watchpoint, returning its identifier. Runs stop with
+    /// [`RunOutcome::Watchpoint`] once an instruction triggers the watchpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `watchpoint` - the watchpoint.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> u32 {
+        self.watchpoints.add(watchpoint)
+    }
+
+    /// Remove a watchpoint, returning it if it existed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the identifier of the watchpoint.
+    pub fn remove_watchpoint(&mut self, id: u32) -> Option<Watchpoint> {
+        self.watchpoints.remove(id)
+    }
+
+    /// Returns a reference to the watchpoints.
+    pub fn watchpoints(&self) -> &Watchpoints {
+        &self.watchpoints
+    }
+
+    /// Undo the most recently executed instruction, returning true if an instruction was undone.
+    ///
+    /// Inputs read from devices are not returned to them.
+    pub fn step_back(&mut self) -> Result<bool> {
+        trace!("Currently in VirtualMachine::step_back");
+
+        match &mut self.journal {
+            Some(journal) => journal.step_back(&mut self.cpu, &mut self.memory),
+            None => Ok(false),
+        }
+    }
+
+    /// Undo executed instructions until the predicate is satisfied or the journal is exhausted.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - the stop predicate, evaluated after each instruction is undone.
+    pub fn run_backwards_until<F>(&mut self, mut predicate: F) -> Result<ReverseOutcome>
+    where
+        F: FnMut(&CPU, &Memory) -> bool,
+    {
+        trace!("Currently in VirtualMachine::run_backwards_until");
+
+        while self.step_back()? {
+            if predicate(&self.cpu, &self.memory) {
+                return Ok(ReverseOutcome::Breakpoint);
+            }
         }
+
+        Ok(ReverseOutcome::JournalExhausted)
+    }
+
+    /// Run the virtual machine until the program is complete or the cycle budget is
+    /// exhausted, executing compiled code where possible if nothing is attached that
+    /// must observe each step.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - the maximum number of cycles that may be consumed, if any.
+    fn run_unobserved(&mut self, budget: Option<u64>) -> RunOutcome {
+        #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+        {
+            let observed = self.journal.is_some()
+                || self.tracer.is_some()
+                || self.profiler.is_some()
+                || self.coverage.is_some()
+                || !self.watchpoints.is_empty();
+            if !observed && self.cpu.jit.is_enabled() {
+                return self.cpu.run_compiled(&mut self.memory, budget);
+            }
+        }
+
+        self.run_observed(budget, |_, _| false)
+    }
+
+    fn run_observed<F>(&mut self, budget: Option<u64>, predicate: F) -> RunOutcome
+    where
+        F: FnMut(&CPU, &Memory) -> bool,
+    {
+        let journal = &mut self.journal;
+        let tracer = &mut self.tracer;
+        let profiler = &mut self.profiler;
+        let coverage = &mut self.coverage;
+        let watchpoints = &self.watchpoints;
+
+        self.cpu
+            .run_with(&mut self.memory, budget, predicate, |cpu, _, outcome| {
+                if let Some(journal) = journal {
+                    journal.record(cpu, outcome);
+                }
+                if let Some(tracer) = tracer {
+                    tracer.trace(cpu, outcome);
+                }
+                if let Some(profiler) = profiler {
+                    profiler.record(cpu, outcome);
+                }
+                if let Some(coverage) = coverage {
+                    coverage.record(cpu, outcome);
+                }
+
+                watchpoints.check(outcome).map(RunOutcome::Watchpoint)
+            })
     }
 
     pub fn run_test(&mut self) {
         trace!("Currently in VirtualMachine::run_test");
-        if let Err(e) = self.cpu.run_test() {
+        if let Err(e) = self.cpu.run_test(&mut self.memory) {
             println!("{}", e);
         } else {
             println!("successfully ran the CPU to completion.");
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instructions::enums::Instruction;
+    use crate::registers::{RegisterValue, RegisterWrite, Registers};
+
+    fn build_vm(instructions: &[Instruction]) -> VirtualMachine {
+        let program: Vec<u8> = instructions.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&program, 0).unwrap();
+        vm
+    }
+
+    #[test]
+    fn run_for_stops_when_budget_exhausted() {
+        let mut vm = build_vm(&[
+            Instruction::NOP(),
+            Instruction::AddLitReg(5, Registers::R1),
+            Instruction::NOP(),
+            Instruction::HLT(),
+        ]);
+
+        // NOP (1) + AddLitReg (2) fits, the second NOP does not.
+        assert!(matches!(vm.run_for(3), RunOutcome::BudgetExhausted));
+        assert_eq!(vm.cpu.cycles(), 3);
+        assert_eq!(vm.cpu.instructions_executed(), 2);
+        assert_eq!(vm.cpu.get_instruction_pointer().unwrap(), 9);
+
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+        assert_eq!(vm.cpu.cycles(), 5);
+        assert!(vm.cpu.is_halted());
+
+        let ac = vm
+            .cpu
+            .registers
+            .get_register_value(Registers::AC, SecurityContext::User)
+            .unwrap();
+        assert_eq!(ac, RegisterValue::I32(5));
+    }
+
+    #[test]
+    fn run_until_stops_at_predicate() {
+        let mut vm = build_vm(&[
+            Instruction::NOP(),
+            Instruction::NOP(),
+            Instruction::NOP(),
+            Instruction::HLT(),
+        ]);
+
+        let outcome = vm.run_until(|cpu, _| cpu.get_instruction_pointer().unwrap() == 4);
+        assert!(matches!(outcome, RunOutcome::Breakpoint));
+        assert_eq!(vm.cpu.instructions_executed(), 2);
+
+        // Resuming from the breakpoint must make progress.
+        let outcome = vm.run_until(|cpu, _| cpu.get_instruction_pointer().unwrap() == 4);
+        assert!(matches!(outcome, RunOutcome::Halted));
+    }
+
+    #[test]
+    fn invalid_programs_fault() {
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&[0x34, 0x12], 0).unwrap();
+        assert!(matches!(
+            vm.run_for(10),
+            RunOutcome::Fault(CpuError::InvalidOpCode { opcode: 0x1234 })
+        ));
+
+        // Running off of the end of the executable region.
+        let mut vm = build_vm(&[Instruction::NOP()]);
+        assert!(matches!(
+            vm.run_for(10),
+            RunOutcome::Fault(CpuError::InstructionPointerOutOfBounds { address: 2 })
+        ));
+    }
+
+    #[test]
+    fn step_reports_instruction_effects() {
+        let mut vm = build_vm(&[
+            Instruction::MovLitReg(0x1234, Registers::R2),
+            Instruction::MovRegMem(Registers::R2, 500),
+            Instruction::MovMemReg(500, Registers::R3),
+            Instruction::HLT(),
+        ]);
+
+        let outcome = vm.step();
+        assert_eq!(outcome.address, 0);
+        assert_eq!(
+            outcome.instruction,
+            Some(Instruction::MovLitReg(0x1234, Registers::R2))
+        );
+        assert_eq!(
+            outcome.register_writes,
+            vec![RegisterWrite {
+                register_id: Registers::R2,
+                old_value: RegisterValue::I32(0),
+                new_value: RegisterValue::I32(0x1234),
+            }]
+        );
+        assert!(outcome.memory_writes.is_empty());
+        assert!(!outcome.halted);
+        assert!(outcome.fault.is_none());
+
+        let outcome = vm.step();
+        assert_eq!(outcome.address, 7);
+        assert!(outcome.register_writes.is_empty());
+        assert_eq!(
+            outcome.memory_writes,
+            vec![MemoryWrite {
+                address: 500,
+                old_bytes: vec![0, 0, 0, 0],
+                new_bytes: vec![0x34, 0x12, 0, 0],
+            }]
+        );
+
+        let outcome = vm.step();
+        assert_eq!(
+            outcome.register_writes[0].new_value,
+            RegisterValue::I32(0x1234)
+        );
+
+        let outcome = vm.step();
+        assert_eq!(outcome.instruction, Some(Instruction::HLT()));
+        assert!(outcome.halted);
+
+        // Stepping a halted CPU does nothing.
+        let outcome = vm.step();
+        assert!(outcome.instruction.is_none());
+        assert!(outcome.halted);
+        assert_eq!(vm.cpu.instructions_executed(), 4);
+    }
+
+    #[test]
+    fn step_reports_faults() {
+        let mut vm = build_vm(&[Instruction::MovRegMem(Registers::R1, 1_000_000)]);
+
+        let outcome = vm.step();
+        assert_eq!(outcome.address, 0);
+        assert!(outcome.halted);
+        assert!(matches!(outcome.fault, Some(CpuError::MemoryFault { .. })));
+        assert_eq!(vm.cpu.get_instruction_pointer().unwrap(), 0);
+        assert_eq!(vm.cpu.instructions_executed(), 0);
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut vm = build_vm(&[
+            Instruction::MovLitReg(77, Registers::R4),
+            Instruction::MovRegMem(Registers::R4, 600),
+            Instruction::AddLitReg(1, Registers::R4),
+            Instruction::HLT(),
+        ]);
+        vm.step();
+        vm.step();
+
+        let path = std::env::temp_dir().join(format!("oxidation-{}.snapshot", std::process::id()));
+        vm.save_snapshot(&path).unwrap();
+        let mut restored = VirtualMachine::load_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.cpu.get_instruction_pointer().unwrap(), 14);
+        assert_eq!(restored.cpu.instructions_executed(), 2);
+        assert_eq!(restored.cpu.cycles(), vm.cpu.cycles());
+        assert_eq!(
+            restored
+                .memory
+                .read_i32(600, SecurityContext::User)
+                .unwrap(),
+            77
+        );
+        assert_eq!(
+            restored.memory.get_region_for_address(0).unwrap(),
+            vm.memory.get_region_for_address(0).unwrap()
+        );
+        assert_eq!(
+            restored.cpu.registers.get_register_ref(Registers::IP),
+            vm.cpu.registers.get_register_ref(Registers::IP)
+        );
+
+        // The restored machine continues from where the original stopped.
+        assert!(matches!(restored.run_for(100), RunOutcome::Halted));
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+
+        let mut original = Vec::new();
+        let mut copy = Vec::new();
+        vm.write_snapshot(&mut original).unwrap();
+        restored.write_snapshot(&mut copy).unwrap();
+        assert_eq!(original, copy);
+    }
+
+    #[test]
+    fn snapshot_rejects_invalid_headers() {
+        let vm = VirtualMachine::new(100, 1, false);
+        let mut bytes = Vec::new();
+        vm.write_snapshot(&mut bytes).unwrap();
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(matches!(
+            VirtualMachine::read_snapshot(&mut bad_magic.as_slice()),
+            Err(snapshot::SnapshotError::InvalidSnapshotMagic)
+        ));
+
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 99;
+        assert!(matches!(
+            VirtualMachine::read_snapshot(&mut bad_version.as_slice()),
+            Err(snapshot::SnapshotError::UnsupportedSnapshotVersion { version: 99 })
+        ));
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(matches!(
+            VirtualMachine::read_snapshot(&mut bytes.as_slice()),
+            Err(snapshot::SnapshotError::SnapshotIo { .. })
+        ));
+    }
+
+    #[test]
+    fn snapshot_rejects_invalid_registers() {
+        let registers = RegisterCollection::new();
+        let mut bytes = Vec::new();
+        registers
+            .write_snapshot(&mut SnapshotWriter::new(&mut bytes))
+            .unwrap();
+
+        // Each register is recorded as its ID, access flags and a tagged 32-bit value.
+        let mut duplicate = bytes.clone();
+        duplicate[4 + 7] = Registers::R1 as u8;
+        assert!(matches!(
+            RegisterCollection::read_snapshot(&mut SnapshotReader::new(&mut duplicate.as_slice())),
+            Err(snapshot::SnapshotError::InvalidSnapshotData { .. })
+        ));
+
+        let mut missing = bytes.clone();
+        missing[0] -= 1;
+        assert!(matches!(
+            RegisterCollection::read_snapshot(&mut SnapshotReader::new(&mut missing.as_slice())),
+            Err(snapshot::SnapshotError::InvalidSnapshotData { .. })
+        ));
+
+        let restored =
+            RegisterCollection::read_snapshot(&mut SnapshotReader::new(&mut bytes.as_slice()))
+                .unwrap();
+        assert_eq!(restored.registers, registers.registers);
+    }
+
+    /// A device that supplies a different value every time it is read.
+    struct Counter(i32);
+
+    impl Device for Counter {
+        fn read(&mut self) -> i32 {
+            self.0 += 17;
+            self.0
+        }
+
+        fn write(&mut self, _value: i32) {}
+    }
+
+    fn io_program() -> Vec<Instruction> {
+        vec![
+            Instruction::In(1, Registers::R1),
+            Instruction::NOP(),
+            Instruction::In(1, Registers::R2),
+            Instruction::Out(Registers::R2, 2),
+            Instruction::HLT(),
+        ]
+    }
+
+    fn register(vm: &VirtualMachine, reg: Registers) -> RegisterValue {
+        vm.cpu
+            .registers
+            .get_register_value(reg, SecurityContext::User)
+            .unwrap()
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_inputs() {
+        let mut vm = build_vm(&io_program());
+        vm.attach_device(1, Box::new(Counter(0)));
+        vm.attach_device(2, Box::new(Counter(0)));
+        vm.start_recording();
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+
+        let log = vm.stop_recording().unwrap();
+        assert_eq!(log.events.len(), 2);
+        assert_eq!(log.events[1].instruction, 2);
+        assert_eq!(log.events[1].address, 6);
+
+        let path = std::env::temp_dir().join(format!("oxidation-{}.replay", std::process::id()));
+        log.save(&path).unwrap();
+        let log = InputLog::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // No devices are needed when replaying.
+        let mut replay = build_vm(&io_program());
+        replay.start_replay(log).unwrap();
+        assert!(matches!(replay.run_for(100), RunOutcome::Halted));
+        replay.finish_replay().unwrap();
+
+        assert_eq!(register(&replay, Registers::R1), RegisterValue::I32(17));
+        assert_eq!(register(&replay, Registers::R2), RegisterValue::I32(34));
+        assert_eq!(
+            register(&vm, Registers::R2),
+            register(&replay, Registers::R2)
+        );
+    }
+
+    #[test]
+    fn replay_detects_divergence() {
+        let mut vm = build_vm(&io_program());
+        vm.attach_device(1, Box::new(Counter(0)));
+        vm.attach_device(2, Box::new(Counter(0)));
+        vm.start_recording();
+        vm.run_for(100);
+        let log = vm.stop_recording().unwrap();
+
+        // The second read now happens one instruction earlier.
+        let mut program = io_program();
+        program.remove(1);
+        let mut replay = build_vm(&program);
+        replay.start_replay(log.clone()).unwrap();
+
+        match replay.run_for(100) {
+            RunOutcome::Fault(CpuError::ReplayDivergence {
+                instruction,
+                address,
+                ..
+            }) => {
+                assert_eq!(instruction, 1);
+                assert_eq!(address, 4);
+            }
+            o => panic!("unexpected outcome {:?}", o),
+        }
+
+        // The second read now happens at the same count, but from a different instruction.
+        let mut program = io_program();
+        program[1] = Instruction::MovLitReg(0, Registers::R3);
+        let mut replay = build_vm(&program);
+        replay.start_replay(log.clone()).unwrap();
+
+        match replay.run_for(100) {
+            RunOutcome::Fault(CpuError::ReplayDivergence {
+                instruction,
+                address,
+                ..
+            }) => {
+                assert_eq!(instruction, 2);
+                assert_eq!(address, 11);
+            }
+            o => panic!("unexpected outcome {:?}", o),
+        }
+
+        // A recorded input that is never consumed is also a divergence.
+        let mut program = io_program();
+        program.truncate(2);
+        program.push(Instruction::HLT());
+        let mut replay = build_vm(&program);
+        replay.start_replay(log).unwrap();
+        assert!(matches!(replay.run_for(100), RunOutcome::Halted));
+        assert!(matches!(
+            replay.finish_replay(),
+            Err(CpuError::ReplayDivergence { instruction: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn step_back_restores_previous_state() {
+        let mut vm = build_vm(&[
+            Instruction::MovLitReg(10, Registers::R1),
+            Instruction::MovRegMem(Registers::R1, 700),
+            Instruction::MovLitReg(20, Registers::R1),
+            Instruction::MovRegMem(Registers::R1, 700),
+            Instruction::HLT(),
+        ]);
+        vm.enable_journal(100);
+
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+        assert_eq!(vm.journal().unwrap().len(), 5);
+
+        // Undo the halt and the final write.
+        assert!(vm.step_back().unwrap());
+        assert!(!vm.cpu.is_halted());
+        assert!(vm.step_back().unwrap());
+        assert_eq!(vm.memory.read_i32(700, SecurityContext::User).unwrap(), 10);
+        assert_eq!(register(&vm, Registers::R1), RegisterValue::I32(20));
+        assert_eq!(vm.cpu.get_instruction_pointer().unwrap(), 21);
+        assert_eq!(vm.cpu.instructions_executed(), 3);
+
+        let outcome = vm
+            .run_backwards_until(|cpu, _| cpu.get_instruction_pointer().unwrap() == 7)
+            .unwrap();
+        assert_eq!(outcome, ReverseOutcome::Breakpoint);
+        assert_eq!(register(&vm, Registers::R1), RegisterValue::I32(10));
+        assert_eq!(vm.memory.read_i32(700, SecurityContext::User).unwrap(), 0);
+
+        let outcome = vm.run_backwards_until(|_, _| false).unwrap();
+        assert_eq!(outcome, ReverseOutcome::JournalExhausted);
+        assert_eq!(register(&vm, Registers::R1), RegisterValue::I32(0));
+        assert_eq!(vm.cpu.get_instruction_pointer().unwrap(), 0);
+        assert_eq!(vm.cpu.cycles(), 0);
+
+        // Execution proceeds identically after being reversed.
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+        assert_eq!(vm.memory.read_i32(700, SecurityContext::User).unwrap(), 20);
+    }
+
+    #[test]
+    fn journal_respects_size_limit() {
+        let mut vm = build_vm(&[
+            Instruction::MovLitReg(1, Registers::R1),
+            Instruction::MovLitReg(2, Registers::R1),
+            Instruction::MovLitReg(3, Registers::R1),
+            Instruction::HLT(),
+        ]);
+        vm.enable_journal(2);
+        vm.step();
+        vm.step();
+        vm.step();
+
+        assert_eq!(vm.journal().unwrap().len(), 2);
+        assert_eq!(
+            vm.run_backwards_until(|_, _| false).unwrap(),
+            ReverseOutcome::JournalExhausted
+        );
+        assert_eq!(register(&vm, Registers::R1), RegisterValue::I32(1));
+
+        vm.disable_journal();
+        assert!(!vm.step_back().unwrap());
+    }
+
+    #[test]
+    fn run_without_program_faults() {
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        assert!(matches!(
+            vm.run_for(10),
+            RunOutcome::Fault(CpuError::MemorySequenceIdNotSet)
+        ));
+    }
+
+    #[test]
+    fn stack_and_subroutines() {
+        let mut vm = build_vm(&[
+            Instruction::PshLit(7),
+            Instruction::CalLit(17),
+            Instruction::Pop(Registers::R2),
+            Instruction::HLT(),
+            // A subroutine at 17.
+            Instruction::MovLitReg(3, Registers::R1),
+            Instruction::Ret(),
+        ]);
+        let top = vm.memory.stack_end();
+
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+        assert_eq!(register(&vm, Registers::R1), RegisterValue::I32(3));
+        assert_eq!(register(&vm, Registers::R2), RegisterValue::I32(7));
+        assert_eq!(vm.cpu.get_stack_pointer().unwrap(), top);
+
+        // The stack holds ten values.
+        let mut program = vec![Instruction::PshLit(1); 11];
+        program.push(Instruction::HLT());
+        let mut vm = build_vm(&program);
+        assert!(matches!(
+            vm.run_for(100),
+            RunOutcome::Fault(CpuError::StackOverflow { .. })
+        ));
+        assert_eq!(vm.cpu.get_stack_pointer().unwrap(), vm.memory.stack_start());
+
+        let mut vm = build_vm(&[Instruction::Ret()]);
+        assert!(matches!(
+            vm.run_for(100),
+            RunOutcome::Fault(CpuError::StackUnderflow)
+        ));
+    }
+
+    #[test]
+    fn executables_load_each_section() {
+        use crate::executable::Section;
+
+        let code: Vec<u8> = [
+            Instruction::NOP(),
+            Instruction::MovMemReg(0x200, Registers::R1),
+            Instruction::MovRegMem(Registers::R1, 0x300),
+            Instruction::HLT(),
+        ]
+        .iter()
+        .flat_map(|i| i.encode())
+        .collect();
+
+        let mut executable = Executable::new(Section::code(0x100, code));
+        executable.entry_point = 0x102;
+        executable.sections.push(Section::data(
+            0x200,
+            7i32.to_le_bytes().to_vec(),
+            MemoryAccess::R,
+        ));
+        executable
+            .sections
+            .push(Section::zero(0x300, 8, MemoryAccess::R | MemoryAccess::W));
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_executable(&executable).unwrap();
+        assert_eq!(vm.cpu.get_instruction_pointer().unwrap(), 0x102);
+
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+        assert_eq!(vm.cpu.instructions_executed(), 3);
+        assert_eq!(vm.memory.read_i32(0x300, SecurityContext::User).unwrap(), 7);
+
+        // The data section was loaded as read-only.
+        assert!(vm
+            .memory
+            .write_bytes(0x200, &[0], SecurityContext::User)
+            .is_err());
+    }
+}
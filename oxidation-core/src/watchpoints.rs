@@ -0,0 +1,383 @@
+//! Watchpoints on memory and registers.
+//!
+//! A watchpoint is checked against the effects of each executed instruction, allowing
+//! execution to be stopped when a range of memory is accessed or a register is modified.
+
+use crate::execution::StepOutcome;
+use crate::memory::Memory;
+use crate::registers::{RegisterValue, Registers};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+/// The kinds of memory access that trigger a memory watchpoint.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchAccess {
+    fn reads(self) -> bool {
+        self != WatchAccess::Write
+    }
+
+    fn writes(self) -> bool {
+        self != WatchAccess::Read
+    }
+}
+
+/// The register writes that trigger a register watchpoint.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RegisterCondition {
+    /// Trigger whenever the value of the register changes.
+    Changed,
+    /// Trigger whenever the register is given the value, having previously held another.
+    Equals(RegisterValue),
+}
+
+/// A condition upon which execution should stop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Watchpoint {
+    /// Watch for accesses to a range of memory addresses.
+    Memory {
+        range: RangeInclusive<u32>,
+        access: WatchAccess,
+    },
+    /// Watch for writes to a register.
+    Register {
+        register: Registers,
+        condition: RegisterCondition,
+    },
+}
+
+impl Watchpoint {
+    /// Create a watchpoint on a range of memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - the address of the first byte to be watched.
+    /// * `len` - the number of bytes to be watched, which must not be zero.
+    /// * `access` - the kinds of access to be watched.
+    pub fn memory(start: u32, len: u32, access: WatchAccess) -> Self {
+        assert!(len > 0, "a watchpoint must watch at least one byte");
+
+        Watchpoint::Memory {
+            range: start..=start.saturating_add(len - 1),
+            access,
+        }
+    }
+
+    /// Create a watchpoint on every address within a named memory region.
+    ///
+    /// Returns `None` if no region with the name exists. If several regions share
+    /// the name, the most recently added region is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `mem` - the memory holding the region.
+    /// * `name` - the name of the region.
+    /// * `access` - the kinds of access to be watched.
+    pub fn region(mem: &Memory, name: &str, access: WatchAccess) -> Option<Self> {
+        mem.regions()
+            .iter()
+            .rev()
+            .find(|r| r.name == name)
+            .map(|r| Watchpoint::Memory {
+                range: r.start..=r.end,
+                access,
+            })
+    }
+
+    /// Create a watchpoint on a register.
+    ///
+    /// Changes to the instruction pointer are only observed when it is written by an
+    /// instruction, such as a jump, and not when it is advanced past an instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `register` - the register to be watched.
+    /// * `condition` - the condition upon which the watchpoint triggers.
+    pub fn register(register: Registers, condition: RegisterCondition) -> Self {
+        Watchpoint::Register {
+            register,
+            condition,
+        }
+    }
+
+    /// Returns the event that triggered the watchpoint during a step, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - the outcome of the step.
+    fn check(&self, outcome: &StepOutcome) -> Option<WatchEvent> {
+        match self {
+            Watchpoint::Memory { range, access } => {
+                let overlaps = |address: u32, len: usize| {
+                    let end = address as u64 + len.max(1) as u64 - 1;
+                    address <= *range.end() && end >= *range.start() as u64
+                };
+
+                let read = outcome
+                    .memory_reads
+                    .iter()
+                    .filter(|_| access.reads())
+                    .find(|r| overlaps(r.address, r.bytes.len()))
+                    .map(|r| WatchEvent::Read {
+                        address: r.address,
+                        bytes: r.bytes.clone(),
+                    });
+
+                read.or_else(|| {
+                    outcome
+                        .memory_writes
+                        .iter()
+                        .filter(|_| access.writes())
+                        .find(|w| overlaps(w.address, w.new_bytes.len()))
+                        .map(|w| WatchEvent::Write {
+                            address: w.address,
+                            old_bytes: w.old_bytes.clone(),
+                            new_bytes: w.new_bytes.clone(),
+                        })
+                })
+            }
+            Watchpoint::Register {
+                register,
+                condition,
+            } => outcome
+                .register_writes
+                .iter()
+                .filter(|w| w.register_id == *register)
+                .find(|w| match condition {
+                    RegisterCondition::Changed => w.old_value != w.new_value,
+                    RegisterCondition::Equals(v) => w.new_value == *v && w.old_value != *v,
+                })
+                .map(|w| WatchEvent::Register {
+                    register: w.register_id,
+                    old_value: w.old_value,
+                    new_value: w.new_value,
+                }),
+        }
+    }
+}
+
+/// The access that triggered a watchpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchEvent {
+    /// Memory was read.
+    Read { address: u32, bytes: Vec<u8> },
+    /// Memory was written.
+    Write {
+        address: u32,
+        old_bytes: Vec<u8>,
+        new_bytes: Vec<u8>,
+    },
+    /// A register was written.
+    Register {
+        register: Registers,
+        old_value: RegisterValue,
+        new_value: RegisterValue,
+    },
+}
+
+/// A report of a triggered watchpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchpointHit {
+    /// The identifier of the watchpoint.
+    pub id: u32,
+    /// The address of the instruction that triggered the watchpoint.
+    pub address: u32,
+    /// The access that triggered the watchpoint.
+    pub event: WatchEvent,
+}
+
+/// A collection of watchpoints, each identified by the order in which it was added.
+#[derive(Debug, Clone, Default)]
+pub struct Watchpoints {
+    watchpoints: BTreeMap<u32, Watchpoint>,
+    next_id: u32,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a watchpoint, returning its identifier.
+    ///
+    /// # Arguments
+    ///
+    /// * `watchpoint` - the watchpoint to be added.
+    pub fn add(&mut self, watchpoint: Watchpoint) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.insert(id, watchpoint);
+
+        id
+    }
+
+    /// Remove a watchpoint, returning it if it existed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the identifier of the watchpoint.
+    pub fn remove(&mut self, id: u32) -> Option<Watchpoint> {
+        self.watchpoints.remove(&id)
+    }
+
+    /// Remove every watchpoint.
+    pub fn clear(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Returns the watchpoint with an identifier, if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - the identifier of the watchpoint.
+    pub fn get(&self, id: u32) -> Option<&Watchpoint> {
+        self.watchpoints.get(&id)
+    }
+
+    /// Returns the number of watchpoints.
+    pub fn len(&self) -> usize {
+        self.watchpoints.len()
+    }
+
+    /// Returns true if there are no watchpoints.
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    /// Returns an iterator over the watchpoints, in ascending order of identifier.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &Watchpoint)> {
+        self.watchpoints.iter().map(|(id, w)| (*id, w))
+    }
+
+    /// Returns the first watchpoint triggered during a step, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `outcome` - the outcome of the step.
+    pub fn check(&self, outcome: &StepOutcome) -> Option<WatchpointHit> {
+        if !outcome.executed() {
+            return None;
+        }
+
+        self.watchpoints.iter().find_map(|(id, w)| {
+            w.check(outcome).map(|event| WatchpointHit {
+                id: *id,
+                address: outcome.address,
+                event,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::RunOutcome;
+    use crate::instructions::enums::Instruction;
+    use crate::virtual_machine::VirtualMachine;
+
+    fn vm() -> VirtualMachine {
+        let program = [
+            Instruction::MovLitReg(5, Registers::R1),
+            Instruction::MovRegMem(Registers::R1, 500),
+            Instruction::MovMemReg(500, Registers::R2),
+            Instruction::HLT(),
+        ];
+        let bytes: Vec<u8> = program.iter().flat_map(|i| i.encode()).collect();
+
+        let mut vm = VirtualMachine::new(1_000, 10, false);
+        vm.load_program(&bytes, 0).unwrap();
+        vm
+    }
+
+    #[test]
+    fn watchpoints_stop_the_run_loop() {
+        let mut vm = vm();
+        let equals = vm.add_watchpoint(Watchpoint::register(
+            Registers::R2,
+            RegisterCondition::Equals(RegisterValue::I32(5)),
+        ));
+        let read = vm.add_watchpoint(Watchpoint::memory(500, 4, WatchAccess::Read));
+        let write = vm.add_watchpoint(Watchpoint::memory(502, 1, WatchAccess::Write));
+
+        let hit = match vm.run_for(100) {
+            RunOutcome::Watchpoint(hit) => hit,
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        };
+        assert_eq!(
+            hit,
+            WatchpointHit {
+                id: write,
+                address: 7,
+                event: WatchEvent::Write {
+                    address: 500,
+                    old_bytes: vec![0; 4],
+                    new_bytes: vec![5, 0, 0, 0],
+                },
+            }
+        );
+
+        // Both remaining watchpoints are triggered by the next instruction,
+        // in which case the earliest added is reported.
+        let hit = match vm.run_for(100) {
+            RunOutcome::Watchpoint(hit) => hit,
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        };
+        assert_eq!(hit.id, equals);
+        assert_eq!(
+            hit.event,
+            WatchEvent::Register {
+                register: Registers::R2,
+                old_value: RegisterValue::I32(0),
+                new_value: RegisterValue::I32(5),
+            }
+        );
+
+        assert!(vm.remove_watchpoint(read).is_some());
+        assert!(matches!(vm.run_for(100), RunOutcome::Halted));
+    }
+
+    #[test]
+    fn watchpoints_ignore_instruction_fetches() {
+        let mut vm = vm();
+        let region = Watchpoint::region(&vm.memory, "Executable", WatchAccess::ReadWrite).unwrap();
+        vm.add_watchpoint(region);
+        vm.add_watchpoint(Watchpoint::register(
+            Registers::R2,
+            RegisterCondition::Changed,
+        ));
+        assert!(Watchpoint::region(&vm.memory, "Missing", WatchAccess::Read).is_none());
+
+        let hit = match vm.run_for(100) {
+            RunOutcome::Watchpoint(hit) => hit,
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        };
+        assert_eq!(hit.id, 1);
+        assert_eq!(hit.address, 14);
+    }
+
+    #[test]
+    fn watchpoints_stop_run_and_step() {
+        let mut running = vm();
+        let write = running.add_watchpoint(Watchpoint::memory(500, 4, WatchAccess::Write));
+
+        // Running stops at the watchpoint rather than reporting completion.
+        match running.run() {
+            Ok(RunOutcome::Watchpoint(hit)) => assert_eq!(hit.id, write),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        assert!(!running.cpu.is_halted());
+        assert!(matches!(running.run(), Ok(RunOutcome::Halted)));
+
+        let mut stepping = vm();
+        let read = stepping.add_watchpoint(Watchpoint::memory(500, 4, WatchAccess::Read));
+        assert!(stepping.step().watchpoint.is_none());
+        assert!(stepping.step().watchpoint.is_none());
+        assert_eq!(stepping.step().watchpoint.map(|hit| hit.id), Some(read));
+    }
+}